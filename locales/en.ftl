@@ -0,0 +1,146 @@
+channel-added = ✓ Channel '{ $name }' added successfully
+channel-updated = ✓ Channel '{ $name }' updated
+priority-set = ✓ Channel '{ $name }' now has priority { $priority }
+reorder-header = Channels, tried first to last:
+reorder-prompt = Move with u<N> / d<N>, s to save, q to quit:
+reorder-invalid = Expected u or d followed by a position from 1 to { $count }, s or q
+reorder-saved = ✓ Channel order saved
+reorder-cancelled = Order left unchanged
+channel-removed = ✓ Channel '{ $name }' removed successfully
+channel-undo-hint = Undo with: ccswitch restore { $name }
+channel-restored = ✓ Channel '{ $name }' restored successfully
+
+trash-empty = Trash is empty
+trash-header = Removed channels:
+trash-entry = { $name } - { $url } (purged in { $days } days)
+
+channels-none = No channels configured
+channels-header = Configured channels:
+channel-entry = { $name } [{ $status }] - { $url } (model: { $model }; { $proxy })
+channel-tags = tags: { $tags }
+channel-enabled = enabled
+channel-disabled = disabled
+channel-auto-disabled = auto-disabled until { $until }
+channel-reenabled = ✓ { $name } passed and is back in rotation
+model-any = any
+
+show-url = URL
+show-status = Status
+show-model = Model
+show-priority = Priority
+show-endpoint = Endpoint
+show-api-key = API key
+show-api-key-helper = from `{ $helper }`
+show-api-key-none = none
+show-proxy = Proxy
+show-key-expires = Key expires
+show-tags = Tags
+show-model-map = Model names
+show-metadata = Metadata
+
+proxy-via = via { $proxy }
+proxy-direct = direct
+proxy-disabled = proxy disabled
+
+key-expired = key expired { $days } days ago
+key-expires-today = key expires today
+key-expires-in = key expires in { $days } days
+
+testing-channel = Testing channel: { $name }
+testing-all = Testing all channels:
+status-available = Available
+status-unavailable = Unavailable
+test-summary = { $available } available / { $failed } failed
+test-slowest = slowest: { $name } ({ $latency })
+
+rank-entry = { $name } - score { $score } (success { $success }, p50 { $p50 }, p95 { $p95 }, errors { $errors }, price { $price }/M, priority { $current } -> { $suggested })
+rank-applied = ✓ Suggested priorities saved
+probe-start = Probing { $name } with model { $model }...
+probe-supported = supported
+probe-unsupported = not supported
+probe-unknown = unknown
+probe-no-limit = no limit found
+probe-saved = ✓ Capabilities saved to the channel
+stats-empty = No requests recorded in this period
+stats-by-channel = By channel:
+stats-by-model = By model:
+stats-by-label = By label { $label }:
+stats-entry = { $requests } requests ({ $failed } failed), { $prompt } prompt + { $completion } completion tokens, avg { $latency }, { $cost }
+
+response-from = ✓ Response from { $channel } (model: { $model })
+response-usage = Usage: { $usage }
+response-cached = (cached)
+usage-summary = { $prompt } prompt + { $completion } completion = { $total } tokens
+usage-cost = (~{ $cost })
+stream-rate = Streaming at { $rate }
+usage-rate = · { $rate }
+usage-estimated = (estimated)
+usage-cached = ({ $cached } cached, { $rate })
+each-response = ✓ { $prompt } (via { $channel })
+each-failed = { $failed } of { $total } prompts failed
+report-header = Run report: { $succeeded }/{ $total } succeeded in { $elapsed }, { $failovers } failovers
+report-tokens = tokens: { $prompt } prompt + { $completion } completion
+report-channel = { $name }: { $succeeded } succeeded, { $failed } failed
+report-slowest = slow: #{ $index } on { $channel } ({ $latency })
+runs-stored = run ID: { $id }
+chat-welcome = Chat started. Ctrl-C cancels a reply, /clear forgets the conversation, /exit or Ctrl-D quits.
+chat-cleared = ✓ Conversation cleared
+chat-resumed = Resumed session '{ $name }' ({ $turns } earlier turns)
+chat-via = (via { $channel }, model: { $model })
+runs-empty = No stored runs
+runs-entry = { $id } - { $succeeded }/{ $total } succeeded in { $elapsed }
+runs-interrupted = { $id } - interrupted, no report
+runs-cleaned = ✓ Removed { $count } runs
+sessions-empty = No saved sessions
+sessions-entry = { $name } - { $turns } turns, last: { $prompt }
+sessions-turn = (via { $channel }, model: { $model }) { $usage }
+sessions-deleted = ✓ Session '{ $name }' deleted
+sessions-summarized = ✓ Session '{ $name }' now keeps the summary and its last { $turns } turns
+jobs-empty = No jobs
+jobs-entry = { $id } [{ $status }] { $prompt }
+jobs-status = Job { $id }: { $status }
+jobs-prompt = Prompt: { $prompt }
+jobs-model = Model: { $model }
+jobs-elapsed = Took { $elapsed }
+jobs-error = Error: { $error }
+jobs-cancelled = ✓ Job { $id } cancelled
+jobs-retried = ✓ Job { $id } queued again
+jobs-already-finished = Job { $id } has already finished
+jobs-pending = Job { $id } has no result yet ({ $status })
+
+choose-header = Several channels serve model '{ $model }' with equal priority:
+choose-prompt = Select a channel [1-{ $count }] (Enter to keep default order):
+
+update-current = ✓ ccswitch { $version } is already up to date
+update-done = ✓ Updated ccswitch from { $from } to { $to }
+serve-listening = ✓ Listening on http://{ $addr }/v1
+
+error-config = Configuration error: { $detail }
+error-channel = Channel error: { $detail }
+error-upstream = Channel error: API request failed: { $status } - { $detail }
+error-network = Network error: { $detail }
+error-stream-interrupted = Stream interrupted: { $detail }
+error-serialization = Serialization error: { $detail }
+error-io = IO error: { $detail }
+error-channel-not-found = Channel '{ $name }' not found
+error-no-channels = No available channels for model '{ $model }'
+error-all-failed = All channels failed:
+error-update = Update error: { $detail }
+error-auth = Authentication failed: { $detail }
+error-cancelled = Request cancelled
+request-cancelled-marker = [cancelled]
+error-read-only = Configuration is read-only; cannot { $action }
+error-budget = Budget exceeded: { $detail }
+error-nothing-extracted = Nothing to extract: the reply has no { $what }
+error-provider = { $kind } on channel { $channel }: { $detail }
+provider-quota-exhausted = Quota exhausted
+provider-rate-limited = Rate limited
+provider-overloaded = Provider overloaded
+provider-context-too-long = Context length exceeded
+provider-model-not-found = Model not found
+provider-content-filtered = Blocked by content filter
+provider-invalid-request = Invalid request
+consensus-majority = Consensus: { $agreeing } of { $answered } answers agreed ({ $asked } channels asked)
+consensus-judge = Consensus: { $channel } combined { $answered } differing answers ({ $asked } channels asked)
+consensus-single = Consensus: only { $channel } answered ({ $asked } channels asked)
+pipeline-stage = { $stage }: { $channel } ({ $model }) { $usage }
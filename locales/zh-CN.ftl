@@ -0,0 +1,146 @@
+channel-added = ✓ 已添加渠道 '{ $name }'
+channel-updated = ✓ 已更新渠道 '{ $name }'
+priority-set = ✓ 渠道 '{ $name }' 的优先级已设为 { $priority }
+reorder-header = 渠道（按尝试顺序）:
+reorder-prompt = 输入 u<序号> 上移 / d<序号> 下移，s 保存，q 退出:
+reorder-invalid = 请输入 u 或 d 加 1 到 { $count } 之间的序号，或 s、q
+reorder-saved = ✓ 已保存渠道顺序
+reorder-cancelled = 渠道顺序未改变
+channel-removed = ✓ 已删除渠道 '{ $name }'
+channel-undo-hint = 撤销: ccswitch restore { $name }
+channel-restored = ✓ 已恢复渠道 '{ $name }'
+
+trash-empty = 回收站为空
+trash-header = 已删除的渠道:
+trash-entry = { $name } - { $url }（{ $days } 天后彻底删除）
+
+channels-none = 尚未配置任何渠道
+channels-header = 已配置的渠道:
+channel-entry = { $name } [{ $status }] - { $url }（模型: { $model }；{ $proxy }）
+channel-tags = 标签: { $tags }
+channel-enabled = 已启用
+channel-disabled = 已禁用
+channel-auto-disabled = 已自动禁用至 { $until }
+channel-reenabled = ✓ { $name } 测试通过，已重新启用
+model-any = 任意
+
+show-url = 地址
+show-status = 状态
+show-model = 模型
+show-priority = 优先级
+show-endpoint = 接口类型
+show-api-key = API 密钥
+show-api-key-helper = 来自 `{ $helper }`
+show-api-key-none = 无
+show-proxy = 代理
+show-key-expires = 密钥到期
+show-tags = 标签
+show-model-map = 模型名映射
+show-metadata = 元数据
+
+proxy-via = 经由 { $proxy }
+proxy-direct = 直连
+proxy-disabled = 已禁用代理
+
+key-expired = 密钥已过期 { $days } 天
+key-expires-today = 密钥今天到期
+key-expires-in = 密钥将在 { $days } 天后到期
+
+testing-channel = 正在测试渠道: { $name }
+testing-all = 正在测试所有渠道:
+status-available = 可用
+status-unavailable = 不可用
+test-summary = { $available } 个可用 / { $failed } 个失败
+test-slowest = 最慢: { $name }（{ $latency }）
+
+rank-entry = { $name } - 得分 { $score }（成功率 { $success }，p50 { $p50 }，p95 { $p95 }，错误率 { $errors }，价格 { $price }/M，优先级 { $current } -> { $suggested }）
+rank-applied = ✓ 已保存建议的优先级
+probe-start = 正在使用模型 { $model } 探测 { $name }...
+probe-supported = 支持
+probe-unsupported = 不支持
+probe-unknown = 未知
+probe-no-limit = 未发现上限
+probe-saved = ✓ 已将能力信息保存到渠道配置
+stats-empty = 该时间段内没有请求记录
+stats-by-channel = 按渠道:
+stats-by-model = 按模型:
+stats-by-label = 按标签 { $label }:
+stats-entry = { $requests } 次请求（失败 { $failed } 次），{ $prompt } prompt + { $completion } completion tokens，平均 { $latency }，{ $cost }
+
+response-from = ✓ 来自 { $channel } 的响应（模型: { $model }）
+response-usage = 用量: { $usage }
+response-cached = （缓存）
+usage-summary = 输入 { $prompt } + 输出 { $completion } = { $total } tokens
+usage-cost = （约 { $cost }）
+stream-rate = 正在以 { $rate } 的速度接收
+usage-rate = · { $rate }
+usage-estimated = （估算）
+usage-cached = （缓存命中 { $cached }，{ $rate }）
+each-response = ✓ { $prompt }（经由 { $channel }）
+each-failed = { $total } 个提示中有 { $failed } 个失败
+report-header = 运行报告: { $total } 个中成功 { $succeeded } 个，耗时 { $elapsed }，故障转移 { $failovers } 次
+report-tokens = token: { $prompt } prompt + { $completion } completion
+report-channel = { $name }: 成功 { $succeeded } 个，失败 { $failed } 个
+report-slowest = 较慢: 第 { $index } 个，渠道 { $channel }（{ $latency }）
+runs-stored = 运行 ID: { $id }
+chat-welcome = 已进入对话。Ctrl-C 取消当前回复，/clear 清空对话，/exit 或 Ctrl-D 退出。
+chat-cleared = ✓ 已清空对话
+chat-resumed = 已恢复会话 '{ $name }'（此前 { $turns } 轮）
+chat-via = （通过 { $channel }，模型: { $model }）
+runs-empty = 没有保存的运行记录
+runs-entry = { $id } - { $total } 个中成功 { $succeeded } 个，耗时 { $elapsed }
+runs-interrupted = { $id } - 已中断，没有报告
+runs-cleaned = ✓ 已删除 { $count } 条运行记录
+sessions-empty = 没有保存的会话
+sessions-entry = { $name } - { $turns } 轮，最近: { $prompt }
+sessions-turn = （通过 { $channel }，模型: { $model }）{ $usage }
+sessions-deleted = ✓ 会话 '{ $name }' 已删除
+sessions-summarized = ✓ 会话 '{ $name }' 已改为保存总结和最近 { $turns } 轮
+jobs-empty = 没有后台任务
+jobs-entry = { $id } [{ $status }] { $prompt }
+jobs-status = 任务 { $id }: { $status }
+jobs-prompt = 提示词: { $prompt }
+jobs-model = 模型: { $model }
+jobs-elapsed = 耗时 { $elapsed }
+jobs-error = 错误: { $error }
+jobs-cancelled = ✓ 任务 { $id } 已取消
+jobs-retried = ✓ 任务 { $id } 已重新排队
+jobs-already-finished = 任务 { $id } 已经结束
+jobs-pending = 任务 { $id } 还没有结果 ({ $status })
+
+choose-header = 有多个相同优先级的渠道支持模型 '{ $model }':
+choose-prompt = 请选择渠道 [1-{ $count }]（直接回车保持默认顺序）:
+
+update-current = ✓ ccswitch { $version } 已是最新版本
+update-done = ✓ 已将 ccswitch 从 { $from } 更新到 { $to }
+serve-listening = ✓ 正在监听 http://{ $addr }/v1
+
+error-config = 配置错误: { $detail }
+error-channel = 渠道错误: { $detail }
+error-upstream = 渠道错误: API 请求失败: { $status } - { $detail }
+error-network = 网络错误: { $detail }
+error-stream-interrupted = 流式响应中断: { $detail }
+error-serialization = 序列化错误: { $detail }
+error-io = IO 错误: { $detail }
+error-channel-not-found = 渠道 '{ $name }' 不存在
+error-no-channels = 没有可用于模型 '{ $model }' 的渠道
+error-all-failed = 所有渠道均失败:
+error-update = 更新错误: { $detail }
+error-auth = 认证失败: { $detail }
+error-cancelled = 请求已取消
+request-cancelled-marker = [已取消]
+error-read-only = 配置为只读，无法{ $action }
+error-budget = 超出预算: { $detail }
+error-nothing-extracted = 没有可提取的内容: 回复中没有 { $what }
+error-provider = 渠道 { $channel } { $kind }: { $detail }
+provider-quota-exhausted = 额度已用尽
+provider-rate-limited = 触发限流
+provider-overloaded = 服务商过载
+provider-context-too-long = 超出上下文长度
+provider-model-not-found = 模型不存在
+provider-content-filtered = 被内容审核拦截
+provider-invalid-request = 请求无效
+consensus-majority = 共识：{ $answered } 个回答中有 { $agreeing } 个一致（询问了 { $asked } 个渠道）
+consensus-judge = 共识：由 { $channel } 综合了 { $answered } 个不同的回答（询问了 { $asked } 个渠道）
+consensus-single = 共识：只有 { $channel } 给出了回答（询问了 { $asked } 个渠道）
+pipeline-stage = { $stage }：{ $channel }（{ $model }）{ $usage }
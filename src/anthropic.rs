@@ -0,0 +1,53 @@
+use crate::client::{Message, RequestOptions};
+use serde_json::{json, Value};
+
+pub const API_VERSION: &str = "2023-06-01";
+
+// The Messages API rejects requests without `max_tokens`
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+// System messages move to the top-level `system` field; the rest keep their order
+pub fn payload(model: &str, messages: &[Message], options: &RequestOptions, prompt_caching: bool) -> Value {
+    let system: Vec<&str> = messages
+        .iter()
+        .filter(|message| message.role == "system")
+        .map(|message| message.content.as_str())
+        .collect();
+    let turns: Vec<Value> = messages
+        .iter()
+        .filter(|message| message.role != "system")
+        .map(|message| json!({ "role": message.role, "content": message.content }))
+        .collect();
+    
+    let mut payload = json!({
+        "model": model,
+        "messages": turns,
+        "max_tokens": options.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        "stream": options.stream
+    });
+    
+    if let Some(temperature) = options.temperature {
+        payload["temperature"] = json!(temperature);
+    }
+    
+    if !system.is_empty() {
+        let system = system.join("\n\n");
+        payload["system"] = if prompt_caching {
+            json!([{ "type": "text", "text": system, "cache_control": { "type": "ephemeral" } }])
+        } else {
+            json!(system)
+        };
+    }
+    
+    payload
+}
+
+// Concatenates the text blocks of a Messages API reply, skipping tool use and thinking blocks
+pub fn content(response: &Value) -> Option<String> {
+    let blocks = response.get("content")?.as_array()?;
+    Some(blocks
+        .iter()
+        .filter(|block| block.get("type").and_then(|t| t.as_str()) == Some("text"))
+        .filter_map(|block| block.get("text").and_then(|t| t.as_str()))
+        .collect())
+}
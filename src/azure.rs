@@ -0,0 +1,23 @@
+use crate::config::{Channel, EndpointKind};
+
+pub const DEFAULT_API_VERSION: &str = "2024-06-01";
+
+// The deployment picks the model on Azure; without one the requested model name is used as the deployment
+pub fn endpoint(channel: &Channel, model: &str) -> String {
+    let base = match channel.url.find("/openai/") {
+        Some(index) => &channel.url[..index],
+        None => channel.url.trim_end_matches('/'),
+    };
+    let operation = match channel.endpoint_kind {
+        EndpointKind::Chat => "chat/completions",
+        EndpointKind::Completion => "completions",
+    };
+    
+    format!(
+        "{}/openai/deployments/{}/{}?api-version={}",
+        base,
+        channel.deployment.as_deref().unwrap_or(model),
+        operation,
+        channel.api_version.as_deref().unwrap_or(DEFAULT_API_VERSION),
+    )
+}
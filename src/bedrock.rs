@@ -0,0 +1,270 @@
+use crate::anthropic;
+use crate::client::{Message, RequestOptions};
+use crate::config::{now_secs, Channel};
+use crate::error::{CCSwitchError, Result};
+use hmac::{Hmac, Mac};
+use reqwest::RequestBuilder;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+const SERVICE: &str = "bedrock";
+const ANTHROPIC_VERSION: &str = "bedrock-2023-05-31";
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+// Bedrock hosts several model families behind one API, each with its own body format
+fn is_anthropic(model: &str) -> bool {
+    model.contains("anthropic.")
+}
+
+// The channel URL is the runtime endpoint, e.g. https://bedrock-runtime.us-east-1.amazonaws.com
+pub fn invoke_url(channel: &Channel, model: &str) -> String {
+    let base = match channel.url.find("/model/") {
+        Some(index) => &channel.url[..index],
+        None => channel.url.trim_end_matches('/'),
+    };
+    format!("{}/model/{}/invoke", base, uri_encode(model, true))
+}
+
+pub fn payload(model: &str, messages: &[Message], options: &RequestOptions) -> Value {
+    if is_anthropic(model) {
+        let mut payload = anthropic::payload(model, messages, options, false);
+        if let Some(fields) = payload.as_object_mut() {
+            // The model is named in the URL and streaming uses a separate endpoint
+            fields.remove("model");
+            fields.remove("stream");
+            fields.insert("anthropic_version".to_string(), json!(ANTHROPIC_VERSION));
+        }
+        return payload;
+    }
+    
+    let mut payload = json!({
+        "prompt": llama_prompt(messages),
+        "max_gen_len": options.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS)
+    });
+    if let Some(temperature) = options.temperature {
+        payload["temperature"] = json!(temperature);
+    }
+    payload
+}
+
+// Llama 3 chat template
+fn llama_prompt(messages: &[Message]) -> String {
+    let mut prompt = "<|begin_of_text|>".to_string();
+    for message in messages {
+        prompt.push_str(&format!("<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>", message.role, message.content.trim()));
+    }
+    prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+    prompt
+}
+
+pub fn content(model: &str, response: &Value) -> Option<String> {
+    if is_anthropic(model) {
+        return anthropic::content(response);
+    }
+    response.get("generation").and_then(|g| g.as_str()).map(str::to_string)
+}
+
+pub fn usage(model: &str, response: &Value) -> Option<Value> {
+    if is_anthropic(model) {
+        return response.get("usage").cloned();
+    }
+    
+    let prompt = response.get("prompt_token_count").and_then(|v| v.as_u64())?;
+    let completion = response.get("generation_token_count").and_then(|v| v.as_u64())?;
+    Some(json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": completion,
+        "total_tokens": prompt + completion
+    }))
+}
+
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    region: String,
+}
+
+// Channel settings win over the standard AWS environment variables
+fn credentials(channel: &Channel, host: &str) -> Result<Credentials> {
+    let aws = &channel.aws;
+    let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let missing = |what: &str| CCSwitchError::Auth(format!("{} has no AWS {} configured", channel.name, what));
+    
+    let region = aws.region.clone()
+        .or_else(|| env("AWS_REGION"))
+        .or_else(|| env("AWS_DEFAULT_REGION"))
+        .or_else(|| region_from_host(host))
+        .ok_or_else(|| missing("region"))?;
+    let access_key_id = aws.access_key_id.clone()
+        .or_else(|| env("AWS_ACCESS_KEY_ID"))
+        .ok_or_else(|| missing("access key"))?;
+    let secret_access_key = aws.secret_access_key.clone()
+        .or_else(|| env("AWS_SECRET_ACCESS_KEY"))
+        .ok_or_else(|| missing("secret access key"))?;
+    let session_token = aws.session_token.clone().or_else(|| env("AWS_SESSION_TOKEN"));
+    
+    Ok(Credentials { access_key_id, secret_access_key, session_token, region })
+}
+
+// bedrock-runtime.<region>.amazonaws.com
+fn region_from_host(host: &str) -> Option<String> {
+    let mut labels = host.split('.');
+    labels.next().filter(|service| service.starts_with("bedrock"))?;
+    labels.next().map(str::to_string)
+}
+
+// Attaches a JSON body and an AWS Signature Version 4 for it
+pub fn json_body(request: RequestBuilder, channel: &Channel, url: &str, payload: &Value) -> Result<RequestBuilder> {
+    let body = serde_json::to_vec(payload)?;
+    let parsed = url::Url::parse(url).map_err(|e| CCSwitchError::Config(format!("Invalid Bedrock URL {}: {}", url, e)))?;
+    let host = match (parsed.host_str(), parsed.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => return Err(CCSwitchError::Config(format!("Invalid Bedrock URL {}: no host", url))),
+    };
+    let credentials = credentials(channel, parsed.host_str().unwrap_or_default())?;
+    
+    let now = now_secs();
+    let amz_date = amz_date(now);
+    let date = &amz_date[..8];
+    let payload_hash = hex(&Sha256::digest(&body));
+    
+    let mut headers = vec![
+        ("content-type", "application/json".to_string()),
+        ("host", host),
+        ("x-amz-content-sha256", payload_hash.clone()),
+        ("x-amz-date", amz_date.clone()),
+    ];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+    
+    let canonical_request = canonical_request("POST", &uri_encode(parsed.path(), false), parsed.query().unwrap_or_default(), &headers, &payload_hash);
+    let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+    let scope = scope(date, &credentials.region, SERVICE);
+    let signature = signature(&credentials.secret_access_key, &credentials.region, SERVICE, &amz_date, &canonical_request);
+    
+    let mut request = request.header(
+        "Authorization",
+        format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", credentials.access_key_id, scope, signed_headers, signature),
+    );
+    // reqwest derives Host from the URL itself
+    for (name, value) in headers.into_iter().filter(|(name, _)| *name != "host") {
+        request = request.header(name, value);
+    }
+    Ok(request.body(body))
+}
+
+// `headers` must be lowercase and sorted by name
+fn canonical_request(method: &str, path: &str, query: &str, headers: &[(&str, String)], payload_hash: &str) -> String {
+    let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+    let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+    format!("{}\n{}\n{}\n{}\n{}\n{}", method, path, query, canonical_headers, signed_headers, payload_hash)
+}
+
+fn scope(date: &str, region: &str, service: &str) -> String {
+    format!("{}/{}/{}/aws4_request", date, region, service)
+}
+
+fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    [date, region, service, "aws4_request"]
+        .iter()
+        .fold(format!("AWS4{}", secret_access_key).into_bytes(), |key, part| hmac(&key, part.as_bytes()))
+}
+
+fn signature(secret_access_key: &str, region: &str, service: &str, amz_date: &str, canonical_request: &str) -> String {
+    let date = &amz_date[..8];
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope(date, region, service),
+        hex(&Sha256::digest(canonical_request.as_bytes())),
+    );
+    hex(&hmac(&signing_key(secret_access_key, date, region, service), string_to_sign.as_bytes()))
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Percent-encodes everything but RFC 3986 unreserved characters (and `/` in paths)
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            b'/' if !encode_slash => "/".to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+// YYYYMMDD'T'HHMMSS'Z' in UTC
+fn amz_date(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+// Days since 1970-01-01 to a proleptic Gregorian date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const SECRET: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+    
+    #[test]
+    fn dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19_723), (2024, 1, 1));
+        assert_eq!(amz_date(1_440_938_160), "20150830T123600Z");
+    }
+    
+    // From AWS's "Examples of how to derive a signing key for Signature Version 4"
+    #[test]
+    fn signing_key_matches_aws_example() {
+        assert_eq!(hex(&signing_key(SECRET, "20120215", "us-east-1", "iam")), "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d");
+    }
+    
+    // `get-vanilla` from the AWS Signature Version 4 test suite
+    #[test]
+    fn signature_matches_aws_test_suite() {
+        let headers = [("host", "example.amazonaws.com".to_string()), ("x-amz-date", "20150830T123600Z".to_string())];
+        let empty_hash = hex(&Sha256::digest(b""));
+        let canonical = canonical_request("GET", "/", "", &headers, &empty_hash);
+        assert_eq!(canonical, format!("GET\n/\n\nhost:example.amazonaws.com\nx-amz-date:20150830T123600Z\n\nhost;x-amz-date\n{}", empty_hash));
+        assert_eq!(
+            signature(SECRET, "us-east-1", "service", "20150830T123600Z", &canonical),
+            "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31",
+        );
+    }
+    
+    #[test]
+    fn paths_keep_their_slashes() {
+        assert_eq!(uri_encode("/model/anthropic.claude-3:0/invoke", false), "/model/anthropic.claude-3%3A0/invoke");
+        assert_eq!(uri_encode("a/b c", true), "a%2Fb%20c");
+    }
+}
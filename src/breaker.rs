@@ -0,0 +1,76 @@
+use crate::config::CircuitBreakerConfig;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+enum Circuit {
+    Closed { failures: u32 },
+    // Skipped until the cool-down ends
+    Open { until: Instant },
+    // One request is let through to see whether the channel recovered
+    HalfOpen { since: Instant },
+}
+
+// Consecutive request failures per channel, kept for the life of the process
+#[derive(Default)]
+pub struct CircuitBreakers {
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+impl CircuitBreakers {
+    // How long the channel is still shut out, if it is
+    pub fn blocked_for(&self, settings: &CircuitBreakerConfig, name: &str) -> Option<Duration> {
+        if !settings.enabled {
+            return None;
+        }
+        let now = Instant::now();
+        match self.circuits.lock().unwrap().get(name)? {
+            Circuit::Open { until } if *until > now => Some(*until - now),
+            // A probe that never reported back frees its slot after another cool-down
+            Circuit::HalfOpen { since } => {
+                let until = *since + Duration::from_secs(settings.cooldown_seconds);
+                (until > now).then(|| until - now)
+            }
+            _ => None,
+        }
+    }
+    
+    // Called when the channel is picked; an expired open circuit lets this request through as the probe
+    pub fn begin(&self, name: &str) {
+        let mut circuits = self.circuits.lock().unwrap();
+        if let Some(circuit) = circuits.get_mut(name) {
+            if matches!(circuit, Circuit::Open { .. } | Circuit::HalfOpen { .. }) {
+                info!("Circuit for channel {} is half-open, sending a probe request", name);
+                *circuit = Circuit::HalfOpen { since: Instant::now() };
+            }
+        }
+    }
+    
+    pub fn record_success(&self, name: &str) {
+        if let Some(Circuit::HalfOpen { .. }) = self.circuits.lock().unwrap().insert(name.to_string(), Circuit::Closed { failures: 0 }) {
+            info!("Channel {} recovered, closing its circuit", name);
+        }
+    }
+    
+    pub fn record_failure(&self, settings: &CircuitBreakerConfig, name: &str) {
+        if !settings.enabled {
+            return;
+        }
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits.entry(name.to_string()).or_insert(Circuit::Closed { failures: 0 });
+        let threshold = settings.failure_threshold.max(1);
+        let failures = match circuit {
+            Circuit::Closed { failures } => *failures + 1,
+            // The probe failed: straight back to open
+            _ => threshold,
+        };
+        
+        *circuit = if failures >= threshold {
+            warn!("Opening the circuit for channel {} for {}s after {} failures", name, settings.cooldown_seconds, failures);
+            Circuit::Open { until: Instant::now() + Duration::from_secs(settings.cooldown_seconds) }
+        } else {
+            Circuit::Closed { failures }
+        };
+    }
+}
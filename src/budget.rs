@@ -0,0 +1,107 @@
+use crate::config::{BudgetConfig, Config, EmailEvent};
+use crate::email;
+use crate::error::{CCSwitchError, Result};
+use crate::stats;
+use chrono::{Datelike, Local, NaiveDate, TimeZone};
+use log::warn;
+
+// Unix time of local midnight on the given day
+fn local_start(date: NaiveDate) -> u64 {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    Local.from_local_datetime(&midnight)
+        .earliest()
+        .map(|start| start.timestamp().max(0) as u64)
+        .unwrap_or(0)
+}
+
+// (label, cap, period start) for each cap that is set
+fn caps(budget: &BudgetConfig) -> Vec<(&'static str, f64, u64)> {
+    let today = Local::now().date_naive();
+    let mut caps = Vec::new();
+    if let Some(cap) = budget.daily {
+        caps.push(("daily", cap, local_start(today)));
+    }
+    if let Some(cap) = budget.monthly {
+        caps.push(("monthly", cap, local_start(today.with_day(1).unwrap_or(today))));
+    }
+    caps
+}
+
+// Why the budget is used up, if it is; an error when the spending cannot be read
+async fn exhausted(budget: &BudgetConfig, channel: Option<&str>, user: Option<&str>) -> Result<Option<String>> {
+    for (label, cap, since) in caps(budget) {
+        let spent = stats::spend(since, channel, user).await?;
+        if spent >= cap {
+            return Ok(Some(format!("{} budget of ${:.2} reached (${:.2} spent)", label, cap, spent)));
+        }
+    }
+    Ok(None)
+}
+
+// A cap that cannot be checked stops spending just like one that is reached
+fn unreadable(e: CCSwitchError) -> String {
+    warn!("Cannot read spending to check the budget: {}", e);
+    format!("budget cannot be checked, spending is unreadable: {}", e)
+}
+
+pub async fn check_global(config: &Config) -> Result<()> {
+    match exhausted(&config.budget, None, None).await {
+        Ok(Some(reason)) => Err(CCSwitchError::BudgetExceeded(reason)),
+        Ok(None) => Ok(()),
+        Err(e) => Err(CCSwitchError::BudgetExceeded(unreadable(e))),
+    }
+}
+
+// A proxy user's own cap, counted over their requests only
+pub async fn check_user(config: &Config, user: &str) -> Result<()> {
+    let Some(budget) = config.serve.users.get(user).map(|settings| &settings.budget) else {
+        return Ok(());
+    };
+    match exhausted(budget, None, Some(user)).await {
+        Ok(Some(reason)) => Err(CCSwitchError::BudgetExceeded(format!("{} for user {}", reason, user))),
+        Ok(None) => Ok(()),
+        Err(e) => Err(CCSwitchError::BudgetExceeded(format!("{} for user {}", unreadable(e), user))),
+    }
+}
+
+// Channels whose own budget is used up, with the reason
+pub async fn exhausted_channels(config: &Config) -> Vec<(String, String)> {
+    let mut exhausted_channels = Vec::new();
+    for channel in config.channels.values().filter(|channel| channel.enabled && channel.budget.is_set()) {
+        match exhausted(&channel.budget, Some(&channel.name), None).await {
+            Ok(Some(reason)) => exhausted_channels.push((channel.name.clone(), reason)),
+            Ok(None) => {}
+            Err(e) => exhausted_channels.push((channel.name.clone(), unreadable(e))),
+        }
+    }
+    exhausted_channels
+}
+
+// Emails once when the request that just cost `cost` pushed spending over a cap
+pub async fn notify_crossed(config: &Config, channel: &str, user: Option<&str>, cost: f64) {
+    let mut scopes = vec![(None, None, &config.budget)];
+    if let Some(settings) = config.channels.get(channel) {
+        scopes.push((Some(channel), None, &settings.budget));
+    }
+    if let Some(settings) = user.and_then(|user| config.serve.users.get(user)) {
+        scopes.push((None, user, &settings.budget));
+    }
+    
+    for (channel, user, budget) in scopes {
+        for (label, cap, since) in caps(budget) {
+            let Ok(spent) = stats::spend(since, channel, user).await else {
+                continue;
+            };
+            if spent >= cap && spent - cost < cap {
+                let scope = match (channel, user) {
+                    (Some(channel), _) => format!("channel {}", channel),
+                    (None, Some(user)) => format!("user {}", user),
+                    (None, None) => "all channels".to_string(),
+                };
+                let subject = format!("[ccswitch] {} budget reached for {}", label, scope);
+                let body = format!("Spent ${:.2} of the ${:.2} {} budget for {}.", spent, cap, label, scope);
+                email::notify(&config.email, EmailEvent::Budget, &subject, &body).await;
+            }
+        }
+    }
+}
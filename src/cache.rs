@@ -0,0 +1,74 @@
+use crate::client::{APIResponse, Message, RequestOptions};
+use crate::config::{now_secs, Config};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    stored_at: u64,
+    response: APIResponse,
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    Config::config_dir().ok().map(|dir| dir.join("cache"))
+}
+
+// Everything that changes what a channel would be asked; JSON object keys serialize sorted
+pub fn key(model: &str, messages: &[Message], options: &RequestOptions, config: &Config) -> String {
+    // Tags, hedging and pipeline stages decide which channels may answer
+    let mut tags = options.tags.clone();
+    tags.sort();
+    let request = json!({
+        "model": model,
+        "messages": messages,
+        "max_tokens": options.max_tokens,
+        "temperature": options.temperature,
+        "interaction": options.interaction,
+        "channel": options.channel,
+        "logprobs": options.logprobs,
+        "default_system_prompt": config.default_system_prompt,
+        "tags": tags,
+        "hedge": options.hedge || config.hedge,
+        "pipeline_stage": options.pipeline_stage,
+        "params": options.params,
+        // Proxy users never see each other's replies
+        "user": options.user,
+    });
+    format!("{:x}", Sha256::digest(request.to_string().as_bytes()))
+}
+
+pub fn lookup(key: &str, ttl_seconds: u64) -> Option<APIResponse> {
+    let path = cache_dir()?.join(format!("{}.json", key));
+    let entry: Entry = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+    
+    if now_secs().saturating_sub(entry.stored_at) >= ttl_seconds {
+        let _ = fs::remove_file(path);
+        return None;
+    }
+    
+    debug!("Response cache hit for {}", key);
+    // Replaying a stored reply costs nothing
+    Some(APIResponse {
+        cached: true,
+        cost: None,
+        tokens_per_second: None,
+        ..entry.response
+    })
+}
+
+pub fn store(key: &str, response: &APIResponse) {
+    let Some(dir) = cache_dir() else {
+        return;
+    };
+    
+    let entry = json!({ "stored_at": now_secs(), "response": response });
+    let result = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(dir.join(format!("{}.json", key)), entry.to_string()));
+    if let Err(e) = result {
+        warn!("Failed to write response cache entry: {}", e);
+    }
+}
@@ -1,16 +1,148 @@
-use crate::config::{Channel, Config};
-use crate::error::{CCSwitchError, Result};
-use reqwest::Client;
+use crate::anthropic;
+use crate::azure;
+use crate::bedrock;
+use crate::breaker::CircuitBreakers;
+use crate::client::{self, Message, RequestOptions};
+use crate::config::{self, now_secs, ApiFormat, AwsConfig, BudgetConfig, Capabilities, Channel, Config, EndpointKind, HealthCheck, OpenRouterConfig, RoutingStrategyKind, TransportConfig, VllmConfig};
+use crate::credentials::CredentialStore;
+use crate::dns::CachingResolver;
+use crate::gemini;
+use crate::huggingface;
+use crate::error::{CCSwitchError, ChannelFailure, ProviderErrorKind, Result};
+use crate::i18n::tr;
+use crate::ollama;
+use crate::openrouter;
+use crate::proxy::HttpClients;
+use crate::ratelimit::RateLimiters;
+use crate::routing::{self, RoutingContext, RoutingStrategy};
+use crate::signing;
+use crate::stats;
+use crate::upstream::{self, Diagnosis};
+use crate::vllm::{self, GpuLoad};
+use futures::stream::{self, Stream, StreamExt};
+use reqwest::{Client, Method, RequestBuilder, Response};
+use serde::Serialize;
 use serde_json::json;
-use std::time::Duration;
-use log::{debug, warn, error};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Write};
+use std::process::{Child, ExitStatus};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use log::{debug, info, warn, error};
+
+// How long a server started for a channel gets to shut down before it is killed
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct ChannelManager {
     pub config: Config,
-    client: Client,
+    clients: HttpClients,
+    interactive: bool,
+    // Channels picked interactively, keyed by proxy user and model, remembered for the rest of the session
+    session_choices: Mutex<HashMap<(Option<String>, String), String>>,
+    // Channels whose start command already ran in this process, with the server it started until that exits
+    started_channels: Mutex<HashMap<String, Option<Child>>>,
+    // Channels that answered in this process, past their `first_request_timeout_seconds`
+    answered_channels: Mutex<HashSet<String>>,
+    credentials: CredentialStore,
+    // Last probe result per channel, reused until `health_cache_seconds` elapse
+    health: Mutex<HashMap<String, CachedHealth>>,
+    // Models reported by channels with `discover_models`, fetched once per process
+    installed_models: Mutex<HashMap<String, Vec<String>>>,
+    // Recent `/metrics` verdicts of vLLM channels: when read, and why the channel was saturated
+    gpu_loads: Mutex<HashMap<String, (Instant, Option<String>)>>,
+    breakers: CircuitBreakers,
+    // Request slots for channels with `max_in_flight`, shared by everything using this manager
+    slots: Mutex<HashMap<String, Arc<Semaphore>>>,
+    rate_limiters: RateLimiters,
+    strategy: Box<dyn RoutingStrategy>,
+    latencies: Mutex<HashMap<String, Duration>>,
+}
+
+// Servers started for channels live only as long as the process that started them
+impl Drop for ChannelManager {
+    fn drop(&mut self) {
+        let started = self.started_channels.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for (name, child) in started.iter_mut() {
+            let Some(child) = child else {
+                continue;
+            };
+            info!("Stopping the server started for channel {}", name);
+            stop_process_tree(child);
+        }
+    }
+}
+
+// Stops a start command together with whatever it launched, forcibly if it does not exit in time
+fn stop_process_tree(child: &mut Child) {
+    #[cfg(unix)]
+    let group = format!("-{}", child.id());
+    #[cfg(unix)]
+    let _ = std::process::Command::new("kill").args(["-TERM", "--", &group]).status();
+    #[cfg(windows)]
+    let _ = std::process::Command::new("taskkill").args(["/T", "/PID", &child.id().to_string()]).status();
+    
+    let deadline = Instant::now() + STOP_TIMEOUT;
+    while Instant::now() < deadline {
+        if let Ok(Some(_)) = child.try_wait() {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    
+    #[cfg(unix)]
+    let _ = std::process::Command::new("kill").args(["-KILL", "--", &group]).status();
+    #[cfg(windows)]
+    let _ = std::process::Command::new("taskkill").args(["/T", "/F", "/PID", &child.id().to_string()]).status();
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+struct CachedHealth {
+    checked_at: Instant,
+    available: bool,
+    error: Option<String>,
+}
+
+// Features a request needs from the channel serving it
+#[derive(Debug, Clone, Default)]
+pub struct ChannelFilter {
+    pub logprobs: bool,
+    pub stream: bool,
+    pub max_tokens: Option<u32>,
+    // The request kind's route, when one is configured
+    pub channels: Option<Vec<String>>,
+    // Channels must carry every one of these tags
+    pub tags: Vec<String>,
+    // Proxy user whose remembered choices apply
+    pub user: Option<String>,
+    // Fixes the choices random and rotating strategies make
+    pub seed: Option<u64>,
+}
+
+impl ChannelFilter {
+    pub fn matches(&self, channel: &Channel) -> bool {
+        // Channels that have not declared a capability are given the benefit of the doubt
+        let capabilities = &channel.capabilities;
+        let unsupported = (self.logprobs && capabilities.logprobs == Some(false))
+            || (self.stream && capabilities.streaming == Some(false))
+            || matches!((self.max_tokens, capabilities.max_output_tokens), (Some(wanted), Some(limit)) if wanted > limit);
+        let unrouted = self.channels.as_ref().is_some_and(|names| !names.contains(&channel.name))
+            || !channel.has_tags(&self.tags);
+        !unsupported && !unrouted
+    }
+}
+
+// Fields `update` changes on an existing channel; None leaves a field alone
+#[derive(Debug, Default)]
+pub struct ChannelUpdate {
+    pub url: Option<String>,
+    pub api_key: Option<String>,
+    pub models: Option<Vec<String>>,
+    pub priority: Option<u32>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ChannelStatus {
     pub name: String,
     pub available: bool,
@@ -21,12 +153,81 @@ pub struct ChannelStatus {
 impl ChannelManager {
     pub fn new() -> Result<Self> {
         let config = Config::load()?;
-        let client = Client::builder()
-            .timeout(Duration::from_secs(config.timeout_seconds))
-            .build()
-            .map_err(CCSwitchError::Network)?;
+        let clients = HttpClients::new(Duration::from_secs(config.timeout_seconds), &config)?;
+        let strategy = routing::strategy(config.routing_strategy);
             
-        Ok(Self { config, client })
+        Ok(Self {
+            config,
+            clients,
+            interactive: false,
+            session_choices: Mutex::new(HashMap::new()),
+            started_channels: Mutex::new(HashMap::new()),
+            answered_channels: Mutex::new(HashSet::new()),
+            credentials: CredentialStore::default(),
+            health: Mutex::new(HashMap::new()),
+            installed_models: Mutex::new(HashMap::new()),
+            gpu_loads: Mutex::new(HashMap::new()),
+            breakers: CircuitBreakers::default(),
+            slots: Mutex::new(HashMap::new()),
+            rate_limiters: RateLimiters::default(),
+            strategy,
+            latencies: Mutex::new(HashMap::new()),
+        })
+    }
+    
+    pub async fn authorize(&self, request: RequestBuilder, channel: &Channel) -> Result<RequestBuilder> {
+        let api_key = self.credentials.api_key(channel).await?;
+        
+        match channel.api_format {
+            // Ollama needs no key, but one is passed on for authenticating reverse proxies
+            ApiFormat::Openai | ApiFormat::Ollama => match api_key {
+                Some(api_key) => Ok(request.header("Authorization", format!("Bearer {}", api_key))),
+                None => Ok(request),
+            },
+            ApiFormat::Huggingface => {
+                let request = if channel.wait_for_model { request.header("x-wait-for-model", "true") } else { request };
+                match api_key {
+                    Some(api_key) => Ok(request.header("Authorization", format!("Bearer {}", api_key))),
+                    None => Ok(request),
+                }
+            }
+            ApiFormat::Openrouter => {
+                let request = openrouter::headers(request, channel);
+                match api_key {
+                    Some(api_key) => Ok(request.header("Authorization", format!("Bearer {}", api_key))),
+                    None => Ok(request),
+                }
+            }
+            ApiFormat::Anthropic => {
+                let request = request.header("anthropic-version", anthropic::API_VERSION);
+                match api_key {
+                    Some(api_key) => Ok(request.header("x-api-key", api_key)),
+                    None => Ok(request),
+                }
+            }
+            ApiFormat::Gemini => match api_key {
+                Some(api_key) => Ok(request.header("x-goog-api-key", api_key)),
+                None => Ok(request),
+            },
+            ApiFormat::Azure => match api_key {
+                Some(api_key) => Ok(request.header("api-key", api_key)),
+                None => Ok(request),
+            },
+            // Signed together with the body in `bedrock::json_body`
+            ApiFormat::Bedrock => Ok(request),
+        }
+    }
+    
+    pub fn http_client(&self, channel: &Channel) -> &Client {
+        self.clients.for_channel(channel)
+    }
+    
+    pub fn dns_cache(&self) -> Option<Arc<CachingResolver>> {
+        self.clients.dns_cache()
+    }
+    
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
     }
     
     pub fn reload_config(&mut self) -> Result<()> {
@@ -34,124 +235,831 @@ impl ChannelManager {
         Ok(())
     }
     
-    pub fn add_channel(&mut self, name: String, url: String, api_key: Option<String>, model: Option<String>) -> Result<()> {
+    pub fn add_channel(&mut self, name: String, url: String, api_key: Option<String>, models: Vec<String>, endpoint_kind: EndpointKind, api_format: ApiFormat) -> Result<()> {
+        // A local Ollama server without pinned models serves whatever is installed
+        let discover_models = api_format == ApiFormat::Ollama && models.is_empty();
         let channel = Channel {
             name: name.clone(),
             url,
             api_key,
-            model,
+            models,
             enabled: true,
             priority: 0,
+            endpoint_kind,
+            api_format,
+            idempotency_header: config::default_idempotency_header(),
+            health_check: None,
+            health_accept_statuses: None,
+            capabilities: Capabilities::default(),
+            signing: None,
+            start_command: None,
+            start_timeout_seconds: config::default_start_timeout_seconds(),
+            credential_helper: None,
+            use_proxy: config::default_use_proxy(),
+            transport: TransportConfig::default(),
+            description: None,
+            metadata: BTreeMap::new(),
+            tags: Vec::new(),
+            key_expires_at: None,
+            prompt_caching: false,
+            discover_models,
+            first_request_timeout_seconds: None,
+            vllm: VllmConfig::default(),
+            wait_for_model: false,
+            keep_alive: false,
+            context_window: None,
+            deployment: None,
+            api_version: None,
+            aws: AwsConfig::default(),
+            openrouter: OpenRouterConfig::default(),
+            system_prompt: None,
+            system_prefix: None,
+            extra_body: serde_json::Map::new(),
+            max_temperature: None,
+            sampling_params: Vec::new(),
+            post_process: Vec::new(),
+            auto_disabled_until: None,
+            max_in_flight: None,
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            pricing: HashMap::new(),
+            weight: 1,
+            model_map: HashMap::new(),
+            budget: BudgetConfig::default(),
         };
         
         self.config.add_channel(channel)?;
         Ok(())
     }
     
+    // Only the fields given change; priority, enabled state and everything else are kept
+    pub fn update_channel(&mut self, name: &str, update: ChannelUpdate) -> Result<()> {
+        self.config.update_channel(name, |channel| {
+            if let Some(url) = update.url {
+                channel.url = url;
+            }
+            // The helper would otherwise keep winning over the key just given
+            if let Some(api_key) = update.api_key {
+                if channel.credential_helper.take().is_some() {
+                    info!("Dropped the credential helper of channel {} in favour of the new key", channel.name);
+                }
+                channel.api_key = Some(api_key);
+            }
+            if let Some(models) = update.models {
+                channel.models = models;
+            }
+            if let Some(priority) = update.priority {
+                channel.priority = priority;
+            }
+        })
+    }
+    
     pub fn remove_channel(&mut self, name: &str) -> Result<()> {
         self.config.remove_channel(name)?;
         Ok(())
     }
     
+    pub fn restore_channel(&mut self, name: &str) -> Result<()> {
+        self.config.restore_channel(name)?;
+        Ok(())
+    }
+    
     pub fn list_channels(&self) -> Vec<&Channel> {
         self.config.channels.values().collect()
     }
     
     pub async fn test_channel(&self, channel: &Channel) -> ChannelStatus {
-        debug!("Testing channel: {}", channel.name);
+        let (status, refused) = self.probe_channel(channel).await;
         
-        let start = std::time::Instant::now();
+        let status = match &channel.start_command {
+            Some(command) if refused => self.wake_channel(channel, command).await.unwrap_or(status),
+            _ => status,
+        };
         
-        // Create a simple test request
-        let test_payload = json!({
-            "model": channel.model.as_deref().unwrap_or("test"),
-            "messages": [
-                {
-                    "role": "user",
-                    "content": "Hello"
-                }
-            ],
-            "max_tokens": 1
+        self.health.lock().unwrap().insert(channel.name.clone(), CachedHealth {
+            checked_at: Instant::now(),
+            available: status.available,
+            error: status.error.clone(),
+        });
+        self.track_health(channel, status.available).await;
+        status
+    }
+    
+    // Checks are remembered across runs so a channel that keeps failing can be taken out of rotation
+    async fn track_health(&self, channel: &Channel, available: bool) {
+        let settings = &self.config.auto_disable;
+        if !settings.enabled {
+            return;
+        }
+        stats::record_health(&channel.name, available).await;
+        if available || channel.is_auto_disabled() {
+            return;
+        }
+        
+        let since = now_secs().saturating_sub(settings.window_minutes * 60);
+        let failures = match stats::health_failures(&channel.name, since).await {
+            Ok(failures) => failures,
+            Err(e) => {
+                warn!("Cannot count health check failures for {}: {}", channel.name, e);
+                return;
+            }
+        };
+        if failures < u64::from(settings.failures.max(1)) {
+            return;
+        }
+        
+        let until = now_secs() + settings.disable_minutes * 60;
+        let saved = Config::load().and_then(|mut config| {
+            if let Some(saved) = config.channels.get_mut(&channel.name) {
+                saved.auto_disabled_until = Some(until);
+            }
+            config.save()
+        });
+        match saved {
+            Ok(()) => warn!("Channel {} failed {} health checks in a row, disabling it for {} minutes", channel.name, failures, settings.disable_minutes),
+            Err(e) => warn!("Could not auto-disable channel {}: {}", channel.name, e),
+        }
+    }
+    
+    // Reuses a recent probe result instead of paying for another test completion
+    async fn cached_status(&self, channel: &Channel) -> ChannelStatus {
+        let ttl = Duration::from_secs(self.config.health_cache_seconds);
+        
+        if let Some(cached) = self.health.lock().unwrap().get(&channel.name) {
+            if cached.checked_at.elapsed() < ttl {
+                debug!("Using cached health for channel: {}", channel.name);
+                return ChannelStatus {
+                    name: channel.name.clone(),
+                    available: cached.available,
+                    response_time_ms: None,
+                    error: cached.error.clone(),
+                };
+            }
+        }
+        
+        self.test_channel(channel).await
+    }
+    
+    // Exponentially weighted, so the latency strategy follows a channel that slows down
+    pub fn record_latency(&self, name: &str, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        let smoothed = match latencies.get(name) {
+            Some(previous) => previous.mul_f64(0.7) + latency.mul_f64(0.3),
+            None => latency,
+        };
+        latencies.insert(name.to_string(), smoothed);
+    }
+    
+    // A real request succeeded, which is as good as a passing probe
+    pub fn mark_healthy(&self, name: &str) {
+        self.health.lock().unwrap().insert(name.to_string(), CachedHealth {
+            checked_at: Instant::now(),
+            available: true,
+            error: None,
         });
+        self.breakers.record_success(name);
+    }
+    
+    // A real request failed; enough of these in a row open the channel's circuit
+    pub fn record_failure(&self, name: &str) {
+        self.invalidate_health(name);
+        self.breakers.record_failure(&self.config.circuit_breaker, name);
+    }
+    
+    fn slots(&self, channel: &Channel) -> Option<Arc<Semaphore>> {
+        let max = channel.max_in_flight?;
+        let mut slots = self.slots.lock().unwrap();
+        Some(slots.entry(channel.name.clone()).or_insert_with(|| Arc::new(Semaphore::new(max.max(1) as usize))).clone())
+    }
+    
+    pub fn is_saturated(&self, channel: &Channel) -> bool {
+        self.slots(channel).is_some_and(|slots| slots.available_permits() == 0)
+    }
+    
+    // Held for the duration of a request; None for channels without `max_in_flight`
+    pub async fn acquire_slot(&self, channel: &Channel) -> Option<OwnedSemaphorePermit> {
+        let slots = self.slots(channel)?;
+        if slots.available_permits() == 0 {
+            debug!("Channel {} is at max_in_flight, waiting for a slot", channel.name);
+        }
+        slots.acquire_owned().await.ok()
+    }
+    
+    pub fn is_rate_limited(&self, channel: &Channel) -> bool {
+        self.rate_limiters.blocked_for(channel).is_some()
+    }
+    
+    // Waits until the channel's RPM/TPM allowance has room, then charges the request to it
+    pub async fn acquire_rate_limit(&self, channel: &Channel, estimated_tokens: u64) {
+        self.rate_limiters.acquire(channel, estimated_tokens).await;
+    }
+    
+    pub fn adjust_rate_limit(&self, channel: &Channel, delta_tokens: i64) {
+        self.rate_limiters.adjust_tokens(channel, delta_tokens);
+    }
+    
+    fn circuit_open(&self, channel: &Channel) -> Option<ChannelFailure> {
+        let remaining = self.breakers.blocked_for(&self.config.circuit_breaker, &channel.name)?;
+        debug!("Skipping channel {} with an open circuit", channel.name);
+        Some(ChannelFailure {
+            channel: channel.name.clone(),
+            reason: format!("circuit open, retrying in {}s", remaining.as_secs().max(1)),
+        })
+    }
+    
+    // Forgets the cached result so the channel is probed again before its next use
+    pub fn invalidate_health(&self, name: &str) {
+        self.health.lock().unwrap().remove(name);
+    }
+    
+    // Runs the channel's start command and waits for it to come up; None if it never does
+    async fn wake_channel(&self, channel: &Channel, command: &str) -> Option<ChannelStatus> {
+        {
+            let mut started = self.started_channels.lock().unwrap();
+            if started.contains_key(&channel.name) {
+                return None;
+            }
+            
+            info!("Channel {} refused the connection, starting it with: {}", channel.name, command);
+            
+            let mut start = if cfg!(windows) {
+                let mut start = std::process::Command::new("cmd");
+                start.args(["/C", command]);
+                start
+            } else {
+                let mut start = std::process::Command::new("sh");
+                start.args(["-c", command]);
+                start
+            };
+            // Its own process group, so the server the shell runs can be stopped along with the shell
+            #[cfg(unix)]
+            {
+                use std::os::unix::process::CommandExt;
+                start.process_group(0);
+            }
+            let spawned = start.spawn();
+            
+            match spawned {
+                Ok(child) => started.insert(channel.name.clone(), Some(child)),
+                Err(e) => {
+                    error!("Failed to run start command for channel {}: {}", channel.name, e);
+                    started.insert(channel.name.clone(), None);
+                    return None;
+                }
+            };
+        }
         
-        let mut request = self.client.post(&channel.url);
+        let deadline = std::time::Instant::now() + Duration::from_secs(channel.start_timeout_seconds);
         
-        if let Some(api_key) = &channel.api_key {
-            request = request.header("Authorization", format!("Bearer {}", api_key));
+        while std::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            
+            // Commands that hand the server off to a daemon exit successfully; anything else is a failed start
+            if let Some(exit) = self.reap_started(&channel.name).filter(|exit| !exit.success()) {
+                warn!("Start command for channel {} exited with {}", channel.name, exit);
+                return None;
+            }
+            
+            let (status, refused) = self.probe_channel(channel).await;
+            if !refused {
+                return Some(status);
+            }
         }
         
-        request = request
-            .header("Content-Type", "application/json")
-            .json(&test_payload);
+        warn!("Channel {} did not become ready within {}s", channel.name, channel.start_timeout_seconds);
+        None
+    }
+    
+    // Collects the exit status of a start command that has finished, so it leaves no zombie behind
+    fn reap_started(&self, name: &str) -> Option<ExitStatus> {
+        let mut started = self.started_channels.lock().unwrap();
+        let slot = started.get_mut(name)?;
+        let exit = slot.as_mut()?.try_wait().ok()??;
+        *slot = None;
+        Some(exit)
+    }
+    
+    // Returns the status plus whether the connection itself was refused
+    async fn probe_channel(&self, channel: &Channel) -> (ChannelStatus, bool) {
+        debug!("Testing channel: {}", channel.name);
+        
+        let start = std::time::Instant::now();
+        
+        let request = match &channel.health_check {
+            Some(check) => match self.custom_health_request(channel, check) {
+                Ok(request) => request,
+                Err(error) => {
+                    error!("Channel {} has an invalid health check: {}", channel.name, error);
+                    let status = ChannelStatus {
+                        name: channel.name.clone(),
+                        available: false,
+                        response_time_ms: None,
+                        error: Some(error),
+                    };
+                    return (status, false);
+                }
+            },
+            None => match self.default_health_request(channel) {
+                Ok(request) => request,
+                Err(e) => {
+                    error!("Channel {} has no usable credentials: {}", channel.name, e);
+                    let status = ChannelStatus {
+                        name: channel.name.clone(),
+                        available: false,
+                        response_time_ms: None,
+                        error: Some(e.to_string()),
+                    };
+                    return (status, false);
+                }
+            },
+        };
+        
+        let request = match self.authorize(request, channel).await {
+            Ok(request) => request,
+            Err(e) => {
+                error!("Channel {} has no usable credentials: {}", channel.name, e);
+                let status = ChannelStatus {
+                    name: channel.name.clone(),
+                    available: false,
+                    response_time_ms: None,
+                    error: Some(e.to_string()),
+                };
+                return (status, false);
+            }
+        };
         
         match request.send().await {
             Ok(response) => {
                 let response_time = start.elapsed().as_millis() as u64;
-                let status_code = response.status();
                 
-                if status_code.is_success() || status_code.as_u16() == 400 {
-                    // 400 might be OK for test requests with invalid model
-                    debug!("Channel {} is available (response time: {}ms)", channel.name, response_time);
-                    ChannelStatus {
-                        name: channel.name.clone(),
-                        available: true,
-                        response_time_ms: Some(response_time),
-                        error: None,
+                let verdict = match &channel.health_check {
+                    Some(check) => evaluate_custom_health(check, response, self.config.max_response_bytes).await,
+                    None => {
+                        let accepted = channel.health_accept_statuses
+                            .as_deref()
+                            .unwrap_or(&self.config.health_accept_statuses);
+                        evaluate_default_health(channel.api_format, accepted, response, self.config.max_response_bytes).await
                     }
-                } else {
-                    let error = format!("HTTP {}: {}", status_code, status_code.canonical_reason().unwrap_or("Unknown"));
-                    warn!("Channel {} returned error: {}", channel.name, error);
-                    ChannelStatus {
-                        name: channel.name.clone(),
-                        available: false,
-                        response_time_ms: Some(response_time),
-                        error: Some(error),
+                };
+                
+                let status = match verdict {
+                    Ok(()) => {
+                        debug!("Channel {} is available (response time: {}ms)", channel.name, response_time);
+                        self.record_latency(&channel.name, Duration::from_millis(response_time));
+                        ChannelStatus {
+                            name: channel.name.clone(),
+                            available: true,
+                            response_time_ms: Some(response_time),
+                            error: None,
+                        }
                     }
-                }
+                    Err(error) => {
+                        warn!("Channel {} returned error: {}", channel.name, error);
+                        ChannelStatus {
+                            name: channel.name.clone(),
+                            available: false,
+                            response_time_ms: Some(response_time),
+                            error: Some(error),
+                        }
+                    }
+                };
+                (status, false)
             }
             Err(e) => {
                 error!("Channel {} failed: {}", channel.name, e);
-                ChannelStatus {
+                let status = ChannelStatus {
                     name: channel.name.clone(),
                     available: false,
                     response_time_ms: None,
                     error: Some(e.to_string()),
+                };
+                (status, e.is_connect())
+            }
+        }
+    }
+    
+    fn default_health_request(&self, channel: &Channel) -> Result<RequestBuilder> {
+        // Listing models is free, unlike a test generation
+        match channel.api_format {
+            ApiFormat::Gemini => {
+                let url = format!("{}/models", gemini::base_url(&channel.url));
+                return Ok(signing::sign(self.http_client(channel).get(url), channel, b""));
+            }
+            ApiFormat::Ollama => {
+                let url = ollama::tags_url(&channel.url);
+                return Ok(signing::sign(self.http_client(channel).get(url), channel, b""));
+            }
+            _ => {}
+        }
+        
+        // Create a simple test request
+        let model = channel.named_models().next().unwrap_or("test");
+        
+        if channel.api_format == ApiFormat::Bedrock {
+            let url = bedrock::invoke_url(channel, model);
+            let options = RequestOptions { max_tokens: Some(1), ..RequestOptions::default() };
+            let payload = bedrock::payload(model, &[Message::user("Hello")], &options);
+            return bedrock::json_body(self.http_client(channel).post(&url), channel, &url, &payload);
+        }
+        
+        // Serverless models have no free endpoint to ask; TGI's `/health` would miss a model still loading
+        if channel.api_format == ApiFormat::Huggingface {
+            let url = huggingface::endpoint(&channel.url, model);
+            let options = RequestOptions { max_tokens: Some(1), ..RequestOptions::default() };
+            let payload = huggingface::payload(&[], "Hello", &options, false);
+            return Ok(signing::json_body(self.http_client(channel).post(url), channel, &payload));
+        }
+        
+        let test_payload = match (channel.api_format, channel.endpoint_kind) {
+            (ApiFormat::Anthropic, _) => json!({
+                "model": model,
+                "messages": [{ "role": "user", "content": "Hello" }],
+                "max_tokens": 1
+            }),
+            (_, EndpointKind::Chat) => json!({
+                "model": model,
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": "Hello"
+                    }
+                ],
+                "max_tokens": 1
+            }),
+            (_, EndpointKind::Completion) => json!({
+                "model": model,
+                "prompt": "Hello",
+                "max_tokens": 1
+            }),
+        };
+        
+        let url = match channel.api_format {
+            ApiFormat::Azure => azure::endpoint(channel, model),
+            _ => channel.url.clone(),
+        };
+        let request = self.http_client(channel).post(url);
+        Ok(signing::json_body(request, channel, &test_payload))
+    }
+    
+    fn custom_health_request(&self, channel: &Channel, check: &HealthCheck) -> std::result::Result<RequestBuilder, String> {
+        let method = Method::from_bytes(check.method.to_uppercase().as_bytes())
+            .map_err(|_| format!("unsupported method '{}'", check.method))?;
+        let url = url::Url::parse(&channel.url)
+            .and_then(|base| base.join(&check.path))
+            .map_err(|e| format!("invalid path '{}': {}", check.path, e))?;
+        
+        let request = self.http_client(channel).request(method, url);
+        Ok(signing::sign(request, channel, b""))
+    }
+    
+    // Results come back in priority order regardless of which probe finishes first
+    pub async fn test_all_channels(&self, include_disabled: bool) -> Vec<ChannelStatus> {
+        stream::iter(self.testable_by_priority(include_disabled))
+            .map(|channel| self.test_channel(channel))
+            .buffered(self.test_concurrency())
+            .collect()
+            .await
+    }
+    
+    // Yields each channel's status as soon as its probe finishes
+    pub fn test_channels_streaming(&self, include_disabled: bool) -> impl Stream<Item = ChannelStatus> + '_ {
+        stream::iter(self.testable_by_priority(include_disabled))
+            .map(move |channel| self.test_channel(channel))
+            .buffer_unordered(self.test_concurrency())
+    }
+    
+    fn testable_by_priority(&self, include_disabled: bool) -> Vec<&Channel> {
+        let mut channels: Vec<&Channel> = self.config.channels.values().filter(|ch| include_disabled || ch.is_active()).collect();
+        channels.sort_by(|a, b| a.priority.cmp(&b.priority).then_with(|| a.name.cmp(&b.name)));
+        channels
+    }
+    
+    fn test_concurrency(&self) -> usize {
+        self.config.test_concurrency.max(1)
+    }
+    
+    async fn channels_for_model(&self, model: &str) -> Vec<&Channel> {
+        let mut channels = Vec::new();
+        
+        for channel in self.config.get_channels_for_model(model) {
+            if channel.models.is_empty() && channel.discover_models {
+                if let Some(installed) = self.installed_models(channel).await {
+                    if !ollama::has_model(&installed, channel.upstream_model(model)) {
+                        debug!("Channel {} does not have model {}", channel.name, model);
+                        continue;
+                    }
+                }
+            }
+            if channel.vllm.is_set() {
+                if let Some(reason) = self.gpu_saturation(channel).await {
+                    info!("Skipping channel {}: {}", channel.name, reason);
+                    continue;
                 }
             }
+            channels.push(channel);
+        }
+        
+        channels
+    }
+    
+    // Reread once `vllm::LOAD_CACHE` has passed; unreadable metrics leave the channel in
+    async fn gpu_saturation(&self, channel: &Channel) -> Option<String> {
+        if let Some((read_at, saturated)) = self.gpu_loads.lock().unwrap().get(&channel.name) {
+            if read_at.elapsed() < vllm::LOAD_CACHE {
+                return saturated.clone();
+            }
+        }
+        
+        let saturated = self.read_gpu_load(channel).await.and_then(|load| load.saturated(&channel.vllm));
+        self.gpu_loads.lock().unwrap().insert(channel.name.clone(), (Instant::now(), saturated.clone()));
+        saturated
+    }
+    
+    async fn read_gpu_load(&self, channel: &Channel) -> Option<GpuLoad> {
+        let request = self.http_client(channel).get(vllm::metrics_url(&channel.url)).timeout(vllm::METRICS_TIMEOUT);
+        let response = match self.authorize(request, channel).await.ok()?.send().await {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                warn!("Could not read metrics on channel {}: {}", channel.name, response.status());
+                return None;
+            }
+            Err(e) => {
+                warn!("Could not read metrics on channel {}: {}", channel.name, e);
+                return None;
+            }
+        };
+        let metrics = match client::read_limited(response, self.config.max_response_bytes).await {
+            Ok(Some(metrics)) => metrics,
+            Ok(None) => {
+                warn!("Metrics on channel {} exceed max_response_bytes", channel.name);
+                return None;
+            }
+            Err(e) => {
+                warn!("Could not read metrics on channel {}: {}", channel.name, e);
+                return None;
+            }
+        };
+        let load = GpuLoad::parse(&String::from_utf8_lossy(&metrics));
+        debug!("Channel {} load: {:?}", channel.name, load);
+        Some(load)
+    }
+    
+    pub fn first_request_timeout(&self, channel: &Channel) -> Option<Duration> {
+        let seconds = channel.first_request_timeout_seconds?;
+        (!self.answered_channels.lock().unwrap().contains(&channel.name)).then(|| Duration::from_secs(seconds))
+    }
+    
+    pub fn record_answer(&self, channel: &Channel) {
+        if channel.first_request_timeout_seconds.is_some() {
+            self.answered_channels.lock().unwrap().insert(channel.name.clone());
+        }
+    }
+    
+    // None when the list cannot be fetched; the channel is then kept and left to the health check
+    pub async fn installed_models(&self, channel: &Channel) -> Option<Vec<String>> {
+        if let Some(installed) = self.installed_models.lock().unwrap().get(&channel.name) {
+            return Some(installed.clone());
         }
+        
+        let url = match channel.api_format {
+            ApiFormat::Ollama => ollama::tags_url(&channel.url),
+            _ => vllm::models_url(&channel.url),
+        };
+        let request = self.http_client(channel).get(url);
+        let response = self.authorize(request, channel).await.ok()?.send().await;
+        let tags: serde_json::Value = match response {
+            Ok(response) if response.status().is_success() => {
+                serde_json::from_str(&read_text(response, self.config.max_response_bytes).await?).ok()?
+            }
+            Ok(response) => {
+                warn!("Could not list models on channel {}: {}", channel.name, response.status());
+                return None;
+            }
+            Err(e) => {
+                warn!("Could not list models on channel {}: {}", channel.name, e);
+                return None;
+            }
+        };
+        
+        let installed = match channel.api_format {
+            ApiFormat::Ollama => ollama::model_names(&tags),
+            _ => vllm::model_ids(&tags),
+        };
+        debug!("Channel {} has models: {:?}", channel.name, installed);
+        self.installed_models.lock().unwrap().insert(channel.name.clone(), installed.clone());
+        Some(installed)
     }
     
-    pub async fn test_all_channels(&self) -> Vec<ChannelStatus> {
-        let mut results = Vec::new();
+    pub async fn available_channels(&self, model: &str, filter: &ChannelFilter) -> Result<Vec<&Channel>> {
+        let mut channels: Vec<&Channel> = self
+            .channels_for_model(model)
+            .await
+            .into_iter()
+            .filter(|ch| filter.matches(ch))
+            .collect();
+        
+        if channels.is_empty() {
+            return Err(CCSwitchError::NoAvailableChannels(model.to_string()));
+        }
+        
+        channels.sort_by_key(|ch| ch.priority);
+        
+        let mut available = Vec::new();
+        let mut failures = Vec::new();
         
-        for channel in self.config.channels.values() {
-            if channel.enabled {
-                let status = self.test_channel(channel).await;
-                results.push(status);
+        for channel in channels {
+            if let Some(failure) = self.circuit_open(channel) {
+                failures.push(failure);
+                continue;
+            }
+            let status = self.cached_status(channel).await;
+            if status.available {
+                available.push(channel);
+            } else {
+                failures.push(ChannelFailure {
+                    channel: status.name,
+                    reason: status.error.unwrap_or_else(|| "Unavailable".to_string()),
+                });
             }
         }
         
-        results
+        if available.is_empty() {
+            return Err(CCSwitchError::AllChannelsFailed(failures));
+        }
+        
+        Ok(available)
     }
     
-    pub async fn find_available_channel(&self, model: &str) -> Result<&Channel> {
-        let channels = self.config.get_channels_for_model(model);
+    pub async fn find_available_channel(&self, model: &str, exclude: &[&str], filter: &ChannelFilter) -> Result<&Channel> {
+        let channels: Vec<&Channel> = self
+            .channels_for_model(model)
+            .await
+            .into_iter()
+            .filter(|ch| !exclude.contains(&ch.name.as_str()) && filter.matches(ch))
+            .collect();
         
         if channels.is_empty() {
             return Err(CCSwitchError::NoAvailableChannels(model.to_string()));
         }
         
-        // Test channels in priority order
+        // Test channels in the order the routing strategy prefers
         let mut sorted_channels = channels;
-        sorted_channels.sort_by_key(|ch| ch.priority);
+        let latencies = self.latencies.lock().unwrap().clone();
+        self.strategy.order(&mut sorted_channels, &RoutingContext {
+            config: &self.config,
+            model,
+            latencies: &latencies,
+            seed: filter.seed,
+        });
+        
+        // Asking only makes sense when priority alone decides
+        if self.interactive && self.config.routing_strategy == RoutingStrategyKind::Priority {
+            self.apply_interactive_choice(model, filter.user.as_deref(), &mut sorted_channels);
+        }
+        
+        let mut failures = Vec::new();
+        let mut busy = None;
+        let mut throttled: Option<(&Channel, Duration)> = None;
         
         for channel in sorted_channels {
-            let status = self.test_channel(channel).await;
+            if let Some(failure) = self.circuit_open(channel) {
+                failures.push(failure);
+                continue;
+            }
+            // Over its RPM/TPM allowance: spill to the next channel, keeping the soonest to free up
+            if let Some(wait) = self.rate_limiters.blocked_for(channel) {
+                debug!("Skipping channel {} over its rate limit", channel.name);
+                if throttled.is_none_or(|(_, soonest)| wait < soonest) {
+                    throttled = Some((channel, wait));
+                }
+                continue;
+            }
+            let status = self.cached_status(channel).await;
+            // A channel with every slot taken is passed over while another has room
+            if status.available && self.is_saturated(channel) {
+                busy.get_or_insert(channel);
+                continue;
+            }
             if status.available {
+                self.breakers.begin(&channel.name);
                 return Ok(channel);
             }
+            
+            failures.push(ChannelFailure {
+                channel: status.name,
+                reason: status.error.unwrap_or_else(|| "Unavailable".to_string()),
+            });
         }
         
-        Err(CCSwitchError::AllChannelsFailed)
+        // All healthy channels are busy or rate limited: wait on the preferred one
+        if let Some(channel) = busy.or(throttled.map(|(channel, _)| channel)) {
+            self.breakers.begin(&channel.name);
+            return Ok(channel);
+        }
+        Err(CCSwitchError::AllChannelsFailed(failures))
     }
+    
+    fn apply_interactive_choice(&self, model: &str, user: Option<&str>, channels: &mut [&Channel]) {
+        let top_priority = channels[0].priority;
+        let tied = channels.iter().take_while(|ch| ch.priority == top_priority).count();
+        
+        if tied < 2 {
+            return;
+        }
+        
+        let key = (user.map(str::to_string), model.to_string());
+        let remembered = self.session_choices.lock().unwrap().get(&key).cloned();
+        let chosen = match remembered.and_then(|name| channels[..tied].iter().position(|ch| ch.name == name)) {
+            Some(index) => index,
+            None => match prompt_channel_choice(model, &channels[..tied]) {
+                Some(index) => {
+                    self.session_choices.lock().unwrap().insert(key, channels[index].name.clone());
+                    index
+                }
+                None => return,
+            },
+        };
+        
+        // Try the chosen channel first; the others remain as fallbacks
+        channels[..=chosen].rotate_right(1);
+    }
+}
+
+fn prompt_channel_choice(model: &str, channels: &[&Channel]) -> Option<usize> {
+    eprintln!("{}", tr("choose-header", &[("model", model.to_string())]));
+    for (index, channel) in channels.iter().enumerate() {
+        eprintln!("  {}) {} - {}", index + 1, channel.name, channel.url);
+    }
+    eprint!("{} ", tr("choose-prompt", &[("count", channels.len().to_string())]));
+    io::stderr().flush().ok()?;
+    
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).ok()?;
+    
+    let choice: usize = input.trim().parse().ok()?;
+    (1..=channels.len()).contains(&choice).then(|| choice - 1)
+}
+
+// The body as text, or None when it cannot be read or runs past `limit`
+async fn read_text(response: Response, limit: u64) -> Option<String> {
+    let body = client::read_limited(response, limit).await.ok()??;
+    Some(String::from_utf8_lossy(&body).into_owned())
+}
+
+async fn evaluate_default_health(format: ApiFormat, accepted: &[u16], response: Response, limit: u64) -> std::result::Result<(), String> {
+    let status_code = response.status();
+    let reason = format!("HTTP {}: {}", status_code, status_code.canonical_reason().unwrap_or("Unknown"));
+    let accepted = accepted.contains(&status_code.as_u16());
+    
+    // The probe endpoints answer in JSON; an HTML page is a challenge or proxy page in the way
+    let html = client::content_type(&response).filter(|value| value.starts_with("text/html"));
+    if let Some(content_type) = html.filter(|_| status_code.is_success() || !accepted) {
+        let body = read_text(response, limit).await.unwrap_or_default();
+        let page = client::html_page(Some(&content_type), &body).unwrap_or_default();
+        return Err(format!("{} ({} instead of an API response)", reason, page));
+    }
+    
+    if status_code.is_success() {
+        return Ok(());
+    }
+    
+    let body = read_text(response, limit).await.unwrap_or_default();
+    let diagnosis = upstream::diagnose_for(format, status_code, &body);
+    if !accepted {
+        return Err(match diagnosis {
+            Some(diagnosis) => format!("{} ({})", reason, upstream::summary(&diagnosis, &body)),
+            None => reason,
+        });
+    }
+    
+    // An accepted error status (e.g. 400 for the fake test model) still fails on auth or quota problems
+    match diagnosis {
+        Some(diagnosis @ (Diagnosis::Auth | Diagnosis::Provider(ProviderErrorKind::QuotaExhausted))) => {
+            Err(format!("{} ({})", reason, upstream::summary(&diagnosis, &body)))
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn evaluate_custom_health(check: &HealthCheck, response: Response, limit: u64) -> std::result::Result<(), String> {
+    let status_code = response.status();
+    let status_ok = match check.expected_status {
+        Some(expected) => status_code.as_u16() == expected,
+        None => status_code.is_success(),
+    };
+    
+    if !status_ok {
+        return Err(format!("HTTP {}: {}", status_code, status_code.canonical_reason().unwrap_or("Unknown")));
+    }
+    
+    if let Some(needle) = &check.body_contains {
+        let body = read_text(response, limit)
+            .await
+            .ok_or_else(|| format!("health check response unreadable or over max_response_bytes ({} bytes)", limit))?;
+        if !body.contains(needle.as_str()) {
+            return Err(format!("health check response does not contain '{}'", needle));
+        }
+    }
+    
+    Ok(())
 }
\ No newline at end of file
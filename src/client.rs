@@ -1,22 +1,128 @@
-use crate::config::Channel;
-use crate::channel::ChannelManager;
-use crate::error::{CCSwitchError, Result};
-use reqwest::Client;
+use crate::anthropic;
+use crate::azure;
+use crate::bedrock;
+use crate::budget;
+use crate::cache;
+use crate::config::{ApiFormat, Channel, Config, EndpointKind, Preset, ResponseRetryConfig};
+use crate::consensus::Consensus;
+use crate::channel::{ChannelFilter, ChannelManager};
+use crate::error::{CCSwitchError, ChannelFailure, Result};
+use crate::events::{EventSink, RequestEvent};
+use crate::gemini;
+use crate::huggingface;
+use crate::language::Language;
+use crate::ollama;
+use crate::openrouter;
+use crate::pipeline::StageUsage;
+use crate::postprocess;
+use crate::pricing;
+use crate::proxy::HttpClients;
+use crate::signing;
+use crate::stats::{self, UsageRecord};
+use crate::tokens;
+use crate::translate;
+use crate::upstream::{self, Diagnosis};
+use futures::future::{self, Either};
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::time::Duration;
-use log::{info, error};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use log::{info, warn, error};
+use uuid::Uuid;
 
 pub struct APIClient {
     channel_manager: ChannelManager,
-    client: Client,
+    clients: HttpClients,
+    event_sink: Option<EventSink>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+impl Message {
+    pub fn system(content: &str) -> Self {
+        Self {
+            role: "system".to_string(),
+            content: content.to_string(),
+        }
+    }
+    
+    pub fn user(content: &str) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: content.to_string(),
+        }
+    }
+    
+    pub fn assistant(content: &str) -> Self {
+        Self {
+            role: "assistant".to_string(),
+            content: content.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct RequestOptions {
     pub model: Option<String>,
     pub max_tokens: Option<u32>,
     pub temperature: Option<f32>,
     pub stream: bool,
+    pub interaction: EndpointKind,
+    // Send to this channel directly, skipping selection and health checks
+    pub channel: Option<String>,
+    pub system: Option<String>,
+    // Number of top alternatives to return log probabilities for
+    pub logprobs: Option<u8>,
+    // Per-request event channel, used instead of the client's sink when set (e.g. by `serve`)
+    pub events: Option<UnboundedSender<RequestEvent>>,
+    // Bypass the response cache for this request
+    pub no_cache: bool,
+    // Ask for replies in this language and retry ones that are not
+    pub language: Option<Language>,
+    // Race the top two channels and keep the first reply; the config's `hedge` turns it on for every request
+    pub hedge: bool,
+    // Only route to channels carrying all of these tags
+    pub tags: Vec<String>,
+    // Proxy user the request is made for; their sticky channel choices, budget and cache entries are their own
+    pub user: Option<String>,
+    // The `--pipeline` stage ("draft" or "refine") this request is, when it is one
+    pub pipeline_stage: Option<&'static str>,
+    // Provider-specific fields, sent only to channels listing them in `sampling_params`
+    pub params: serde_json::Map<String, Value>,
+    // Makes the random and rotating routing strategies pick the same channels every time
+    pub selection_seed: Option<u64>,
+    // `--label` pairs recorded with the request's usage and history, e.g. project=alpha
+    pub labels: BTreeMap<String, String>,
+}
+
+impl RequestOptions {
+    pub fn channel_filter(&self, config: &Config) -> ChannelFilter {
+        ChannelFilter {
+            logprobs: self.logprobs.is_some(),
+            stream: self.stream,
+            max_tokens: self.max_tokens,
+            channels: config.routes.get(&self.interaction).cloned(),
+            tags: self.tags.clone(),
+            user: self.user.clone(),
+            seed: self.selection_seed,
+        }
+    }
+    
+    // Explicitly set options win over the preset's values
+    pub fn with_preset(mut self, preset: &Preset) -> Self {
+        self.model = self.model.or_else(|| preset.model.clone());
+        self.max_tokens = self.max_tokens.or(preset.max_tokens);
+        self.temperature = self.temperature.or(preset.temperature);
+        self.system = self.system.or_else(|| preset.system.clone());
+        self
+    }
 }
 
 impl Default for RequestOptions {
@@ -26,114 +132,1147 @@ impl Default for RequestOptions {
             max_tokens: Some(1000),
             temperature: Some(0.7),
             stream: false,
+            interaction: EndpointKind::Chat,
+            channel: None,
+            system: None,
+            logprobs: None,
+            events: None,
+            no_cache: false,
+            language: None,
+            hedge: false,
+            tags: Vec::new(),
+            user: None,
+            pipeline_stage: None,
+            params: serde_json::Map::new(),
+            selection_seed: None,
+            labels: BTreeMap::new(),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedChannel {
+    pub channel: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct APIResponse {
     pub content: String,
     pub channel_used: String,
     pub model: String,
     pub usage: Option<Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_channels: Vec<SkippedChannel>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Value>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub upstream_headers: BTreeMap<String, String>,
+    // Served from the response cache rather than a channel
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    // Estimated from `usage` and the pricing table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    // Completion tokens per second after the first one arrived; streamed replies only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens_per_second: Option<f64>,
+    // How a `--consensus` reply was reached
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consensus: Option<Consensus>,
+    // Per-stage usage of a `--pipeline` reply; `usage` and `cost` hold the totals
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pipeline: Vec<StageUsage>,
+    // The `--selection-seed` routing used, so the choice can be replayed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection_seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+pub fn is_event_stream(response: &reqwest::Response) -> bool {
+    response.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"))
+}
+
+// The channel's `extra_body` fields the request left unset, and its temperature ceiling
+fn apply_channel_params(payload: &mut Value, channel: &Channel) {
+    let Some(body) = payload.as_object_mut() else {
+        return;
+    };
+    for (key, value) in &channel.extra_body {
+        if body.get(key).is_none_or(Value::is_null) {
+            body.insert(key.clone(), value.clone());
+        }
+    }
+    if let (Some(max), Some(temperature)) = (channel.max_temperature, body.get("temperature").and_then(Value::as_f64)) {
+        if temperature > max as f64 {
+            body.insert("temperature".to_string(), json!(max));
+        }
+    }
+}
+
+// OpenAI chat chunks carry `delta.content`, completion chunks `text`, Claude `content_block_delta` events `delta.text`
+fn stream_delta(event: &Value) -> Option<&str> {
+    event.pointer("/choices/0/delta/content")
+        .or_else(|| event.pointer("/choices/0/text"))
+        .or_else(|| event.pointer("/delta/text"))
+        .and_then(|value| value.as_str())
+}
+
+// OpenAI sends usage once at the end; Claude splits it between `message_start` and `message_delta`;
+// Moonshot puts it in the last chunk's choice
+fn merge_stream_usage(usage: &mut Option<Value>, event: &Value) {
+    for source in [event.get("usage"), event.pointer("/message/usage"), event.pointer("/choices/0/usage")].into_iter().flatten() {
+        let Some(fields) = source.as_object() else {
+            continue;
+        };
+        let merged = usage.get_or_insert_with(|| json!({}));
+        for (key, value) in fields {
+            merged[key] = value.clone();
+        }
+    }
+}
+
+// How often `Rate` events are sent while streaming
+const RATE_INTERVAL: Duration = Duration::from_millis(500);
+
+const STREAM_HEAD_BYTES: usize = 4096;
+
+// Too short a window gives a meaningless rate
+fn tokens_per_second(tokens: u64, elapsed: Duration) -> Option<f64> {
+    let seconds = elapsed.as_secs_f64();
+    (tokens > 1 && seconds >= 0.05).then(|| tokens as f64 / seconds)
+}
+
+// Reads at most `limit` bytes of a body; None when there is more
+pub(crate) async fn read_limited(mut response: reqwest::Response, limit: u64) -> reqwest::Result<Option<Vec<u8>>> {
+    if response.content_length().is_some_and(|length| length > limit) {
+        return Ok(None);
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if (body.len() + chunk.len()) as u64 > limit {
+            return Ok(None);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(Some(body))
+}
+
+pub(crate) fn content_type(response: &reqwest::Response) -> Option<String> {
+    response.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+// Block pages, bot challenges and proxy error pages are HTML where an API reply would be JSON;
+// describes which one it looks like, with the page title when there is one
+pub(crate) fn html_page(content_type: Option<&str>, body: &str) -> Option<String> {
+    let start: String = body.trim_start().chars().take(64).collect::<String>().to_lowercase();
+    let is_html = content_type.is_some_and(|value| value.starts_with("text/html"))
+        || start.starts_with("<!doctype html")
+        || start.starts_with("<html");
+    if !is_html {
+        return None;
+    }
+    
+    let page = body.to_lowercase();
+    let kind = if ["cf-chl", "challenge-platform", "just a moment..."].iter().any(|marker| page.contains(marker)) {
+        "a Cloudflare challenge"
+    } else if ["bad gateway", "gateway timeout", "service unavailable", "cloudflare"].iter().any(|marker| page.contains(marker)) {
+        "a gateway error page"
+    } else if ["access denied", "blocked", "forbidden"].iter().any(|marker| page.contains(marker)) {
+        "a block page"
+    } else {
+        "an HTML page"
+    };
+    // Offsets come from the lowercased copy, so `get` guards against case mappings that change length
+    let start = page.find("<title>").map(|start| start + "<title>".len());
+    let title = start
+        .and_then(|start| page[start..].find("</title>").and_then(|end| body.get(start..start + end)))
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .map(|title| format!(" (\"{}\")", title.chars().take(80).collect::<String>()))
+        .unwrap_or_default();
+    Some(format!("{}{}", kind, title))
+}
+
+pub(crate) fn too_large(channel: &Channel, limit: u64) -> CCSwitchError {
+    CCSwitchError::Channel(format!("{} sent a reply over max_response_bytes ({} bytes); aborted", channel.name, limit))
+}
+
+// Every candidate was tried: report each channel that failed along the way
+fn exhausted(skipped: &[SkippedChannel], last: CCSwitchError) -> CCSwitchError {
+    let mut failures: Vec<ChannelFailure> = skipped
+        .iter()
+        .map(|s| ChannelFailure { channel: s.channel.clone(), reason: s.reason.clone() })
+        .collect();
+    if let CCSwitchError::AllChannelsFailed(remaining) = last {
+        failures.extend(remaining);
+    }
+    CCSwitchError::AllChannelsFailed(failures)
+}
+
+const REQUEST_ID_HEADERS: &[&str] = &["x-request-id", "request-id", "x-amzn-requestid", "x-ms-request-id", "cf-ray"];
+
+fn header_request_id(headers: &HeaderMap) -> Option<String> {
+    REQUEST_ID_HEADERS
+        .iter()
+        .find_map(|name| headers.get(*name)?.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+fn body_request_id(body: &str) -> Option<String> {
+    let body: Value = serde_json::from_str(body).ok()?;
+    ["/request_id", "/error/request_id", "/requestId", "/error/requestId"]
+        .iter()
+        .find_map(|pointer| body.pointer(pointer)?.as_str())
+        .map(|value| value.to_string())
+}
+
+// OpenAI reports prompt/completion tokens, Claude reports input/output tokens
+pub fn usage_tokens(usage: &Value) -> (Option<u64>, Option<u64>) {
+    let prompt = usage.get("prompt_tokens").or_else(|| usage.get("input_tokens")).and_then(|v| v.as_u64());
+    let completion = usage.get("completion_tokens").or_else(|| usage.get("output_tokens")).and_then(|v| v.as_u64());
+    (prompt, completion)
+}
+
+// Prompt tokens served from the provider's prompt cache, and all prompt tokens including them.
+// Anthropic reports cache reads and writes beside `input_tokens`; OpenAI counts them inside `prompt_tokens`.
+pub fn cache_tokens(usage: &Value) -> Option<(u64, u64)> {
+    let field = |name: &str| usage.get(name).and_then(|v| v.as_u64());
+    
+    if let Some(read) = field("cache_read_input_tokens") {
+        let total = field("input_tokens").unwrap_or(0) + read + field("cache_creation_input_tokens").unwrap_or(0);
+        return Some((read, total));
+    }
+    
+    let cached = usage.pointer("/prompt_tokens_details/cached_tokens").and_then(|v| v.as_u64())?;
+    Some((cached, field("prompt_tokens").unwrap_or(cached)))
+}
+
+// System messages become text blocks marked cacheable; the rest are sent unchanged
+fn cacheable_messages(messages: &[Message]) -> Vec<Value> {
+    messages
+        .iter()
+        .map(|message| match message.role.as_str() {
+            "system" => json!({
+                "role": "system",
+                "content": [{
+                    "type": "text",
+                    "text": message.content,
+                    "cache_control": { "type": "ephemeral" }
+                }]
+            }),
+            _ => json!(message),
+        })
+        .collect()
+}
+
+fn rejection_reason(retry: &ResponseRetryConfig, response: &APIResponse) -> Option<String> {
+    if retry.retry_on_empty {
+        if response.content.trim().is_empty() {
+            return Some("empty response".to_string());
+        }
+        
+        let (_, completion_tokens) = response.usage.as_ref().map(usage_tokens).unwrap_or((None, None));
+        if completion_tokens == Some(0) {
+            return Some("zero-token completion".to_string());
+        }
+    }
+    
+    let content = response.content.to_lowercase();
+    retry.refusal_patterns
+        .iter()
+        .find(|pattern| content.contains(&pattern.to_lowercase()))
+        .map(|pattern| format!("refusal matched '{}'", pattern))
+}
+
+fn expand_system_prefix(template: &str, channel: &Channel, model: &str) -> String {
+    template
+        .replace("{channel}", &channel.name)
+        .replace("{model}", model)
+        .replace("{date}", &chrono::Local::now().format("%Y-%m-%d").to_string())
+}
+
+fn with_language_instruction(messages: &[Message], language: Language) -> Vec<Message> {
+    let mut messages = messages.to_vec();
+    if let Some(last) = messages.iter_mut().rev().find(|message| message.role == "user") {
+        last.content = format!("{}\n\n{}", last.content, language.instruction());
+    }
+    messages
 }
 
 impl APIClient {
     pub fn new() -> Result<Self> {
         let channel_manager = ChannelManager::new()?;
-        let client = Client::builder()
-            .timeout(Duration::from_secs(60))
-            .build()
-            .map_err(CCSwitchError::Network)?;
+        let clients = HttpClients::with_resolver(Duration::from_secs(60), &channel_manager.config, channel_manager.dns_cache())?;
             
         Ok(Self {
             channel_manager,
-            client,
+            clients,
+            event_sink: None,
         })
     }
     
-    pub async fn make_request(&mut self, prompt: &str, options: RequestOptions) -> Result<APIResponse> {
-        let model = options.model
-            .as_deref()
-            .or(self.channel_manager.config.default_model.as_deref())
-            .unwrap_or("gpt-3.5-turbo");
+    // Drops the cached DNS answers shared by every channel's HTTP client; false if DNS caching is off
+    pub fn clear_dns_cache(&self) -> bool {
+        self.clients.clear_dns_cache()
+    }
+    
+    pub fn set_event_sink(&mut self, sink: EventSink) {
+        self.event_sink = Some(sink);
+    }
+    
+    fn emit(&self, options: &RequestOptions, event: RequestEvent) {
+        if let Some(events) = &options.events {
+            let _ = events.send(event);
+        } else if let Some(sink) = &self.event_sink {
+            sink(&event);
+        }
+    }
+    
+    pub async fn make_request(&self, prompt: &str, options: RequestOptions) -> Result<APIResponse> {
+        let mut messages = Vec::new();
+        if let Some(system) = &options.system {
+            messages.push(Message::system(system));
+        }
+        messages.push(Message::user(prompt));
+        
+        self.make_chat_request(&messages, options).await
+    }
+    
+    pub async fn make_chat_request(&self, messages: &[Message], options: RequestOptions) -> Result<APIResponse> {
+        let model = self.resolve_model(options.model.as_deref());
+        let model = model.as_str();
             
         info!("Making request for model: {}", model);
         
-        // Find an available channel for the model
-        let channel = self.channel_manager.find_available_channel(model).await?;
+        let with_instruction: Vec<Message>;
+        let messages = match options.language {
+            Some(language) => {
+                with_instruction = with_language_instruction(messages, language);
+                &with_instruction[..]
+            }
+            None => messages,
+        };
         
-        // Prepare the request payload
-        let payload = json!({
-            "model": model,
-            "messages": [
-                {
-                    "role": "user",
-                    "content": prompt
-                }
-            ],
-            "max_tokens": options.max_tokens,
-            "temperature": options.temperature,
-            "stream": options.stream
+        // Identical requests within the TTL are answered from disk without contacting a channel
+        let cache_key = self.channel_manager.config.cache_ttl_seconds
+            .filter(|_| !options.no_cache)
+            .map(|_| cache::key(model, messages, &options, &self.channel_manager.config));
+        let cached = cache_key.as_deref().and_then(|key| cache::lookup(key, self.channel_manager.config.cache_ttl_seconds.unwrap_or(0)));
+        let from_cache = cached.is_some();
+        
+        let mut response = match cached {
+            Some(response) => response,
+            None => {
+                let config = &self.channel_manager.config;
+                budget::check_global(config).await?;
+                if let Some(user) = &options.user {
+                    budget::check_user(config, user).await?;
+                }
+                
+                let start = Instant::now();
+                let mut result = self.route_request(model, messages, &options).await;
+                if let Ok(response) = &mut result {
+                    response.cost = response.usage.as_ref()
+                        .and_then(|usage| pricing::cost(config, config.get_channel(&response.channel_used), &response.model, usage));
+                }
+                // Budgets are computed from the recorded usage, so they need it even with stats off
+                if config.usage_stats || config.has_budgets() {
+                    self.record_usage(model, &options, &result, start.elapsed()).await;
+                }
+                result?
+            }
+        };
+        if let (Some(key), false) = (&cache_key, from_cache) {
+            cache::store(key, &response);
+        }
+        // A cached reply carries the request it was first made for
+        response.selection_seed = options.selection_seed;
+        response.labels = options.labels.clone();
+        
+        // Streamed replies already emitted their deltas as they arrived
+        if !options.stream || from_cache {
+            self.emit(&options, RequestEvent::Delta { content: response.content.clone() });
+        }
+        if let Some(usage) = &response.usage {
+            self.emit(&options, RequestEvent::Usage { usage: usage.clone() });
+        }
+        self.emit(&options, RequestEvent::Done {
+            channel: response.channel_used.clone(),
+            model: response.model.clone(),
         });
         
-        // Make the request
-        let response = self.send_request(channel, &payload).await?;
+        Ok(response)
+    }
+    
+    async fn record_usage(&self, model: &str, options: &RequestOptions, result: &Result<APIResponse>, elapsed: Duration) {
+        let config = &self.channel_manager.config;
+        let user = options.user.as_deref();
+        let response = result.as_ref().ok();
+        let model = response.map_or(model, |response| response.model.as_str());
+        let (prompt_tokens, completion_tokens) = response
+            .and_then(|response| response.usage.as_ref())
+            .map(usage_tokens)
+            .unwrap_or((None, None));
+        let cost = response.and_then(|response| response.cost);
+        
+        stats::record(UsageRecord {
+            channel: response.map(|response| response.channel_used.clone()),
+            model: model.to_string(),
+            prompt_tokens,
+            completion_tokens,
+            latency_ms: elapsed.as_millis() as u64,
+            success: response.is_some(),
+            error_code: result.as_ref().err().map(|e| e.code().to_string()),
+            cost,
+            tokens_per_second: response.and_then(|response| response.tokens_per_second),
+            user: user.map(str::to_string),
+            labels: options.labels.clone(),
+        }).await;
         
-        // Parse the response
-        self.parse_response(response, channel.name.clone(), model.to_string()).await
+        if let (Some(response), Some(cost)) = (response, cost) {
+            budget::notify_crossed(config, &response.channel_used, user, cost).await;
+        }
     }
     
-    async fn send_request(&self, channel: &Channel, payload: &Value) -> Result<reqwest::Response> {
-        info!("Sending request to channel: {}", channel.name);
+    // Picks channels for the request, failing over and retrying rejected replies as configured
+    async fn route_request(&self, model: &str, messages: &[Message], options: &RequestOptions) -> Result<APIResponse> {
+        let retry = &self.channel_manager.config.response_retry;
+        let retry_enabled = retry.enabled && options.channel.is_none();
+        
+        // One key per logical request so a retried send is recognisable as a duplicate upstream
+        let mut idempotency_key = Uuid::new_v4().to_string();
+        let mut skipped: Vec<SkippedChannel> = Vec::new();
+        let mut rejected: Option<APIResponse> = None;
+        let mut rejections = 0;
+        let language_retry = &self.channel_manager.config.language_retry;
+        let mut language_retries = 0;
+        // Set to ask the same channel again after a reply in the wrong language
+        let mut same_channel: Option<String> = None;
+        
+        // Channel switches after a failed request; a pinned channel never fails over
+        let max_failovers = if options.channel.is_none() { self.channel_manager.config.retry_attempts } else { 0 };
+        let mut failovers = 0;
+        
+        // Channels that used up their own budget sit out until the period rolls over
+        let over_budget = budget::exhausted_channels(&self.channel_manager.config).await;
+        if let Some((_, reason)) = over_budget.iter().find(|(name, _)| Some(name) == options.channel.as_ref()) {
+            return Err(CCSwitchError::BudgetExceeded(reason.clone()));
+        }
         
-        let mut request = self.client.post(&channel.url);
+        let hedge = options.hedge || self.channel_manager.config.hedge;
+        // A streamed reply that may still be rejected is held back, so a retry never shows two replies
+        let hold_back = options.stream && (retry_enabled || (options.language.is_some() && language_retry.max_retries > 0));
+        let estimated_tokens: u64 = messages.iter().map(|message| tokens::estimate_tokens(&message.content)).sum();
+        
+        let mut response = loop {
+            let excluded: Vec<&str> = skipped.iter()
+                .map(|s| s.channel.as_str())
+                .chain(over_budget.iter().map(|(name, _)| name.as_str()))
+                .collect();
+            
+            // Find an available channel for the model
+            let pinned = options.channel.clone().or(same_channel.take());
+            let selection = match &pinned {
+                Some(name) => self.channel_manager.config
+                    .get_channel(name)
+                    .ok_or_else(|| CCSwitchError::ChannelNotFound(name.clone())),
+                None => self.channel_manager.find_available_channel(model, &excluded, &options.channel_filter(&self.channel_manager.config)).await,
+            };
+            
+            let channel = match selection {
+                Ok(channel) => channel,
+                // Nothing left to retry on: surface the rejected response rather than an error
+                Err(_) if rejected.is_some() => {
+                    let previous = rejected.take().unwrap();
+                    skipped.retain(|s| s.channel != previous.channel_used);
+                    if options.stream {
+                        self.emit(options, RequestEvent::Delta { content: previous.content.clone() });
+                    }
+                    break previous;
+                }
+                Err(e) if failovers > 0 => return Err(exhausted(&skipped, e)),
+                // Only budget-capped channels serve this model
+                Err(CCSwitchError::NoAvailableChannels(_)) if !over_budget.is_empty() => {
+                    let serving: Vec<String> = self.channel_manager.config
+                        .get_channels_for_model(model)
+                        .iter()
+                        .filter_map(|channel| over_budget.iter().find(|(name, _)| *name == channel.name))
+                        .map(|(name, reason)| format!("{}: {}", name, reason))
+                        .collect();
+                    if serving.is_empty() {
+                        return Err(CCSwitchError::NoAvailableChannels(model.to_string()));
+                    }
+                    return Err(CCSwitchError::BudgetExceeded(serving.join("; ")));
+                }
+                Err(e) => return Err(e),
+            };
+            
+            self.emit(options, RequestEvent::Selection {
+                channel: channel.name.clone(),
+                model: model.to_string(),
+            });
+            
+            // Streamed deltas from two channels would interleave, so only whole replies are hedged
+            let runner_up = match pinned {
+                None if hedge && !options.stream => {
+                    let excluded: Vec<&str> = excluded.iter().copied().chain([channel.name.as_str()]).collect();
+                    self.channel_manager.find_available_channel(model, &excluded, &options.channel_filter(&self.channel_manager.config)).await.ok()
+                }
+                _ => None,
+            };
+            
+            let (held_options, held) = if hold_back {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                (Some(RequestOptions { events: Some(sender), ..options.clone() }), Some(receiver))
+            } else {
+                (None, None)
+            };
+            let attempt_options = held_options.as_ref().unwrap_or(options);
+            
+            let started = Instant::now();
+            let attempt = |channel| self.attempt(channel, model, messages, attempt_options, &idempotency_key, estimated_tokens);
+            let (channel, response) = match runner_up {
+                Some(runner_up) => {
+                    let (winner, response, lost) = self.race([channel, runner_up], attempt).await;
+                    skipped.extend(lost);
+                    (winner, response)
+                }
+                None => (channel, attempt(channel).await),
+            };
+            let response = match response {
+                Ok(response) => response,
+                Err(e) if e.is_channel_specific() && failovers < max_failovers => {
+                    warn!("Channel {} failed, trying the next one: {}", channel.name, e);
+                    self.channel_manager.record_failure(&channel.name);
+                    failovers += 1;
+                    self.emit(options, RequestEvent::Retry {
+                        channel: channel.name.clone(),
+                        attempt: failovers,
+                        error: e.to_string(),
+                    });
+                    skipped.push(SkippedChannel {
+                        channel: channel.name.clone(),
+                        reason: e.to_string(),
+                    });
+                    continue;
+                }
+                Err(e) => {
+                    if e.is_channel_specific() {
+                        self.channel_manager.record_failure(&channel.name);
+                    }
+                    return Err(e);
+                }
+            };
+            self.channel_manager.mark_healthy(&channel.name);
+            self.channel_manager.record_latency(&channel.name, started.elapsed());
+            
+            if let Some(language) = options.language.filter(|language| !language.matches(&response.content)) {
+                if language_retries < language_retry.max_retries {
+                    let reason = format!("reply is not in {}", language.name());
+                    warn!("Retrying after channel {} replied in the wrong language", channel.name);
+                    language_retries += 1;
+                    self.emit(options, RequestEvent::Retry {
+                        channel: channel.name.clone(),
+                        attempt: language_retries,
+                        error: reason.clone(),
+                    });
+                    if language_retry.switch_channel && options.channel.is_none() {
+                        skipped.push(SkippedChannel {
+                            channel: channel.name.clone(),
+                            reason,
+                        });
+                        rejected = Some(response);
+                    } else {
+                        // A fresh key, or the provider may hand back the same reply
+                        same_channel = Some(channel.name.clone());
+                        idempotency_key = Uuid::new_v4().to_string();
+                    }
+                    continue;
+                }
+                // Out of retries: the reply is still better than nothing
+                warn!("Channel {} replied in the wrong language; no retries left", channel.name);
+                self.release(options, held);
+                break response;
+            }
+            
+            match rejection_reason(retry, &response) {
+                Some(reason) if retry_enabled && rejections < retry.max_retries => {
+                    warn!("Rejecting response from channel {}: {}", channel.name, reason);
+                    rejections += 1;
+                    self.emit(options, RequestEvent::Retry {
+                        channel: channel.name.clone(),
+                        attempt: rejections,
+                        error: reason.clone(),
+                    });
+                    skipped.push(SkippedChannel {
+                        channel: channel.name.clone(),
+                        reason,
+                    });
+                    rejected = Some(response);
+                }
+                _ => {
+                    self.release(options, held);
+                    break response;
+                }
+            }
+        };
+        
+        response.skipped_channels = skipped;
+        Ok(response)
+    }
+    
+    // Passes on the events of a held-back attempt once its reply is accepted
+    fn release(&self, options: &RequestOptions, held: Option<UnboundedReceiver<RequestEvent>>) {
+        let Some(mut held) = held else {
+            return;
+        };
+        while let Ok(event) = held.try_recv() {
+            // A live rate means nothing once the reply has finished
+            if !matches!(event, RequestEvent::Rate { .. }) {
+                self.emit(options, event);
+            }
+        }
+    }
+    
+    // One send within the channel's concurrency and rate limits
+    async fn attempt(&self, channel: &Channel, model: &str, messages: &[Message], options: &RequestOptions, idempotency_key: &str, estimated_tokens: u64) -> Result<APIResponse> {
+        let _slot = self.channel_manager.acquire_slot(channel).await;
+        self.channel_manager.acquire_rate_limit(channel, estimated_tokens).await;
+        let response = self.request_on_channel(channel, model, messages, options, idempotency_key).await;
+        // The estimate only covered the prompt; settle up with what the provider reported
+        if let Some(usage) = response.as_ref().ok().and_then(|response| response.usage.as_ref()) {
+            let (prompt, completion) = usage_tokens(usage);
+            let used = prompt.unwrap_or(estimated_tokens) + completion.unwrap_or(0);
+            self.channel_manager.adjust_rate_limit(channel, used as i64 - estimated_tokens as i64);
+        }
+        response
+    }
+    
+    // Sends to both channels at once; the first success wins and dropping the other future cancels its request.
+    // A channel that lost by failing is returned as skipped; when both fail the preferred one's error is kept
+    // so failover carries on as usual
+    async fn race<'c, F, Fut>(&self, [first, second]: [&'c Channel; 2], attempt: F) -> (&'c Channel, Result<APIResponse>, Option<SkippedChannel>)
+    where
+        F: Fn(&'c Channel) -> Fut,
+        Fut: Future<Output = Result<APIResponse>>,
+    {
+        info!("Hedging the request across channels {} and {}", first.name, second.name);
+        let (failed, error, other, result) = match future::select(Box::pin(attempt(first)), Box::pin(attempt(second))).await {
+            Either::Left((Ok(response), _)) => return (first, Ok(response), None),
+            Either::Right((Ok(response), _)) => return (second, Ok(response), None),
+            Either::Left((Err(e), rest)) => (first, e, second, rest.await),
+            Either::Right((Err(e), rest)) => (second, e, first, rest.await),
+        };
+        
+        let (kept, lost) = match result {
+            Ok(response) => ((other, Ok(response)), (failed, error)),
+            Err(other_error) if failed.name == first.name => ((failed, Err(error)), (other, other_error)),
+            Err(other_error) => ((other, Err(other_error)), (failed, error)),
+        };
+        let (lost_channel, lost_error) = lost;
+        warn!("Hedged request on channel {} failed: {}", lost_channel.name, lost_error);
+        if lost_error.is_channel_specific() {
+            self.channel_manager.record_failure(&lost_channel.name);
+        }
+        let skipped = SkippedChannel {
+            channel: lost_channel.name.clone(),
+            reason: lost_error.to_string(),
+        };
+        (kept.0, kept.1, Some(skipped))
+    }
+    
+    async fn request_on_channel(&self, channel: &Channel, model: &str, messages: &[Message], options: &RequestOptions, idempotency_key: &str) -> Result<APIResponse> {
+        let model = channel.upstream_model(model);
+        
+        // Chat-style requests sent to a completion-only channel go through a prompt template
+        let translated = options.interaction == EndpointKind::Chat
+            && (channel.endpoint_kind == EndpointKind::Completion && channel.api_format.is_openai_compatible()
+                || channel.api_format == ApiFormat::Huggingface);
+        let prompt = messages.last().map(|message| message.content.as_str()).unwrap_or_default();
+        
+        // Chat requests without a system message of their own get the channel's or the config's default
+        let default_system = channel.system_prompt.as_ref()
+            .or(self.channel_manager.config.default_system_prompt.as_ref())
+            .filter(|_| options.interaction == EndpointKind::Chat && !messages.iter().any(|message| message.role == "system"));
+        let prefix = channel.system_prefix.as_deref()
+            .filter(|_| options.interaction == EndpointKind::Chat)
+            .map(|template| expand_system_prefix(template, channel, model));
+        let with_system: Vec<Message>;
+        let messages = if default_system.is_some() || prefix.is_some() {
+            let mut adjusted: Vec<Message> = default_system.map(|system| Message::system(system))
+                .into_iter()
+                .chain(messages.iter().cloned())
+                .collect();
+            if let Some(prefix) = prefix {
+                match adjusted.iter_mut().find(|message| message.role == "system") {
+                    Some(system) => system.content = format!("{}\n\n{}", prefix, system.content),
+                    None => adjusted.insert(0, Message::system(&prefix)),
+                }
+            }
+            with_system = adjusted;
+            &with_system[..]
+        } else {
+            messages
+        };
+        
+        // Prepare the request payload
+        let mut payload = match (channel.api_format, channel.endpoint_kind) {
+            (ApiFormat::Anthropic, _) => anthropic::payload(model, messages, options, channel.prompt_caching),
+            (ApiFormat::Gemini, _) => gemini::payload(messages, options),
+            (ApiFormat::Ollama, _) => ollama::payload(model, messages, options),
+            (ApiFormat::Bedrock, _) => bedrock::payload(model, messages, options),
+            (ApiFormat::Huggingface, _) => huggingface::payload(messages, prompt, options, translated),
+            (_, EndpointKind::Chat) if channel.prompt_caching => json!({
+                "model": model,
+                "messages": cacheable_messages(messages),
+                "max_tokens": options.max_tokens,
+                "temperature": options.temperature,
+                "stream": options.stream
+            }),
+            (_, EndpointKind::Chat) => json!({
+                "model": model,
+                "messages": messages,
+                "max_tokens": options.max_tokens,
+                "temperature": options.temperature,
+                "stream": options.stream
+            }),
+            (_, EndpointKind::Completion) if translated => json!({
+                "model": model,
+                "prompt": translate::messages_to_prompt(messages),
+                "max_tokens": options.max_tokens,
+                "temperature": options.temperature,
+                "stream": options.stream,
+                "stop": [translate::COMPLETION_STOP]
+            }),
+            (_, EndpointKind::Completion) => json!({
+                "model": model,
+                "prompt": prompt,
+                "max_tokens": options.max_tokens,
+                "temperature": options.temperature,
+                "stream": options.stream
+            }),
+        };
+        
+        // The Messages API has no logprobs
+        if let Some(top) = options.logprobs.filter(|_| channel.api_format.is_openai_compatible()) {
+            match channel.endpoint_kind {
+                EndpointKind::Chat => {
+                    payload["logprobs"] = json!(true);
+                    payload["top_logprobs"] = json!(top);
+                }
+                EndpointKind::Completion => payload["logprobs"] = json!(top),
+            }
+        }
+        
+        apply_channel_params(&mut payload, channel);
+        for name in &channel.sampling_params {
+            if let Some(value) = options.params.get(name) {
+                payload[name] = value.clone();
+            }
+        }
+        if channel.api_format == ApiFormat::Openrouter {
+            openrouter::apply(&mut payload, channel);
+        }
+        
+        // Without this OpenAI omits usage from streamed replies
+        if options.stream && channel.endpoint_kind == EndpointKind::Chat && channel.api_format.is_openai_compatible() {
+            payload["stream_options"] = json!({ "include_usage": true });
+        }
+        
+        // Gemini puts the model and the streaming mode in the URL
+        let url = match channel.api_format {
+            ApiFormat::Gemini => gemini::endpoint(&channel.url, model, options.stream),
+            ApiFormat::Ollama => ollama::chat_url(&channel.url),
+            ApiFormat::Azure => azure::endpoint(channel, model),
+            ApiFormat::Bedrock => bedrock::invoke_url(channel, model),
+            ApiFormat::Huggingface => huggingface::endpoint(&channel.url, model),
+            _ => channel.url.clone(),
+        };
+        
+        let max_attempts = self.channel_manager.config.retry_attempts.max(1);
+        let mut attempt = 1;
+        
+        let mut response = loop {
+            // Make the request and parse the response
+            let result = match self.send_request(channel, &url, &payload, idempotency_key).await {
+                // Ollama streams newline-delimited JSON rather than SSE
+                Ok(response) if options.stream && (is_event_stream(&response) || channel.api_format == ApiFormat::Ollama) => {
+                    self.read_stream(response, channel, model.to_string(), options).await
+                }
+                // Servers that ignore `stream` answer in one piece; deliver it as a single delta
+                Ok(response) if options.stream => {
+                    let response = self.parse_response(response, channel, model.to_string()).await;
+                    if let Ok(response) = &response {
+                        self.emit(options, RequestEvent::Delta { content: response.content.clone() });
+                    }
+                    response
+                }
+                Ok(response) => self.parse_response(response, channel, model.to_string()).await,
+                Err(e) => Err(e),
+            };
+            
+            match result {
+                Err(CCSwitchError::Network(e)) if attempt < max_attempts => {
+                    warn!("Attempt {}/{} on channel {} failed, retrying: {}", attempt, max_attempts, channel.name, e);
+                    self.emit(options, RequestEvent::Retry {
+                        channel: channel.name.clone(),
+                        attempt,
+                        error: e.to_string(),
+                    });
+                    attempt += 1;
+                }
+                result => break result?,
+            }
+        };
+        
+        if translated {
+            response.content = translate::completion_to_reply(&response.content);
+        }
+        
+        // Some relays strip usage and TGI counts only generated tokens; estimate what is missing so token accounting still works
+        let (reported_prompt, reported_completion) = response.usage.as_ref().map_or((None, None), usage_tokens);
+        if reported_prompt.is_none() || reported_completion.is_none() {
+            let prompt_tokens = reported_prompt.unwrap_or_else(|| {
+                let prompt_text: String = messages.iter().map(|m| m.content.as_str()).collect::<Vec<_>>().join("\n");
+                tokens::estimate_tokens(&prompt_text)
+            });
+            let completion_tokens = reported_completion.unwrap_or_else(|| tokens::estimate_tokens(&response.content));
+            let estimate = json!({
+                "prompt_tokens": prompt_tokens,
+                "completion_tokens": completion_tokens,
+                "total_tokens": prompt_tokens + completion_tokens,
+                "estimated": true
+            });
+            let usage = response.usage.get_or_insert_with(|| json!({}));
+            if let (Some(usage), Value::Object(estimate)) = (usage.as_object_mut(), estimate) {
+                usage.extend(estimate);
+            }
+        }
+        
+        if !channel.post_process.is_empty() {
+            response.content = postprocess::apply(channel, response.content).await?;
+        }
+        
+        Ok(response)
+    }
+    
+    pub fn resolve_model(&self, requested: Option<&str>) -> String {
+        requested
+            .or(self.channel_manager.config.default_model.as_deref())
+            .unwrap_or("gpt-3.5-turbo")
+            .to_string()
+    }
+    
+    // Sends a hand-built payload straight to one channel, without routing, caching or usage stats
+    pub async fn send_raw(&self, channel: &Channel, model: &str, payload: &Value) -> Result<reqwest::Response> {
+        let url = match channel.api_format {
+            ApiFormat::Azure => azure::endpoint(channel, model),
+            _ => channel.url.clone(),
+        };
+        let mut payload = payload.clone();
+        apply_channel_params(&mut payload, channel);
+        self.send_request(channel, &url, &payload, &Uuid::new_v4().to_string()).await
+    }
+    
+    async fn send_request(&self, channel: &Channel, url: &str, payload: &Value, idempotency_key: &str) -> Result<reqwest::Response> {
+        info!("Sending request to channel: {}", channel.name);
         
         // Add authentication if available
-        if let Some(api_key) = &channel.api_key {
-            request = request.header("Authorization", format!("Bearer {}", api_key));
+        let mut request = self.channel_manager.authorize(self.clients.for_channel(channel).post(url), channel).await?;
+        
+        if let Some(header) = &channel.idempotency_header {
+            request = request.header(header.as_str(), idempotency_key);
+        }
+        if let Some(timeout) = self.channel_manager.first_request_timeout(channel) {
+            request = request.timeout(timeout);
         }
         
         // Send the request
-        request = request
-            .header("Content-Type", "application/json")
-            .json(payload);
+        request = match channel.api_format {
+            ApiFormat::Bedrock => bedrock::json_body(request, channel, url, payload)?,
+            _ => signing::json_body(request, channel, payload),
+        };
             
         let response = request.send().await
             .map_err(|e| {
                 error!("Request failed for channel {}: {}", channel.name, e);
                 CCSwitchError::Network(e)
             })?;
+        self.channel_manager.record_answer(channel);
             
         if !response.status().is_success() {
             let status = response.status();
-            let error_text = response.text().await.unwrap_or_default();
+            let request_id = header_request_id(response.headers());
+            let content_type = content_type(&response);
+            let limit = self.channel_manager.config.max_response_bytes;
+            let mut error_text = match read_limited(response, limit).await {
+                Ok(Some(body)) => String::from_utf8_lossy(&body).into_owned(),
+                Ok(None) => format!("(error body over {} bytes not read)", limit),
+                Err(_) => String::new(),
+            };
             error!("API request failed with status {}: {}", status, error_text);
-            return Err(CCSwitchError::Channel(format!("API request failed: {} - {}", status, error_text)));
+            
+            // Not the provider's answer but something in front of it; another channel may get through
+            if let Some(page) = html_page(content_type.as_deref(), &error_text) {
+                return Err(CCSwitchError::Channel(format!("{} returned {} with status {} instead of an API response", channel.name, page, status)));
+            }
+            
+            // Read before the request ID is appended and the body stops being JSON
+            let diagnosis = upstream::diagnose_for(channel.api_format, status, &error_text);
+            let provider_message = upstream::message_for(channel.api_format, &error_text);
+            
+            // Providers ask for this ID when a failure is reported to them
+            let request_id = request_id.or_else(|| body_request_id(&error_text));
+            if let Some(request_id) = &request_id {
+                error_text.push_str(&format!(" (request ID: {})", request_id));
+            }
+            
+            // Relays answer 403 for an empty balance too, so what the payload says wins over the status
+            return Err(match diagnosis {
+                Some(Diagnosis::Provider(kind)) if status != reqwest::StatusCode::UNAUTHORIZED => {
+                    let mut message = provider_message.unwrap_or_else(|| status.to_string());
+                    if let Some(request_id) = &request_id {
+                        message.push_str(&format!(" (request ID: {})", request_id));
+                    }
+                    CCSwitchError::Provider { channel: channel.name.clone(), kind, status, message }
+                }
+                _ if matches!(diagnosis, Some(Diagnosis::Auth))
+                    || status == reqwest::StatusCode::UNAUTHORIZED
+                    || status == reqwest::StatusCode::FORBIDDEN => {
+                    CCSwitchError::Auth(format!("{} rejected the credentials: {} - {}", channel.name, status, error_text))
+                }
+                _ => CCSwitchError::Upstream { status, message: error_text },
+            });
         }
         
         Ok(response)
     }
     
-    async fn parse_response(&self, response: reqwest::Response, channel_name: String, model: String) -> Result<APIResponse> {
-        let response_text = response.text().await
-            .map_err(CCSwitchError::Network)?;
+    async fn parse_response(&self, response: reqwest::Response, channel: &Channel, model: String) -> Result<APIResponse> {
+        let upstream_headers = self.capture_headers(response.headers());
+        let content_type = content_type(&response);
+        let limit = self.channel_manager.config.max_response_bytes;
+        let response_text = match read_limited(response, limit).await.map_err(CCSwitchError::Network)? {
+            Some(body) => String::from_utf8_lossy(&body).into_owned(),
+            None => return Err(too_large(channel, limit)),
+        };
+        if let Some(page) = html_page(content_type.as_deref(), &response_text) {
+            return Err(CCSwitchError::Channel(format!("{} returned {} instead of an API response", channel.name, page)));
+        }
             
         let json_response: Value = serde_json::from_str(&response_text)
             .map_err(|e| CCSwitchError::Channel(format!("Failed to parse response: {}", e)))?;
+        
+        // OpenRouter answers 200 when a provider fails after the request was accepted
+        if channel.api_format == ApiFormat::Openrouter {
+            if let Some(error) = openrouter::body_error(channel, &json_response) {
+                return Err(error);
+            }
+        }
+            
+        let content = match channel.api_format {
+            ApiFormat::Anthropic => anthropic::content(&json_response)
+                .ok_or_else(|| CCSwitchError::Channel("Response has no content blocks".to_string()))?,
+            ApiFormat::Gemini => gemini::content(&json_response)
+                .ok_or_else(|| CCSwitchError::Channel("Response has no candidates".to_string()))?,
+            ApiFormat::Ollama => ollama::content(&json_response)
+                .ok_or_else(|| CCSwitchError::Channel("Response has no message".to_string()))?,
+            ApiFormat::Bedrock => bedrock::content(&model, &json_response)
+                .ok_or_else(|| CCSwitchError::Channel("Could not extract content from response".to_string()))?,
+            ApiFormat::Huggingface => huggingface::content(&json_response)
+                .ok_or_else(|| CCSwitchError::Channel("Response has no generated_text".to_string()))?,
+            // Extract content from different response formats
+            ApiFormat::Openai | ApiFormat::Azure | ApiFormat::Openrouter => self.extract_content(&json_response)?,
+        };
+        let usage = match channel.api_format {
+            ApiFormat::Gemini => json_response.get("usageMetadata").map(gemini::usage),
+            ApiFormat::Ollama => ollama::usage(&json_response),
+            ApiFormat::Bedrock => bedrock::usage(&model, &json_response),
+            ApiFormat::Huggingface => huggingface::usage(&json_response),
+            _ => json_response.get("usage").cloned(),
+        };
+        let logprobs = json_response
+            .pointer("/choices/0/logprobs")
+            .filter(|logprobs| !logprobs.is_null())
+            .cloned();
+        
+        Ok(APIResponse {
+            content,
+            channel_used: channel.name.clone(),
+            model,
+            usage,
+            skipped_channels: Vec::new(),
+            logprobs,
+            upstream_headers,
+            cached: false,
+            cost: None,
+            tokens_per_second: None,
+            consensus: None,
+            pipeline: Vec::new(),
+            selection_seed: None,
+            labels: BTreeMap::new(),
+        })
+    }
+    
+    // Reads an SSE reply, emitting each piece of text as it arrives
+    async fn read_stream(&self, mut response: reqwest::Response, channel: &Channel, model: String, options: &RequestOptions) -> Result<APIResponse> {
+        let upstream_headers = self.capture_headers(response.headers());
+        let content_type = content_type(&response);
+        let limit = self.channel_manager.config.max_response_bytes;
+        // A block page or challenge served with 200 is not worth scanning for events
+        if content_type.as_deref().is_some_and(|value| value.starts_with("text/html")) {
+            let body = match read_limited(response, limit).await.map_err(CCSwitchError::Network)? {
+                Some(body) => String::from_utf8_lossy(&body).into_owned(),
+                None => return Err(too_large(channel, limit)),
+            };
+            let page = html_page(content_type.as_deref(), &body).unwrap_or_else(|| "an HTML page".to_string());
+            return Err(CCSwitchError::Channel(format!("{} returned {} instead of an API response", channel.name, page)));
+        }
+        
+        let mut buffer: Vec<u8> = Vec::new();
+        // The start of the reply, to explain a stream that never produced an event
+        let mut head: Vec<u8> = Vec::new();
+        let mut events = 0usize;
+        let mut content = String::new();
+        let mut usage: Option<Value> = None;
+        let mut first_token_at: Option<Instant> = None;
+        let mut rate_emitted_at = Instant::now();
+        let mut received = 0u64;
+        
+        'read: loop {
+            let chunk = match response.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(e) if !content.is_empty() => {
+                    return Err(CCSwitchError::StreamInterrupted(format!("{} stopped mid-reply: {}", channel.name, e)));
+                }
+                Err(e) => return Err(CCSwitchError::Network(e)),
+            };
+            received += chunk.len() as u64;
+            if received > limit {
+                let error = too_large(channel, limit);
+                if content.is_empty() {
+                    return Err(error);
+                }
+                return Err(CCSwitchError::StreamInterrupted(error.to_string()));
+            }
+            buffer.extend_from_slice(&chunk);
+            if head.len() < STREAM_HEAD_BYTES {
+                head.extend_from_slice(&chunk[..chunk.len().min(STREAM_HEAD_BYTES - head.len())]);
+            }
             
-        // Extract content from different response formats
-        let content = self.extract_content(&json_response)?;
-        let usage = json_response.get("usage").cloned();
+            // Events may be split across chunks; only complete lines are parsed
+            while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                let data = match line.trim().strip_prefix("data:") {
+                    Some(data) => data.trim(),
+                    None if channel.api_format == ApiFormat::Ollama => line.trim(),
+                    None => continue,
+                };
+                if data == "[DONE]" {
+                    break 'read;
+                }
+                let Ok(event) = serde_json::from_str::<Value>(data) else {
+                    continue;
+                };
+                events += 1;
+                
+                if let Some(error) = event.get("error").filter(|error| !error.is_null()) {
+                    let error = match channel.api_format {
+                        ApiFormat::Openrouter => openrouter::body_error(channel, &event),
+                        _ => None,
+                    }
+                    .unwrap_or_else(|| CCSwitchError::Channel(format!("Stream reported an error: {}", error)));
+                    if content.is_empty() {
+                        return Err(error);
+                    }
+                    return Err(CCSwitchError::StreamInterrupted(error.to_string()));
+                }
+                
+                let delta = match channel.api_format {
+                    ApiFormat::Gemini => gemini::content(&event),
+                    ApiFormat::Ollama => ollama::content(&event),
+                    ApiFormat::Huggingface => huggingface::stream_delta(&event),
+                    _ => stream_delta(&event).map(str::to_string),
+                };
+                if let Some(delta) = delta.filter(|delta| !delta.is_empty()) {
+                    content.push_str(&delta);
+                    self.emit(options, RequestEvent::Delta { content: delta });
+                    
+                    let first_token_at = *first_token_at.get_or_insert_with(Instant::now);
+                    if rate_emitted_at.elapsed() >= RATE_INTERVAL {
+                        rate_emitted_at = Instant::now();
+                        if let Some(tokens_per_second) = tokens_per_second(tokens::estimate_tokens(&content), first_token_at.elapsed()) {
+                            self.emit(options, RequestEvent::Rate { tokens_per_second });
+                        }
+                    }
+                }
+                
+                match event.get("usageMetadata") {
+                    // Each Gemini chunk carries the running totals
+                    Some(metadata) if channel.api_format == ApiFormat::Gemini => usage = Some(gemini::usage(metadata)),
+                    _ if channel.api_format == ApiFormat::Ollama => usage = ollama::usage(&event).or(usage.take()),
+                    _ => merge_stream_usage(&mut usage, &event),
+                }
+            }
+        }
+        
+        if events == 0 {
+            let head = String::from_utf8_lossy(&head);
+            if let Some(page) = html_page(content_type.as_deref(), &head) {
+                return Err(CCSwitchError::Channel(format!("{} returned {} instead of an API response", channel.name, page)));
+            }
+            let detail = upstream::message_for(channel.api_format, &head)
+                .unwrap_or_else(|| head.split_whitespace().collect::<Vec<_>>().join(" ").chars().take(200).collect());
+            return Err(CCSwitchError::Channel(format!("{} ended the stream without sending any events: {}", channel.name, detail)));
+        }
+        
+        // Provider counts beat the estimate when the stream reported them
+        let completion_tokens = usage.as_ref()
+            .and_then(|usage| usage_tokens(usage).1)
+            .unwrap_or_else(|| tokens::estimate_tokens(&content));
         
         Ok(APIResponse {
+            tokens_per_second: first_token_at.and_then(|at| tokens_per_second(completion_tokens, at.elapsed())),
+            consensus: None,
+            pipeline: Vec::new(),
+            selection_seed: None,
+            labels: BTreeMap::new(),
             content,
-            channel_used: channel_name,
+            channel_used: channel.name.clone(),
             model,
             usage,
+            skipped_channels: Vec::new(),
+            logprobs: None,
+            upstream_headers,
+            cached: false,
+            cost: None,
         })
     }
     
+    fn capture_headers(&self, headers: &HeaderMap) -> BTreeMap<String, String> {
+        let patterns = &self.channel_manager.config.capture_headers;
+        headers
+            .iter()
+            .filter(|(name, _)| {
+                patterns.iter().any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => name.as_str().starts_with(&prefix.to_lowercase()),
+                    None => name.as_str() == pattern.to_lowercase(),
+                })
+            })
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect()
+    }
+    
     fn extract_content(&self, response: &Value) -> Result<String> {
         // Try OpenAI format first
         if let Some(choices) = response.get("choices").and_then(|c| c.as_array()) {
@@ -150,6 +1289,11 @@ impl APIClient {
                         return Ok(content.to_string());
                     }
                 }
+                
+                // Try text completion format
+                if let Some(text) = first_choice.get("text").and_then(|t| t.as_str()) {
+                    return Ok(text.to_string());
+                }
             }
         }
         
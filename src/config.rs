@@ -1,17 +1,764 @@
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 use crate::error::{CCSwitchError, Result};
+use crate::postprocess;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointKind {
+    /// Chat completions API (`messages` in, `choices[].message` out)
+    #[default]
+    Chat,
+    /// Legacy text completions API (`prompt` in, `choices[].text` out)
+    Completion,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiFormat {
+    /// OpenAI-compatible request and response bodies with Bearer auth
+    #[default]
+    Openai,
+    /// Anthropic Messages API (`system` field, `x-api-key` and `anthropic-version` headers)
+    Anthropic,
+    /// Google Gemini `generateContent` (`contents` in, `candidates[].content.parts` out)
+    Gemini,
+    /// Ollama native API (`/api/chat`, newline-delimited JSON streaming)
+    Ollama,
+    /// Azure OpenAI: OpenAI bodies sent to a deployment URL with an `api-key` header
+    Azure,
+    /// AWS Bedrock `invoke` with SigV4 signing (Anthropic and Meta Llama models)
+    Bedrock,
+    /// OpenRouter: OpenAI bodies plus attribution headers and provider routing preferences
+    Openrouter,
+    /// Hugging Face text generation: the serverless Inference API or a TGI server (`inputs` in, `generated_text` out)
+    Huggingface,
+}
+
+impl ApiFormat {
+    // Formats that take OpenAI request bodies and return OpenAI responses
+    pub fn is_openai_compatible(self) -> bool {
+        matches!(self, ApiFormat::Openai | ApiFormat::Azure | ApiFormat::Openrouter)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthCheck {
+    #[serde(default = "default_health_check_method")]
+    pub method: String,
+    // Resolved against the channel URL, so "/health" replaces the whole path
+    pub path: String,
+    // Any 2xx status is accepted when unset
+    #[serde(default)]
+    pub expected_status: Option<u16>,
+    #[serde(default)]
+    pub body_contains: Option<String>,
+}
+
+fn default_health_check_method() -> String {
+    "GET".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SigningAlgorithm {
+    #[default]
+    HmacSha256,
+    HmacSha512,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningConfig {
+    pub secret: String,
+    #[serde(default = "default_signature_header")]
+    pub header: String,
+    #[serde(default)]
+    pub algorithm: SigningAlgorithm,
+    // When set, the unix timestamp is sent in this header and signed as "<timestamp>.<body>"
+    #[serde(default)]
+    pub timestamp_header: Option<String>,
+}
+
+fn default_signature_header() -> String {
+    "X-Signature".to_string()
+}
+
+// Known provider features; `None` means the feature has not been declared either way
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Capabilities {
+    pub logprobs: Option<bool>,
+    pub streaming: Option<bool>,
+    pub tools: Option<bool>,
+    pub vision: Option<bool>,
+    pub json_mode: Option<bool>,
+    // Largest max_tokens the provider accepts
+    pub max_output_tokens: Option<u32>,
+    // Unix time of the last `probe`
+    pub probed_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HttpVersion {
+    #[default]
+    Auto,
+    /// Force HTTP/1.1, for relays that mishandle HTTP/2
+    Http1,
+    /// Speak HTTP/2 without negotiation (h2c)
+    Http2,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IpVersion {
+    /// Race addresses in the order the resolver returns them
+    #[default]
+    Auto,
+    /// Only connect over IPv4, e.g. for relays with broken AAAA records
+    Ipv4,
+    /// Only connect over IPv6
+    Ipv6,
+    /// Try IPv4 first and fall back to IPv6
+    PreferIpv4,
+    /// Try IPv6 first and fall back to IPv4
+    PreferIpv6,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingStrategyKind {
+    /// Lowest `priority` first
+    #[default]
+    Priority,
+    /// Rotate the first choice across channels on every request
+    RoundRobin,
+    /// Lowest recent latency first
+    Latency,
+    /// Cheapest price for the model first
+    Cost,
+    /// A random order for every request
+    Random,
+    /// Split first choices in proportion to each channel's `weight`
+    Weighted,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransportConfig {
+    pub http_version: HttpVersion,
+    pub http2_adaptive_window: bool,
+    pub tcp_keepalive_seconds: Option<u64>,
+    pub pool_idle_timeout_seconds: Option<u64>,
+    pub ip_version: IpVersion,
+    // Spread over the addresses tried, so an unreachable one gives way quickly instead of using up the request timeout
+    pub connect_timeout_ms: Option<u64>,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Channel {
     pub name: String,
     pub url: String,
     pub api_key: Option<String>,
-    pub model: Option<String>,
+    // Models the channel serves, as names, globs like `gpt-4*` or regexes like `/claude-3-.*/`; empty serves any
+    #[serde(default)]
+    pub models: Vec<String>,
     pub enabled: bool,
     pub priority: u32,
+    #[serde(default)]
+    pub endpoint_kind: EndpointKind,
+    #[serde(default)]
+    pub api_format: ApiFormat,
+    // Header carrying the per-request idempotency key; null for providers that reject unknown headers
+    #[serde(default = "default_idempotency_header")]
+    pub idempotency_header: Option<String>,
+    #[serde(default)]
+    pub health_check: Option<HealthCheck>,
+    // Non-2xx statuses that still count as healthy for the default probe; falls back to the global list
+    #[serde(default)]
+    pub health_accept_statuses: Option<Vec<u16>>,
+    #[serde(default)]
+    pub capabilities: Capabilities,
+    #[serde(default)]
+    pub signing: Option<SigningConfig>,
+    // Command that starts a local server when its connection is refused (e.g. `ollama serve`)
+    #[serde(default)]
+    pub start_command: Option<String>,
+    #[serde(default = "default_start_timeout_seconds")]
+    pub start_timeout_seconds: u64,
+    // Command whose stdout is used as the API key, so secrets never live in the config
+    #[serde(default)]
+    pub credential_helper: Option<String>,
+    // Set to false to bypass HTTP_PROXY/HTTPS_PROXY, e.g. for servers on the local network
+    #[serde(default = "default_use_proxy")]
+    pub use_proxy: bool,
+    #[serde(default)]
+    pub transport: TransportConfig,
+    // Free-form notes for people, e.g. who owns the key and when it expires
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    // Labels such as "cheap" or "eu" that `--tags` restricts routing to
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // Date (YYYY-MM-DD) the API key stops working, e.g. for trial keys
+    #[serde(default)]
+    pub key_expires_at: Option<String>,
+    // Send system prompts as Anthropic-style `cache_control` blocks so the provider can reuse them
+    #[serde(default)]
+    pub prompt_caching: bool,
+    // Serve only the models the server reports (Ollama `/api/tags`, otherwise `/v1/models`) when `models` is empty
+    #[serde(default)]
+    pub discover_models: bool,
+    // Allowed for the channel's first request from this process, while a cold server loads the model;
+    // later requests use `timeout_seconds`
+    #[serde(default)]
+    pub first_request_timeout_seconds: Option<u64>,
+    // Load limits read from a vLLM server's `/metrics` before routing to it
+    #[serde(default)]
+    pub vllm: VllmConfig,
+    // Have the Hugging Face Inference API hold requests while a cold model loads, instead of answering 503 at once
+    #[serde(default)]
+    pub wait_for_model: bool,
+    // Kept warm by `serve.keep_alive_seconds` like the cold-start channels above, e.g. a serverless relay
+    #[serde(default)]
+    pub keep_alive: bool,
+    // Tokens the model accepts, prompt and reply together; chat and session history is trimmed to fit
+    #[serde(default)]
+    pub context_window: Option<u64>,
+    // Azure OpenAI deployment name; defaults to the requested model
+    #[serde(default)]
+    pub deployment: Option<String>,
+    #[serde(default)]
+    pub api_version: Option<String>,
+    // SigV4 settings for Bedrock; unset fields fall back to the standard AWS environment variables
+    #[serde(default)]
+    pub aws: AwsConfig,
+    #[serde(default)]
+    pub openrouter: OpenRouterConfig,
+    // Once reached, the channel is skipped until the period rolls over
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    // Replaces `default_system_prompt` for requests routed to this channel
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    // Put in front of the system message of every chat request; `{channel}`, `{model}` and `{date}` are filled in
+    #[serde(default)]
+    pub system_prefix: Option<String>,
+    // Merged into every request body unless the request sets the field, for parameters a provider insists on
+    #[serde(default)]
+    pub extra_body: serde_json::Map<String, Value>,
+    // Higher temperatures are lowered to this, for providers with a narrower range than OpenAI's
+    #[serde(default)]
+    pub max_temperature: Option<f32>,
+    // Non-OpenAI request fields the server understands (vLLM's `top_k`, ...), passed through when a request sets them
+    #[serde(default)]
+    pub sampling_params: Vec<String>,
+    // Applied in order to every reply from the channel before it is printed, cached or saved
+    #[serde(default)]
+    pub post_process: Vec<PostProcessor>,
+    // Unix time until which the channel sits out after failing health checks repeatedly
+    #[serde(default)]
+    pub auto_disabled_until: Option<u64>,
+    // Requests sent at once through this process; more wait for a free slot
+    #[serde(default)]
+    pub max_in_flight: Option<u32>,
+    // Client-side limits kept below the provider's, so excess traffic moves on instead of drawing 429s
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+    #[serde(default)]
+    pub tokens_per_minute: Option<u64>,
+    // What this channel charges, keyed by model, when it differs from the global `pricing`
+    #[serde(default)]
+    pub pricing: HashMap<String, ModelPrice>,
+    // Share of traffic under the `weighted` routing strategy, relative to the other channels
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    // Requested model names and what this provider calls them, e.g. a dated snapshot or an Azure deployment
+    #[serde(default)]
+    pub model_map: HashMap<String, String>,
+}
+
+impl Channel {
+    pub fn serves(&self, model: &str) -> bool {
+        self.models.is_empty() || self.models.iter().any(|served| model_matches(served, model)) || self.model_map.contains_key(model)
+    }
+    
+    // Declared model names that are not patterns, e.g. for test requests
+    pub fn named_models(&self) -> impl Iterator<Item = &str> {
+        self.models.iter().map(String::as_str).filter(|served| model_pattern(served).is_none())
+    }
+    
+    // The name sent to the provider for a requested model
+    pub fn upstream_model<'a>(&'a self, model: &'a str) -> &'a str {
+        self.model_map.get(model).map_or(model, |mapped| mapped.as_str())
+    }
+    
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.tags.contains(tag))
+    }
+    
+    pub fn is_auto_disabled(&self) -> bool {
+        self.auto_disabled_until.is_some_and(|until| until > now_secs())
+    }
+    
+    // Enabled and not sitting out an automatic disable
+    pub fn is_active(&self) -> bool {
+        self.enabled && !self.is_auto_disabled()
+    }
+    
+    // Slow to answer after sitting idle, so worth a keep-alive probe
+    pub fn cold_starts(&self) -> bool {
+        self.keep_alive || self.wait_for_model || self.first_request_timeout_seconds.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AwsConfig {
+    pub region: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    pub session_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpenRouterConfig {
+    // Sent as `HTTP-Referer` and `X-Title`, which OpenRouter uses to attribute usage to an app
+    pub referer: Option<String>,
+    pub title: Option<String>,
+    // Passed through as the body's `provider` object, e.g. `{"order": ["Anthropic"], "allow_fallbacks": false}`
+    pub provider: Option<Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VllmConfig {
+    // Share of the KV cache (GPU memory for running requests) in use, 0.0-1.0, above which the channel is skipped
+    pub max_gpu_cache_usage: Option<f64>,
+    pub max_waiting_requests: Option<u64>,
+}
+
+impl VllmConfig {
+    pub fn is_set(&self) -> bool {
+        self.max_gpu_cache_usage.is_some() || self.max_waiting_requests.is_some()
+    }
+}
+
+pub fn default_start_timeout_seconds() -> u64 {
+    30
+}
+
+pub fn default_use_proxy() -> bool {
+    true
+}
+
+pub fn default_idempotency_header() -> Option<String> {
+    Some("Idempotency-Key".to_string())
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencyUnit {
+    #[default]
+    Ms,
+    S,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    pub latency_unit: LatencyUnit,
+    pub thousands_separator: String,
+    // Symbol written before costs, which the pricing table keeps in US dollars
+    pub currency: String,
+    // Units of `currency` per US dollar
+    pub rate: f64,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            latency_unit: LatencyUnit::Ms,
+            thousands_separator: ",".to_string(),
+            currency: "$".to_string(),
+            rate: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyExpiryConfig {
+    // Start warning this many days before a key expires
+    pub warn_days: u64,
+    // URL that receives a JSON POST listing expiring keys when channels are tested
+    pub webhook: Option<String>,
+}
+
+impl Default for KeyExpiryConfig {
+    fn default() -> Self {
+        Self {
+            warn_days: 14,
+            webhook: None,
+        }
+    }
+}
+
+// Spending caps in USD, computed from `pricing` and the recorded usage
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BudgetConfig {
+    // Since local midnight
+    pub daily: Option<f64>,
+    // Since the first of the month, local time
+    pub monthly: Option<f64>,
+}
+
+impl BudgetConfig {
+    pub fn is_set(&self) -> bool {
+        self.daily.is_some() || self.monthly.is_some()
+    }
+}
+
+// USD per million tokens
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServeConfig {
+    // Proxy users by name; once any exist, every request must carry one of their keys
+    pub users: BTreeMap<String, ServeUser>,
+    // URLs `x-ccswitch-callback` may point at or under, matched by scheme, host, port and path; while empty, any public
+    // http(s) address is accepted
+    pub callback_allowlist: Vec<String>,
+    // Ping channels that cold-start this often with a one-token request; unset sends no keep-alives
+    pub keep_alive_seconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServeUser {
+    // Sent by the client as `Authorization: Bearer <key>` or `x-api-key`
+    pub api_key: String,
+    // Caps on this user's own spending, on top of the global and channel budgets
+    #[serde(default)]
+    pub budget: BudgetConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailConfig {
+    // SMTP server; email notifications are off while unset
+    pub smtp_host: Option<String>,
+    // Defaults to 587 for STARTTLS, 465 for TLS and 25 without encryption
+    pub smtp_port: Option<u16>,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    // Falls back to the CCSWITCH_SMTP_PASSWORD environment variable
+    pub password: Option<String>,
+    pub from: Option<String>,
+    pub to: Vec<String>,
+    pub events: Vec<EmailEvent>,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            smtp_host: None,
+            smtp_port: None,
+            security: SmtpSecurity::Starttls,
+            username: None,
+            password: None,
+            from: None,
+            to: Vec::new(),
+            events: vec![EmailEvent::ChannelDown, EmailEvent::KeyExpiry, EmailEvent::JobFinished, EmailEvent::Budget],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpSecurity {
+    Starttls,
+    Tls,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailEvent {
+    // `test` found unavailable channels
+    ChannelDown,
+    KeyExpiry,
+    // A `submit` or scheduled job finished, failed or was interrupted
+    JobFinished,
+    // A request pushed spending past a daily or monthly cap
+    Budget,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DnsConfig {
+    // Resolve channel hostnames in-process and reuse answers until their TTL expires
+    pub cache: bool,
+    pub min_ttl_seconds: Option<u64>,
+    pub max_ttl_seconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    // "host:port" of a statsd daemon (UDP)
+    pub statsd: Option<String>,
+    // Base URL of a Prometheus Pushgateway
+    pub pushgateway: Option<String>,
+    pub prefix: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub system: Option<String>,
+}
+
+// One step of a pipeline; unset fields fall back to the request's own options and routing
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipelineStage {
+    pub channel: Option<String>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+// A cheap stage drafts the reply and a stronger one refines the draft
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub draft: PipelineStage,
+    pub refine: PipelineStage,
+    // Instruction sent to the refine stage after the draft
+    #[serde(default)]
+    pub refine_prompt: Option<String>,
+}
+
+// A cleanup step for replies, e.g. for relays that append ads or leave reasoning in the text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PostProcessor {
+    // Removes `<think>...</think>` reasoning blocks, including an unclosed one at the end
+    StripThink,
+    // Turns odd Unicode spaces into plain ones, trims line ends and collapses runs of blank lines
+    NormalizeWhitespace,
+    // Replaces every match of a regular expression; `$1` and `${name}` refer to capture groups
+    Replace {
+        pattern: String,
+        #[serde(default)]
+        replacement: String,
+    },
+    // Pipes the reply through a shell command and uses what it prints
+    Command { command: String },
+}
+
+// A prompt that `serve` sends on a cron schedule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    // Standard five-field cron expression in local time, e.g. "0 7 * * *"; a leading seconds field is also accepted
+    pub cron: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub preset: Option<String>,
+    pub output: ScheduleOutput,
+    // URL that receives a JSON POST when a run fails
+    #[serde(default)]
+    pub failure_webhook: Option<String>,
+    #[serde(default = "default_schedule_enabled")]
+    pub enabled: bool,
+}
+
+fn default_schedule_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScheduleOutput {
+    // Each reply is appended to this file
+    File { path: PathBuf },
+    // Each reply is POSTed as JSON to this URL
+    Webhook { url: String },
+    // Each reply is mailed through the `email` SMTP settings; an empty list uses `email.to`
+    Email {
+        #[serde(default)]
+        to: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResponseRetryConfig {
+    pub enabled: bool,
+    pub retry_on_empty: bool,
+    // Case-insensitive substrings that mark a response as a refusal
+    pub refusal_patterns: Vec<String>,
+    pub max_retries: u32,
+}
+
+impl Default for ResponseRetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            retry_on_empty: true,
+            refusal_patterns: vec![
+                "I can't help with that".to_string(),
+                "I cannot help with that".to_string(),
+                "I can't assist with that".to_string(),
+                "I cannot assist with that".to_string(),
+                "I'm sorry, but I can't".to_string(),
+            ],
+            max_retries: 1,
+        }
+    }
+}
+
+// Retries for replies that ignore `--language`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LanguageRetryConfig {
+    pub max_retries: u32,
+    // Ask another channel rather than the same one again
+    pub switch_channel: bool,
+}
+
+impl Default for LanguageRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            switch_channel: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextStrategy {
+    /// Leave out the oldest turns until the conversation fits
+    #[default]
+    Drop,
+    /// Send only the last `window_turns` turns, and fewer if they still don't fit
+    SlidingWindow,
+    /// Replace the turns that don't fit with a summary of them
+    Summarize,
+}
+
+// How chat and session history is cut down to a channel's `context_window`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContextConfig {
+    pub strategy: ContextStrategy,
+    // Window for models whose channels set no `context_window`; unset sends the whole history
+    pub max_tokens: Option<u64>,
+    pub window_turns: usize,
+    // Channel and model that write summaries; unset uses the conversation's own model
+    pub summary_channel: Option<String>,
+    pub summary_model: Option<String>,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            strategy: ContextStrategy::default(),
+            max_tokens: None,
+            window_turns: 10,
+            summary_channel: None,
+            summary_model: None,
+        }
+    }
+}
+
+// How `--consensus` settles answers that differ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConsensusConfig {
+    // Channel and model asked to combine differing answers; unset routes the judge like any request
+    pub judge_channel: Option<String>,
+    pub judge_model: Option<String>,
+    // Answers up to this long are compared for a majority before a judge is asked
+    pub max_vote_chars: usize,
+}
+
+impl Default for ConsensusConfig {
+    fn default() -> Self {
+        Self {
+            judge_channel: None,
+            judge_model: None,
+            max_vote_chars: 200,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    pub enabled: bool,
+    // Consecutive failed requests that open the circuit
+    pub failure_threshold: u32,
+    pub cooldown_seconds: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            failure_threshold: 3,
+            cooldown_seconds: 60,
+        }
+    }
+}
+
+// Takes channels that keep failing health checks out of rotation for a while
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoDisableConfig {
+    pub enabled: bool,
+    // Failed checks since the last passing one, within the window, that trigger it
+    pub failures: u32,
+    pub window_minutes: u64,
+    pub disable_minutes: u64,
+}
+
+impl Default for AutoDisableConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            failures: 5,
+            window_minutes: 30,
+            disable_minutes: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemovedChannel {
+    pub channel: Channel,
+    pub removed_at: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -20,6 +767,122 @@ pub struct Config {
     pub default_model: Option<String>,
     pub timeout_seconds: u64,
     pub retry_attempts: u32,
+    #[serde(default)]
+    pub removed: HashMap<String, RemovedChannel>,
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u64,
+    #[serde(default)]
+    pub display: DisplayConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default = "default_health_accept_statuses")]
+    pub health_accept_statuses: Vec<u16>,
+    // How long a probe result is trusted before re-probing; 0 probes before every request
+    #[serde(default = "default_health_cache_seconds")]
+    pub health_cache_seconds: u64,
+    // Record every request in a local SQLite database for `ccswitch stats`
+    #[serde(default = "default_usage_stats")]
+    pub usage_stats: bool,
+    // Identical requests are answered from a local cache for this long; unset disables the cache
+    #[serde(default)]
+    pub cache_ttl_seconds: Option<u64>,
+    // Replies (streamed or not) beyond this are aborted rather than buffered without end
+    #[serde(default = "default_max_response_bytes")]
+    pub max_response_bytes: u64,
+    // Channels probed at once by `test`
+    #[serde(default = "default_test_concurrency")]
+    pub test_concurrency: usize,
+    #[serde(default)]
+    pub presets: HashMap<String, Preset>,
+    #[serde(default)]
+    pub pipelines: HashMap<String, Pipeline>,
+    #[serde(default)]
+    pub response_retry: ResponseRetryConfig,
+    #[serde(default)]
+    pub language_retry: LanguageRetryConfig,
+    #[serde(default)]
+    pub consensus: ConsensusConfig,
+    #[serde(default)]
+    pub context: ContextConfig,
+    // How channels serving a model are ordered; later ones are the failover sequence
+    #[serde(default)]
+    pub routing_strategy: RoutingStrategyKind,
+    // Race the top two channels on every request, as `--hedge` does for one
+    #[serde(default)]
+    pub hedge: bool,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    #[serde(default)]
+    pub auto_disable: AutoDisableConfig,
+    #[serde(default)]
+    pub dns: DnsConfig,
+    // Upstream response headers kept on each response; a trailing `*` matches a prefix
+    #[serde(default = "default_capture_headers")]
+    pub capture_headers: Vec<String>,
+    #[serde(default)]
+    pub key_expiry: KeyExpiryConfig,
+    // Language for CLI messages, e.g. "en" or "zh-CN"; unset follows LANG
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub schedules: HashMap<String, Schedule>,
+    // Sent as the system message when a chat request doesn't bring its own
+    #[serde(default)]
+    pub default_system_prompt: Option<String>,
+    #[serde(default)]
+    pub email: EmailConfig,
+    #[serde(default)]
+    pub serve: ServeConfig,
+    // Keyed by model name
+    #[serde(default)]
+    pub pricing: HashMap<String, ModelPrice>,
+    // Once reached, requests are refused until the period rolls over
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    // Channel pools per request kind, e.g. completions on a cheap dedicated channel; kinds without an entry use every channel.
+    // Chat and completion are the only kinds: embeddings, images and audio are not proxied at all.
+    #[serde(default)]
+    pub routes: HashMap<EndpointKind, Vec<String>>,
+    // Refuse every change to this file (and self-update), e.g. for a centrally provisioned install;
+    // the CCSWITCH_READ_ONLY environment variable has the same effect
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+fn default_capture_headers() -> Vec<String> {
+    ["x-request-id", "request-id", "openai-model", "openai-version", "x-ratelimit-*", "anthropic-ratelimit-*", "retry-after"]
+        .iter()
+        .map(|header| header.to_string())
+        .collect()
+}
+
+// 400 usually just means the probe's placeholder model was rejected
+fn default_health_accept_statuses() -> Vec<u16> {
+    vec![400]
+}
+
+fn default_health_cache_seconds() -> u64 {
+    300
+}
+
+fn default_usage_stats() -> bool {
+    true
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_max_response_bytes() -> u64 {
+    32 * 1024 * 1024
+}
+
+fn default_test_concurrency() -> usize {
+    8
+}
+
+fn default_trash_retention_days() -> u64 {
+    30
 }
 
 impl Default for Config {
@@ -29,8 +892,161 @@ impl Default for Config {
             default_model: None,
             timeout_seconds: 30,
             retry_attempts: 3,
+            removed: HashMap::new(),
+            trash_retention_days: default_trash_retention_days(),
+            display: DisplayConfig::default(),
+            metrics: MetricsConfig::default(),
+            health_accept_statuses: default_health_accept_statuses(),
+            health_cache_seconds: default_health_cache_seconds(),
+            usage_stats: default_usage_stats(),
+            cache_ttl_seconds: None,
+            max_response_bytes: default_max_response_bytes(),
+            test_concurrency: default_test_concurrency(),
+            presets: HashMap::new(),
+            pipelines: HashMap::new(),
+            response_retry: ResponseRetryConfig::default(),
+            language_retry: LanguageRetryConfig::default(),
+            consensus: ConsensusConfig::default(),
+            context: ContextConfig::default(),
+            routing_strategy: RoutingStrategyKind::default(),
+            hedge: false,
+            circuit_breaker: CircuitBreakerConfig::default(),
+            auto_disable: AutoDisableConfig::default(),
+            dns: DnsConfig::default(),
+            capture_headers: default_capture_headers(),
+            key_expiry: KeyExpiryConfig::default(),
+            language: None,
+            schedules: HashMap::new(),
+            default_system_prompt: None,
+            email: EmailConfig::default(),
+            serve: ServeConfig::default(),
+            pricing: HashMap::new(),
+            budget: BudgetConfig::default(),
+            routes: HashMap::new(),
+            read_only: false,
+        }
+    }
+}
+
+// Channels saved before `models` replaced the single `model` field carry one name or null
+fn migrate(config: &mut Value) {
+    let Some(config) = config.as_object_mut() else {
+        return;
+    };
+    for (section, pointer) in [("channels", ""), ("removed", "/channel")] {
+        let Some(entries) = config.get_mut(section).and_then(Value::as_object_mut) else {
+            continue;
+        };
+        for entry in entries.values_mut() {
+            let Some(channel) = entry.pointer_mut(pointer).and_then(Value::as_object_mut) else {
+                continue;
+            };
+            if let Some(model) = channel.remove("model") {
+                channel.entry("models").or_insert_with(|| json!(model.as_str().into_iter().collect::<Vec<_>>()));
+            }
+        }
+    }
+}
+
+fn model_regex(entry: &str) -> Option<&str> {
+    entry.strip_prefix('/').and_then(|rest| rest.strip_suffix('/')).filter(|regex| !regex.is_empty())
+}
+
+// The regex a `models` entry stands for, or None for a plain name matched exactly
+fn model_pattern(entry: &str) -> Option<String> {
+    if let Some(regex) = model_regex(entry) {
+        return Some(format!("^(?:{})$", regex));
+    }
+    if !entry.contains(['*', '?']) {
+        return None;
+    }
+    let mut regex = String::from("^");
+    for c in entry.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
         }
     }
+    regex.push('$');
+    Some(regex)
+}
+
+// Compiled `models` entries, None for plain names; every routing decision matches against them
+static MODEL_PATTERNS: OnceLock<Mutex<HashMap<String, Option<Regex>>>> = OnceLock::new();
+
+// Regex syntax that a glob would match literally, e.g. the `.*` in `claude-3-.*`
+const REGEX_SYNTAX: &[&str] = &[".*", ".+", "\\", "(", "[", "|", "^", "$"];
+
+fn compiled_pattern(entry: &str) -> Option<Regex> {
+    let patterns = MODEL_PATTERNS.get_or_init(Default::default);
+    if let Some(regex) = patterns.lock().unwrap().get(entry) {
+        return regex.clone();
+    }
+    // Patterns are checked when the config is loaded or a channel is added
+    let regex = model_pattern(entry).and_then(|pattern| Regex::new(&pattern).ok());
+    patterns.lock().unwrap().insert(entry.to_string(), regex.clone());
+    regex
+}
+
+pub fn model_matches(entry: &str, model: &str) -> bool {
+    match compiled_pattern(entry) {
+        Some(regex) => regex.is_match(model),
+        None => entry == model,
+    }
+}
+
+// Settings that would otherwise only fail, or hang, once a request is routed to the channel
+pub fn validate_channel(channel: &Channel) -> Result<()> {
+    validate_models(channel)?;
+    postprocess::validate(channel)?;
+    // An allowance of zero never refills, so every request would wait on the channel forever
+    let zero = [
+        ("max_in_flight", channel.max_in_flight.map(u64::from)),
+        ("requests_per_minute", channel.requests_per_minute.map(u64::from)),
+        ("tokens_per_minute", channel.tokens_per_minute),
+    ]
+    .into_iter()
+    .find(|(_, limit)| *limit == Some(0));
+    if let Some((setting, _)) = zero {
+        return Err(CCSwitchError::Config(format!(
+            "Channel '{}' has {} set to 0; remove the limit, or disable the channel instead", channel.name, setting
+        )));
+    }
+    Ok(())
+}
+
+fn validate_models(channel: &Channel) -> Result<()> {
+    // Globs are escaped into valid regexes, so only the regex form can be malformed
+    for entry in &channel.models {
+        match model_regex(entry) {
+            Some(regex) => {
+                Regex::new(regex).map_err(|e| CCSwitchError::Config(format!(
+                    "Channel '{}' has an invalid model pattern '{}': {}", channel.name, entry, e
+                )))?;
+            }
+            None if REGEX_SYNTAX.iter().any(|syntax| entry.contains(syntax)) => {
+                return Err(CCSwitchError::Config(format!(
+                    "Channel '{}' has model '{}', which looks like a regular expression; write it as '/{}/', or use the * and ? wildcards",
+                    channel.name, entry, entry
+                )));
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+// How a request kind is written under `routes`
+fn route_key(kind: EndpointKind) -> String {
+    format!("{:?}", kind).to_lowercase()
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 impl Config {
@@ -40,18 +1056,63 @@ impl Config {
         if !config_path.exists() {
             // Create default config if it doesn't exist
             let config = Config::default();
-            config.save()?;
+            if !config.is_read_only() {
+                config.save()?;
+            }
             return Ok(config);
         }
         
         let content = fs::read_to_string(&config_path)
             .map_err(|e| CCSwitchError::Config(format!("Failed to read config file: {}", e)))?;
             
-        serde_json::from_str(&content)
-            .map_err(|e| CCSwitchError::Config(format!("Failed to parse config file: {}", e)))
+        let mut value: Value = serde_json::from_str(&content)
+            .map_err(|e| CCSwitchError::Config(format!("Failed to parse config file: {}", e)))?;
+        migrate(&mut value);
+        let config: Config = serde_json::from_value(value)
+            .map_err(|e| CCSwitchError::Config(format!("Failed to parse config file: {}", e)))?;
+        for channel in config.channels.values() {
+            validate_channel(channel)?;
+        }
+        config.validate_routes()?;
+        Ok(config)
+    }
+    
+    // A pool naming a missing channel, or none at all, would fail every request of its kind
+    fn validate_routes(&self) -> Result<()> {
+        for (kind, pool) in &self.routes {
+            if pool.is_empty() {
+                return Err(CCSwitchError::Config(format!(
+                    "routes.{} lists no channels; remove the entry to use every channel", route_key(*kind)
+                )));
+            }
+            if let Some(name) = pool.iter().find(|name| !self.channels.contains_key(name.as_str())) {
+                return Err(CCSwitchError::Config(format!(
+                    "routes.{} names channel '{}', which does not exist", route_key(*kind), name
+                )));
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn has_budgets(&self) -> bool {
+        self.budget.is_set()
+            || self.channels.values().any(|channel| channel.budget.is_set())
+            || self.serve.users.values().any(|user| user.budget.is_set())
+    }
+    
+    pub fn is_read_only(&self) -> bool {
+        self.read_only || env::var("CCSWITCH_READ_ONLY").is_ok_and(|value| !matches!(value.as_str(), "" | "0" | "false"))
+    }
+    
+    pub fn ensure_writable(&self, action: &str) -> Result<()> {
+        if self.is_read_only() {
+            return Err(CCSwitchError::ReadOnly(action.to_string()));
+        }
+        Ok(())
     }
     
     pub fn save(&self) -> Result<()> {
+        self.ensure_writable("save the configuration")?;
         let config_path = Self::config_path()?;
         
         // Create config directory if it doesn't exist
@@ -70,23 +1131,86 @@ impl Config {
     }
     
     pub fn add_channel(&mut self, channel: Channel) -> Result<()> {
+        self.ensure_writable("add channels")?;
         if self.channels.contains_key(&channel.name) {
             return Err(CCSwitchError::Config(format!("Channel '{}' already exists", channel.name)));
         }
+        validate_channel(&channel)?;
         
         self.channels.insert(channel.name.clone(), channel);
         self.save()
     }
     
+    // Edits a copy, so a change that fails validation leaves the channel as it was
+    pub fn update_channel(&mut self, name: &str, edit: impl FnOnce(&mut Channel)) -> Result<()> {
+        self.ensure_writable("update channels")?;
+        let mut channel = self.channels
+            .get(name)
+            .ok_or_else(|| CCSwitchError::ChannelNotFound(name.to_string()))?
+            .clone();
+        edit(&mut channel);
+        validate_channel(&channel)?;
+        
+        self.channels.insert(name.to_string(), channel);
+        self.save()
+    }
+    
     pub fn remove_channel(&mut self, name: &str) -> Result<()> {
-        if !self.channels.contains_key(name) {
-            return Err(CCSwitchError::ChannelNotFound(name.to_string()));
+        self.ensure_writable("remove channels")?;
+        if let Some((kind, _)) = self.routes.iter().find(|(_, pool)| pool.len() == 1 && pool[0] == name) {
+            return Err(CCSwitchError::Config(format!(
+                "Channel '{}' is the only one in routes.{}; change the route before removing it", name, route_key(*kind)
+            )));
         }
+        let channel = self.channels
+            .remove(name)
+            .ok_or_else(|| CCSwitchError::ChannelNotFound(name.to_string()))?;
         
-        self.channels.remove(name);
+        for pool in self.routes.values_mut() {
+            pool.retain(|routed| routed != name);
+        }
+        // Keep the channel around so a mistaken remove can be undone with `restore`
+        self.removed.insert(name.to_string(), RemovedChannel {
+            channel,
+            removed_at: now_secs(),
+        });
+        self.purge_expired_removals();
         self.save()
     }
     
+    pub fn restore_channel(&mut self, name: &str) -> Result<()> {
+        self.ensure_writable("restore channels")?;
+        self.purge_expired_removals();
+        
+        if self.channels.contains_key(name) {
+            return Err(CCSwitchError::Config(format!("Channel '{}' already exists", name)));
+        }
+        
+        let removed = self.removed
+            .remove(name)
+            .ok_or_else(|| CCSwitchError::Config(format!("Channel '{}' is not in the trash", name)))?;
+        
+        self.channels.insert(name.to_string(), removed.channel);
+        self.save()
+    }
+    
+    fn purge_expired_removals(&mut self) {
+        let cutoff = now_secs().saturating_sub(self.trash_retention_days * 24 * 60 * 60);
+        self.removed.retain(|_, removed| removed.removed_at >= cutoff);
+    }
+    
+    pub fn get_preset(&self, name: &str) -> Result<&Preset> {
+        self.presets
+            .get(name)
+            .ok_or_else(|| CCSwitchError::Config(format!("Preset '{}' not found", name)))
+    }
+    
+    pub fn get_pipeline(&self, name: &str) -> Result<&Pipeline> {
+        self.pipelines
+            .get(name)
+            .ok_or_else(|| CCSwitchError::Config(format!("Pipeline '{}' not found", name)))
+    }
+    
     pub fn get_channel(&self, name: &str) -> Option<&Channel> {
         self.channels.get(name)
     }
@@ -94,17 +1218,30 @@ impl Config {
     pub fn get_channels_for_model(&self, model: &str) -> Vec<&Channel> {
         self.channels
             .values()
-            .filter(|ch| ch.enabled && (ch.model.as_deref() == Some(model) || ch.model.is_none()))
+            .filter(|ch| ch.is_active() && ch.serves(model))
             .collect()
     }
     
-    fn config_path() -> Result<PathBuf> {
+    // Where run artifacts and background jobs are kept
+    pub fn data_dir() -> Result<PathBuf> {
+        dirs::data_dir()
+            .map(|mut path| {
+                path.push("ccswitch");
+                path
+            })
+            .ok_or_else(|| CCSwitchError::Config("Could not determine data directory".to_string()))
+    }
+    
+    pub fn config_dir() -> Result<PathBuf> {
         dirs::config_dir()
             .map(|mut path| {
                 path.push("ccswitch");
-                path.push("config.json");
                 path
             })
             .ok_or_else(|| CCSwitchError::Config("Could not determine config directory".to_string()))
     }
+    
+    fn config_path() -> Result<PathBuf> {
+        Ok(Self::config_dir()?.join("config.json"))
+    }
 }
\ No newline at end of file
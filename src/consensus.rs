@@ -0,0 +1,179 @@
+use crate::client::{APIClient, APIResponse, Message, RequestOptions};
+use crate::error::{CCSwitchError, Result};
+use futures::future;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsensusMethod {
+    // Most short answers said the same thing
+    Majority,
+    // A judge channel combined the answers
+    Judge,
+    // Only one channel answered, so there was nothing to compare
+    Single,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsensusAnswer {
+    pub channel: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// How a `--consensus` reply was reached, kept on the response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Consensus {
+    pub method: ConsensusMethod,
+    // Answers matching the returned one; for a judged reply, the answers the judge saw
+    pub agreeing: usize,
+    pub answers: Vec<ConsensusAnswer>,
+}
+
+// Case, spacing and trailing punctuation don't make two short answers different
+fn normalize(content: &str) -> String {
+    content
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(['.', '!', '。', '！'])
+        .to_lowercase()
+}
+
+// The conversation the channels answered, with its last request replaced by the judging one
+fn judge_messages(messages: &[Message], answers: &[&str]) -> Vec<Message> {
+    let mut messages = messages.to_vec();
+    let last = messages.iter().rposition(|message| message.role == "user");
+    let question = last.map_or("", |index| messages[index].content.as_str());
+    let mut prompt = format!(
+        "Several assistants answered the same request. Write the single best answer: keep what they agree on, \
+        settle disagreements by working out which answer is right, and reply with the answer only.\n\nRequest:\n{}",
+        question
+    );
+    for (index, answer) in answers.iter().enumerate() {
+        prompt.push_str(&format!("\n\nAnswer {}:\n{}", index + 1, answer));
+    }
+    match last {
+        Some(index) => messages[index] = Message::user(&prompt),
+        None => messages.push(Message::user(&prompt)),
+    }
+    messages
+}
+
+// Sends the request to `count` channels at once, then returns the majority answer when short answers agree
+// or has the judge channel combine them
+pub async fn consensus_request(client: &APIClient, messages: &[Message], options: RequestOptions, count: usize) -> Result<APIResponse> {
+    let manager = client.get_channel_manager();
+    let settings = &manager.config.consensus;
+    let model = client.resolve_model(options.model.as_deref());
+    let channels = manager.available_channels(&model, &options.channel_filter(&manager.config)).await?;
+    let channels: Vec<String> = channels.into_iter().take(count.max(2)).map(|ch| ch.name.clone()).collect();
+    if channels.len() < 2 {
+        return Err(CCSwitchError::Config(format!(
+            "--consensus needs at least 2 available channels serving {}, found {}",
+            model,
+            channels.len()
+        )));
+    }
+    
+    info!("Asking {} channels for a consensus: {}", channels.len(), channels.join(", "));
+    let results = future::join_all(channels.iter().map(|channel| {
+        let options = RequestOptions {
+            model: Some(model.clone()),
+            channel: Some(channel.clone()),
+            stream: false,
+            no_cache: true,
+            ..options.clone()
+        };
+        client.make_chat_request(messages, options)
+    })).await;
+    
+    let answers: Vec<ConsensusAnswer> = channels.iter().zip(&results).map(|(channel, result)| ConsensusAnswer {
+        channel: channel.clone(),
+        content: result.as_ref().ok().map(|response| response.content.clone()),
+        error: result.as_ref().err().map(|e| e.to_string()),
+    }).collect();
+    let total_cost: Option<f64> = results.iter()
+        .filter_map(|result| result.as_ref().ok().and_then(|response| response.cost))
+        .reduce(|a, b| a + b);
+    
+    let mut responses = Vec::new();
+    let mut first_error = None;
+    for result in results {
+        match result {
+            Ok(response) => responses.push(response),
+            Err(e) => {
+                warn!("A consensus channel failed: {}", e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    
+    if responses.len() < 2 {
+        let Some(mut response) = responses.pop() else {
+            return Err(first_error.unwrap_or(CCSwitchError::NoAvailableChannels(model)));
+        };
+        response.consensus = Some(Consensus { method: ConsensusMethod::Single, agreeing: 1, answers });
+        return Ok(response);
+    }
+    
+    // Long answers are never word-for-word alike, so only short ones are put to a vote
+    if responses.iter().all(|response| response.content.chars().count() <= settings.max_vote_chars) {
+        let normalized: Vec<String> = responses.iter().map(|response| normalize(&response.content)).collect();
+        let (winner, agreeing) = normalized.iter()
+            .enumerate()
+            .map(|(index, answer)| (index, normalized.iter().filter(|other| *other == answer).count()))
+            .max_by_key(|&(index, agreeing)| (agreeing, std::cmp::Reverse(index)))
+            .unwrap_or((0, 0));
+        if agreeing * 2 > responses.len() {
+            let mut response = responses.swap_remove(winner);
+            response.cost = total_cost;
+            response.consensus = Some(Consensus { method: ConsensusMethod::Majority, agreeing, answers });
+            return Ok(response);
+        }
+    }
+    
+    let judge_options = RequestOptions {
+        model: settings.judge_model.clone().or(Some(model)),
+        channel: settings.judge_channel.clone(),
+        stream: false,
+        no_cache: true,
+        ..options
+    };
+    info!("Answers differ, asking a judge to combine them");
+    let answered: Vec<&str> = responses.iter().map(|response| response.content.as_str()).collect();
+    let mut response = client.make_chat_request(&judge_messages(messages, &answered), judge_options).await?;
+    
+    response.cost = match (total_cost, response.cost) {
+        (Some(answers), Some(judge)) => Some(answers + judge),
+        (answers, judge) => answers.or(judge),
+    };
+    response.consensus = Some(Consensus { method: ConsensusMethod::Judge, agreeing: responses.len(), answers });
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn the_judge_sees_the_whole_conversation() {
+        let messages = [
+            Message::system("Answer in French."),
+            Message::user("What is 2 + 2?"),
+            Message::assistant("Quatre."),
+            Message::user("And 3 + 3?"),
+        ];
+        let judged = judge_messages(&messages, &["Six.", "Sept."]);
+        assert_eq!(judged.len(), 4);
+        assert_eq!(judged[0].content, "Answer in French.");
+        assert_eq!(judged[2].content, "Quatre.");
+        assert_eq!(judged[3].role, "user");
+        assert!(judged[3].content.contains("Request:\nAnd 3 + 3?"));
+        assert!(judged[3].content.contains("Answer 1:\nSix."));
+        assert!(judged[3].content.contains("Answer 2:\nSept."));
+    }
+}
@@ -0,0 +1,170 @@
+use crate::client::{APIClient, Message, RequestOptions};
+use crate::config::{Config, ContextStrategy};
+use crate::error::Result;
+use crate::tokens::estimate_tokens;
+use log::{info, warn};
+use tokio::sync::mpsc;
+
+// Left for the reply when the request doesn't set `max_tokens`
+const DEFAULT_REPLY_TOKENS: u64 = 1024;
+// Role markers and separators each message adds on top of its text
+const MESSAGE_OVERHEAD_TOKENS: u64 = 4;
+// Marks the system message that stands in for trimmed turns
+const SUMMARY_HEADER: &str = "Summary of the earlier conversation:";
+const SUMMARY_PROMPT: &str = "Summarize the conversation below for the assistant taking it over. Keep the facts, decisions, \
+    names, numbers and open questions; leave out pleasantries. Reply with the summary only.";
+
+// The smallest context window among the channels serving the model, or the configured fallback
+pub fn window(config: &Config, model: &str) -> Option<u64> {
+    config.get_channels_for_model(model)
+        .iter()
+        .filter_map(|channel| channel.context_window)
+        .min()
+        .or(config.context.max_tokens)
+}
+
+fn tokens(messages: &[Message]) -> u64 {
+    messages.iter().map(|message| estimate_tokens(&message.content) + MESSAGE_OVERHEAD_TOKENS).sum()
+}
+
+pub fn summary_message(summary: &str) -> Message {
+    Message::system(&format!("{}\n{}", SUMMARY_HEADER, summary))
+}
+
+fn is_summary(message: &Message) -> bool {
+    message.role == "system" && message.content.starts_with(SUMMARY_HEADER)
+}
+
+// Leading system messages, then the turns, each starting at a user message
+fn split(messages: &[Message]) -> (Vec<Message>, Vec<Vec<Message>>) {
+    let start = messages.iter().position(|message| message.role != "system").unwrap_or(messages.len());
+    let mut turns: Vec<Vec<Message>> = Vec::new();
+    for message in &messages[start..] {
+        match turns.last_mut() {
+            Some(turn) if message.role != "user" => turn.push(message.clone()),
+            _ => turns.push(vec![message.clone()]),
+        }
+    }
+    (messages[..start].to_vec(), turns)
+}
+
+// Drops the oldest turns until the rest fits in `budget` tokens, or only the last turn is left; `keep_turns` caps
+// the count as well. Returns the messages to send and the turns dropped.
+fn trim(messages: &[Message], budget: Option<u64>, keep_turns: Option<usize>) -> (Vec<Message>, Vec<Message>) {
+    let (system, mut turns) = split(messages);
+    let mut dropped = Vec::new();
+    let over = |turns: &[Vec<Message>]| {
+        let size = tokens(&system) + turns.iter().map(|turn| tokens(turn)).sum::<u64>();
+        budget.is_some_and(|budget| size > budget) || keep_turns.is_some_and(|keep| turns.len() > keep)
+    };
+    while turns.len() > 1 && over(&turns) {
+        dropped.extend(turns.remove(0));
+    }
+    (system.into_iter().chain(turns.into_iter().flatten()).collect(), dropped)
+}
+
+// Cuts a conversation down to what the channels serving it accept, as `context.strategy` says
+pub async fn fit(client: &APIClient, messages: &[Message], options: &RequestOptions) -> Result<Vec<Message>> {
+    let config = &client.get_channel_manager().config;
+    let settings = &config.context;
+    let model = client.resolve_model(options.model.as_deref());
+    let budget = window(config, &model)
+        .map(|window| window.saturating_sub(options.max_tokens.map_or(DEFAULT_REPLY_TOKENS, u64::from)));
+    // The latest turn is the question being asked, so a window of N keeps N answered turns
+    let keep_turns = (settings.strategy == ContextStrategy::SlidingWindow).then_some(settings.window_turns + 1);
+    
+    let (kept, dropped) = trim(messages, budget, keep_turns);
+    if dropped.is_empty() {
+        return Ok(kept);
+    }
+    if settings.strategy != ContextStrategy::Summarize {
+        info!("Left the {} oldest messages out of the conversation to fit the context window", dropped.len());
+        return Ok(kept);
+    }
+    
+    // An earlier summary is folded into the new one
+    let earlier: Vec<Message> = kept.iter().filter(|message| is_summary(message)).cloned().collect();
+    let to_summarize: Vec<Message> = earlier.into_iter().chain(dropped).collect();
+    match summarize(client, &to_summarize, &model).await {
+        Ok(summary) => {
+            info!("Summarized the {} oldest messages of the conversation to fit the context window", to_summarize.len());
+            let mut messages: Vec<Message> = kept.into_iter().filter(|message| !is_summary(message)).collect();
+            let at = messages.iter().position(|message| message.role != "system").unwrap_or(messages.len());
+            messages.insert(at, summary_message(&summary));
+            Ok(messages)
+        }
+        Err(e) => {
+            warn!("Could not summarize the trimmed turns, leaving them out: {}", e);
+            Ok(kept)
+        }
+    }
+}
+
+// Has the summary channel and model condense a transcript
+pub async fn summarize(client: &APIClient, messages: &[Message], model: &str) -> Result<String> {
+    let settings = &client.get_channel_manager().config.context;
+    let transcript: Vec<String> = messages.iter()
+        .map(|message| match message.content.strip_prefix(SUMMARY_HEADER) {
+            Some(summary) => format!("[earlier summary]{}", summary),
+            None => format!("[{}] {}", message.role, message.content),
+        })
+        .collect();
+    
+    // Its events go nowhere: the summary is not part of the reply being shown
+    let (events, _) = mpsc::unbounded_channel();
+    let options = RequestOptions {
+        model: Some(settings.summary_model.clone().unwrap_or_else(|| model.to_string())),
+        channel: settings.summary_channel.clone(),
+        events: Some(events),
+        ..RequestOptions::default()
+    };
+    let response = client.make_chat_request(&[Message::system(SUMMARY_PROMPT), Message::user(&transcript.join("\n\n"))], options).await?;
+    Ok(response.content.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn conversation(turns: usize) -> Vec<Message> {
+        let mut messages = vec![Message::system("Be brief.")];
+        for turn in 0..turns {
+            messages.push(Message::user(&format!("question {} {}", turn, "word ".repeat(40))));
+            messages.push(Message::assistant(&format!("answer {} {}", turn, "word ".repeat(40))));
+        }
+        messages.push(Message::user("the latest question"));
+        messages
+    }
+    
+    #[test]
+    fn oldest_turns_go_first() {
+        let messages = conversation(5);
+        let (kept, dropped) = trim(&messages, Some(tokens(&messages) - 1), None);
+        assert_eq!(dropped.len(), 2);
+        assert!(dropped[0].content.starts_with("question 0"));
+        assert_eq!(kept[0].content, "Be brief.");
+        assert!(kept[1].content.starts_with("question 1"));
+        assert_eq!(kept.last().unwrap().content, "the latest question");
+    }
+    
+    #[test]
+    fn the_question_is_always_sent() {
+        let messages = conversation(3);
+        let (kept, dropped) = trim(&messages, Some(1), None);
+        assert_eq!(dropped.len(), 6);
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[1].content, "the latest question");
+    }
+    
+    #[test]
+    fn sliding_window_counts_turns() {
+        let messages = conversation(5);
+        let (kept, dropped) = trim(&messages, None, Some(3));
+        assert_eq!(dropped.len(), 6);
+        assert!(kept[1].content.starts_with("question 3"));
+        
+        let (kept, dropped) = trim(&messages, None, None);
+        assert!(dropped.is_empty());
+        assert_eq!(kept.len(), messages.len());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::config::Channel;
+use crate::error::{CCSwitchError, Result};
+use log::debug;
+use reqwest::Client;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::process::Command;
+
+const VAULT_PREFIX: &str = "vault:";
+const ONEPASSWORD_PREFIX: &str = "op://";
+
+// Resolves API keys, running helpers and secret lookups at most once per channel per process
+pub struct CredentialStore {
+    cache: Mutex<HashMap<String, String>>,
+    client: Client,
+}
+
+impl Default for CredentialStore {
+    fn default() -> Self {
+        Self {
+            cache: Mutex::new(HashMap::new()),
+            client: Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl CredentialStore {
+    pub async fn api_key(&self, channel: &Channel) -> Result<Option<String>> {
+        let needs_lookup = channel.credential_helper.is_some()
+            || channel.api_key.as_deref().is_some_and(is_secret_reference);
+        if !needs_lookup {
+            return Ok(channel.api_key.clone());
+        }
+        
+        if let Some(key) = self.cache.lock().unwrap().get(&channel.name) {
+            return Ok(Some(key.clone()));
+        }
+        
+        let key = match (&channel.credential_helper, channel.api_key.as_deref()) {
+            (Some(helper), _) => {
+                debug!("Running credential helper for channel {}", channel.name);
+                run_command(&channel.name, "credential helper", shell(helper)).await?
+            }
+            (None, Some(reference)) if reference.starts_with(ONEPASSWORD_PREFIX) => {
+                debug!("Reading 1Password secret for channel {}", channel.name);
+                let mut command = Command::new("op");
+                command.args(["read", "--no-newline", reference]);
+                run_command(&channel.name, "1Password CLI", command).await?
+            }
+            (None, Some(reference)) => self.read_vault(&channel.name, &reference[VAULT_PREFIX.len()..]).await?,
+            (None, None) => unreachable!(),
+        };
+        
+        self.cache.lock().unwrap().insert(channel.name.clone(), key.clone());
+        Ok(Some(key))
+    }
+    
+    // `vault:<path>#<field>`, read over the HTTP API using VAULT_ADDR and VAULT_TOKEN (or ~/.vault-token)
+    async fn read_vault(&self, channel: &str, reference: &str) -> Result<String> {
+        let (path, field) = reference.split_once('#').ok_or_else(|| {
+            CCSwitchError::Auth(format!("secret reference for '{}' must look like vault:<path>#<field>", channel))
+        })?;
+        
+        let addr = env::var("VAULT_ADDR")
+            .map_err(|_| CCSwitchError::Auth(format!("VAULT_ADDR is not set; cannot resolve the key for '{}'", channel)))?;
+        let token = env::var("VAULT_TOKEN")
+            .ok()
+            .or_else(|| dirs::home_dir().and_then(|home| fs::read_to_string(home.join(".vault-token")).ok()))
+            .map(|token| token.trim().to_string())
+            .ok_or_else(|| CCSwitchError::Auth(format!("No Vault token found for '{}' (set VAULT_TOKEN or run `vault login`)", channel)))?;
+        
+        let url = format!("{}/v1/{}", addr.trim_end_matches('/'), path.trim_start_matches('/'));
+        debug!("Reading Vault secret {} for channel {}", url, channel);
+        
+        let response = self.client
+            .get(&url)
+            .header("X-Vault-Token", token)
+            .send()
+            .await
+            .map_err(|e| CCSwitchError::Auth(format!("Vault at {} is unreachable for '{}': {}", addr, channel, e)))?;
+        
+        let status = response.status();
+        if !status.is_success() {
+            return Err(CCSwitchError::Auth(format!("Vault returned {} for '{}' ({})", status, path, channel)));
+        }
+        
+        let body: Value = response.json().await
+            .map_err(|e| CCSwitchError::Auth(format!("Vault returned an unreadable response for '{}': {}", channel, e)))?;
+        
+        // KV v2 nests the secret under data.data, KV v1 directly under data
+        body.pointer(&format!("/data/data/{}", field))
+            .or_else(|| body.pointer(&format!("/data/{}", field)))
+            .and_then(|value| value.as_str())
+            .map(|value| value.to_string())
+            .ok_or_else(|| CCSwitchError::Auth(format!("Vault secret '{}' has no field '{}' ({})", path, field, channel)))
+    }
+}
+
+fn is_secret_reference(value: &str) -> bool {
+    value.starts_with(VAULT_PREFIX) || value.starts_with(ONEPASSWORD_PREFIX)
+}
+
+fn shell(script: &str) -> Command {
+    let mut command = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+    command.args([if cfg!(windows) { "/C" } else { "-c" }, script]);
+    command
+}
+
+async fn run_command(channel: &str, source: &str, mut command: Command) -> Result<String> {
+    let output = command.output().await
+        .map_err(|e| CCSwitchError::Auth(format!("{} for '{}' could not be run: {}", source, channel, e)))?;
+    
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut message = format!("{} for '{}' exited with {}", source, channel, output.status);
+        if !stderr.trim().is_empty() {
+            message.push_str(&format!(": {}", stderr.trim()));
+        }
+        return Err(CCSwitchError::Auth(message));
+    }
+    
+    let key = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if key.is_empty() {
+        return Err(CCSwitchError::Auth(format!("{} for '{}' printed no key", source, channel)));
+    }
+    
+    Ok(key)
+}
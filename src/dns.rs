@@ -0,0 +1,104 @@
+use crate::config::{DnsConfig, IpVersion};
+use hickory_resolver::system_conf::read_system_conf;
+use hickory_resolver::TokioAsyncResolver;
+use hyper::client::connect::dns::Name;
+use log::warn;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Resolver with an in-process cache that honours record TTLs, clamped to the configured bounds
+pub struct CachingResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl CachingResolver {
+    pub fn from_config(config: &DnsConfig) -> Option<Arc<Self>> {
+        if !config.cache {
+            return None;
+        }
+        
+        let (resolver_config, mut options) = match read_system_conf() {
+            Ok(conf) => conf,
+            Err(e) => {
+                warn!("Could not read the system DNS configuration, DNS cache disabled: {}", e);
+                return None;
+            }
+        };
+        options.positive_min_ttl = config.min_ttl_seconds.map(Duration::from_secs);
+        options.positive_max_ttl = config.max_ttl_seconds.map(Duration::from_secs);
+        
+        Some(Arc::new(Self {
+            resolver: TokioAsyncResolver::tokio(resolver_config, options),
+        }))
+    }
+    
+    // Forgets every cached answer, e.g. after a channel's host moved
+    pub fn clear(&self) {
+        self.resolver.clear_cache();
+    }
+    
+    async fn lookup(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        let lookup = self.resolver.lookup_ip(host).await.map_err(io::Error::other)?;
+        Ok(lookup.into_iter().map(|ip| SocketAddr::new(ip, 0)).collect())
+    }
+}
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.resolver.clone();
+        Box::pin(async move {
+            let lookup = resolver.lookup_ip(name.as_str()).await?;
+            let addrs: Addrs = Box::new(lookup.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+// Applies a channel's `ip_version` to the resolved addresses; hyper connects to the first
+// address family and only races the other one after a short head start
+pub struct IpVersionResolver {
+    cache: Option<Arc<CachingResolver>>,
+    ip_version: IpVersion,
+}
+
+impl IpVersionResolver {
+    pub fn new(cache: Option<Arc<CachingResolver>>, ip_version: IpVersion) -> Arc<Self> {
+        Arc::new(Self { cache, ip_version })
+    }
+}
+
+impl Resolve for IpVersionResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let cache = self.cache.clone();
+        let ip_version = self.ip_version;
+        Box::pin(async move {
+            let mut addrs: Vec<SocketAddr> = match cache {
+                Some(cache) => cache.lookup(name.as_str()).await?,
+                None => tokio::net::lookup_host((name.as_str(), 0)).await?.collect(),
+            };
+            // Sorting is stable, so the resolver's order holds within each family
+            match ip_version {
+                IpVersion::Auto => {}
+                IpVersion::Ipv4 => addrs.retain(SocketAddr::is_ipv4),
+                IpVersion::Ipv6 => addrs.retain(SocketAddr::is_ipv6),
+                IpVersion::PreferIpv4 => addrs.sort_by_key(SocketAddr::is_ipv6),
+                IpVersion::PreferIpv6 => addrs.sort_by_key(SocketAddr::is_ipv4),
+            }
+            
+            if addrs.is_empty() {
+                // Only a single-family setting can have filtered the answer away
+                let message = match ip_version {
+                    IpVersion::Ipv4 => format!("{} has no IPv4 address", name.as_str()),
+                    IpVersion::Ipv6 => format!("{} has no IPv6 address", name.as_str()),
+                    _ => format!("{} did not resolve to any address", name.as_str()),
+                };
+                return Err(io::Error::new(io::ErrorKind::NotFound, message).into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
@@ -0,0 +1,63 @@
+use crate::config::{EmailConfig, EmailEvent, SmtpSecurity};
+use crate::error::{CCSwitchError, Result};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use log::{info, warn};
+use std::io;
+
+pub fn enabled_for(settings: &EmailConfig, event: EmailEvent) -> bool {
+    settings.smtp_host.is_some() && !settings.to.is_empty() && settings.events.contains(&event)
+}
+
+// Sends a notification for the event if email is configured for it; failures are only logged
+pub async fn notify(settings: &EmailConfig, event: EmailEvent, subject: &str, body: &str) {
+    if !enabled_for(settings, event) {
+        return;
+    }
+    
+    if let Err(e) = send(settings, &settings.to, subject, body).await {
+        warn!("Failed to send {:?} email: {}", event, e);
+    }
+}
+
+pub async fn send(settings: &EmailConfig, to: &[String], subject: &str, body: &str) -> Result<()> {
+    let host = settings.smtp_host.as_deref()
+        .ok_or_else(|| CCSwitchError::Config("email.smtp_host is not set".to_string()))?;
+    let from = settings.from.as_deref()
+        .or(settings.username.as_deref())
+        .ok_or_else(|| CCSwitchError::Config("email.from is not set".to_string()))?;
+    
+    let mut message = Message::builder()
+        .from(mailbox(from)?)
+        .subject(subject);
+    for recipient in to {
+        message = message.to(mailbox(recipient)?);
+    }
+    let message = message.body(body.to_string()).map_err(io::Error::other)?;
+    
+    let builder = match settings.security {
+        SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+        SmtpSecurity::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)),
+    };
+    let mut builder = builder.map_err(io::Error::other)?;
+    if let Some(port) = settings.smtp_port {
+        builder = builder.port(port);
+    }
+    if let Some(username) = &settings.username {
+        let password = settings.password.clone()
+            .or_else(|| std::env::var("CCSWITCH_SMTP_PASSWORD").ok())
+            .unwrap_or_default();
+        builder = builder.credentials(Credentials::new(username.clone(), password));
+    }
+    
+    builder.build().send(message).await.map_err(io::Error::other)?;
+    info!("Sent email '{}' to {}", subject, to.join(", "));
+    Ok(())
+}
+
+fn mailbox(address: &str) -> Result<Mailbox> {
+    address.parse()
+        .map_err(|e| CCSwitchError::Config(format!("Invalid email address '{}': {}", address, e)))
+}
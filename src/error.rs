@@ -1,5 +1,75 @@
+use serde::Serialize;
+use std::fmt;
 use thiserror::Error;
 
+#[derive(Debug, Clone)]
+pub struct ChannelFailure {
+    pub channel: String,
+    pub reason: String,
+}
+
+impl fmt::Display for ChannelFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.channel, self.reason)
+    }
+}
+
+fn format_failures(failures: &[ChannelFailure]) -> String {
+    failures
+        .iter()
+        .map(|failure| format!("\n  - {}", failure))
+        .collect()
+}
+
+// What a provider's error payload meant, the same whichever provider or relay sent it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderErrorKind {
+    QuotaExhausted,
+    RateLimited,
+    Overloaded,
+    ContextTooLong,
+    ModelNotFound,
+    ContentFiltered,
+    InvalidRequest,
+}
+
+impl fmt::Display for ProviderErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProviderErrorKind::QuotaExhausted => "quota exhausted",
+            ProviderErrorKind::RateLimited => "rate limited",
+            ProviderErrorKind::Overloaded => "provider overloaded",
+            ProviderErrorKind::ContextTooLong => "context length exceeded",
+            ProviderErrorKind::ModelNotFound => "model not found",
+            ProviderErrorKind::ContentFiltered => "blocked by content filter",
+            ProviderErrorKind::InvalidRequest => "invalid request",
+        })
+    }
+}
+
+impl ProviderErrorKind {
+    // Another channel may well succeed where this one is out of credit, throttled or lacks the model
+    pub fn is_channel_specific(self) -> bool {
+        matches!(
+            self,
+            ProviderErrorKind::QuotaExhausted | ProviderErrorKind::RateLimited | ProviderErrorKind::Overloaded | ProviderErrorKind::ModelNotFound
+        )
+    }
+    
+    pub fn key(self) -> &'static str {
+        match self {
+            ProviderErrorKind::QuotaExhausted => "provider-quota-exhausted",
+            ProviderErrorKind::RateLimited => "provider-rate-limited",
+            ProviderErrorKind::Overloaded => "provider-overloaded",
+            ProviderErrorKind::ContextTooLong => "provider-context-too-long",
+            ProviderErrorKind::ModelNotFound => "provider-model-not-found",
+            ProviderErrorKind::ContentFiltered => "provider-content-filtered",
+            ProviderErrorKind::InvalidRequest => "provider-invalid-request",
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum CCSwitchError {
     #[error("Configuration error: {0}")]
@@ -8,9 +78,21 @@ pub enum CCSwitchError {
     #[error("Channel error: {0}")]
     Channel(String),
     
+    // Non-success HTTP status from a channel; reported like any other channel error
+    #[error("Channel error: API request failed: {status} - {message}")]
+    Upstream { status: reqwest::StatusCode, message: String },
+    
+    // An error status whose payload was recognized; `message` is the provider's own wording
+    #[error("Channel error: {kind} on channel {channel}: {message}")]
+    Provider { channel: String, kind: ProviderErrorKind, status: reqwest::StatusCode, message: String },
+    
     #[error("Network error: {0}")]
     Network(#[from] reqwest::Error),
     
+    // Part of the reply was already delivered, so the request must not be replayed elsewhere
+    #[error("Stream interrupted: {0}")]
+    StreamInterrupted(String),
+    
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
     
@@ -23,8 +105,157 @@ pub enum CCSwitchError {
     #[error("No available channels for model '{0}'")]
     NoAvailableChannels(String),
     
-    #[error("All channels failed")]
-    AllChannelsFailed,
+    #[error("All channels failed:{}", format_failures(.0))]
+    AllChannelsFailed(Vec<ChannelFailure>),
+    
+    #[error("Update error: {0}")]
+    Update(String),
+    
+    #[error("Authentication failed: {0}")]
+    Auth(String),
+    
+    #[error("Request cancelled")]
+    Cancelled,
+    
+    #[error("Configuration is read-only; cannot {0}")]
+    ReadOnly(String),
+    
+    #[error("Budget exceeded: {0}")]
+    BudgetExceeded(String),
+    
+    #[error("Nothing to extract: the reply has no {0}")]
+    NothingExtracted(String),
+    
+    // A failure stored earlier, such as a background job's, replayed with its original code
+    #[error("{message}")]
+    Recorded { code: &'static str, exit_code: i32, message: String },
 }
 
-pub type Result<T> = std::result::Result<T, CCSwitchError>;
\ No newline at end of file
+// Every code with its exit code, for replaying stored failures
+const CODES: &[(&str, i32)] = &[
+    ("CONFIG", 3),
+    ("CHANNEL", 4),
+    ("NETWORK", 5),
+    ("SERIALIZATION", 6),
+    ("IO", 7),
+    ("CHANNEL_NOT_FOUND", 8),
+    ("NO_CHANNELS_FOR_MODEL", 9),
+    ("ALL_CHANNELS_FAILED", 10),
+    ("UPDATE", 11),
+    ("AUTH_FAILED", 12),
+    ("READ_ONLY", 13),
+    ("BUDGET_EXCEEDED", 14),
+    ("NOTHING_EXTRACTED", 15),
+    ("CANCELLED", 130),
+];
+
+impl CCSwitchError {
+    // An unknown code, e.g. from a file written by another version, reads as a configuration error
+    pub fn recorded(code: &str, message: String) -> Self {
+        match CODES.iter().find(|(known, _)| *known == code) {
+            Some(&(code, exit_code)) => CCSwitchError::Recorded { code, exit_code, message },
+            None => CCSwitchError::Config(message),
+        }
+    }
+    
+    // Stable identifiers for scripts; never renumber or rename existing entries
+    pub fn code(&self) -> &'static str {
+        match self {
+            CCSwitchError::Config(_) => "CONFIG",
+            CCSwitchError::Channel(_) | CCSwitchError::Upstream { .. } | CCSwitchError::Provider { .. } => "CHANNEL",
+            CCSwitchError::Network(_) | CCSwitchError::StreamInterrupted(_) => "NETWORK",
+            CCSwitchError::Serialization(_) => "SERIALIZATION",
+            CCSwitchError::Io(_) => "IO",
+            CCSwitchError::ChannelNotFound(_) => "CHANNEL_NOT_FOUND",
+            CCSwitchError::NoAvailableChannels(_) => "NO_CHANNELS_FOR_MODEL",
+            CCSwitchError::AllChannelsFailed(_) => "ALL_CHANNELS_FAILED",
+            CCSwitchError::Update(_) => "UPDATE",
+            CCSwitchError::Auth(_) => "AUTH_FAILED",
+            CCSwitchError::Cancelled => "CANCELLED",
+            CCSwitchError::ReadOnly(_) => "READ_ONLY",
+            CCSwitchError::BudgetExceeded(_) => "BUDGET_EXCEEDED",
+            CCSwitchError::NothingExtracted(_) => "NOTHING_EXTRACTED",
+            CCSwitchError::Recorded { code, .. } => code,
+        }
+    }
+    
+    // Errors another channel might not reproduce: connection problems, rejected keys,
+    // server-side failures and rate limits, or unreadable responses
+    pub fn is_channel_specific(&self) -> bool {
+        match self {
+            CCSwitchError::Upstream { status, .. } => {
+                status.is_server_error() || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            // A 5xx is the channel's failure whatever its body blames
+            CCSwitchError::Provider { kind, status, .. } => kind.is_channel_specific() || status.is_server_error(),
+            CCSwitchError::Channel(_) | CCSwitchError::Network(_) | CCSwitchError::Auth(_) => true,
+            _ => false,
+        }
+    }
+    
+    pub fn provider_kind(&self) -> Option<ProviderErrorKind> {
+        match self {
+            CCSwitchError::Provider { kind, .. } => Some(*kind),
+            _ => None,
+        }
+    }
+    
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CCSwitchError::Config(_) => 3,
+            CCSwitchError::Channel(_) | CCSwitchError::Upstream { .. } | CCSwitchError::Provider { .. } => 4,
+            CCSwitchError::Network(_) | CCSwitchError::StreamInterrupted(_) => 5,
+            CCSwitchError::Serialization(_) => 6,
+            CCSwitchError::Io(_) => 7,
+            CCSwitchError::ChannelNotFound(_) => 8,
+            CCSwitchError::NoAvailableChannels(_) => 9,
+            CCSwitchError::AllChannelsFailed(_) => 10,
+            CCSwitchError::Update(_) => 11,
+            CCSwitchError::Auth(_) => 12,
+            CCSwitchError::ReadOnly(_) => 13,
+            CCSwitchError::BudgetExceeded(_) => 14,
+            CCSwitchError::NothingExtracted(_) => 15,
+            CCSwitchError::Recorded { exit_code, .. } => *exit_code,
+            // Conventional status for termination by SIGINT
+            CCSwitchError::Cancelled => 130,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, CCSwitchError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn provider(kind: ProviderErrorKind, status: u16) -> CCSwitchError {
+        CCSwitchError::Provider {
+            channel: "test".to_string(),
+            kind,
+            status: reqwest::StatusCode::from_u16(status).unwrap(),
+            message: String::new(),
+        }
+    }
+    
+    #[test]
+    fn server_errors_fail_over_whatever_their_kind() {
+        assert!(provider(ProviderErrorKind::InvalidRequest, 500).is_channel_specific());
+        assert!(provider(ProviderErrorKind::ContentFiltered, 502).is_channel_specific());
+        assert!(provider(ProviderErrorKind::RateLimited, 429).is_channel_specific());
+        assert!(!provider(ProviderErrorKind::InvalidRequest, 400).is_channel_specific());
+        assert!(!provider(ProviderErrorKind::ContextTooLong, 400).is_channel_specific());
+    }
+    
+    #[test]
+    fn recorded_errors_keep_their_code() {
+        let error = CCSwitchError::recorded("ALL_CHANNELS_FAILED", "All channels failed".to_string());
+        assert_eq!(error.code(), "ALL_CHANNELS_FAILED");
+        assert_eq!(error.exit_code(), CCSwitchError::AllChannelsFailed(Vec::new()).exit_code());
+        assert_eq!(error.to_string(), "All channels failed");
+        
+        for (code, exit_code) in CODES {
+            assert_eq!(CCSwitchError::recorded(code, String::new()).exit_code(), *exit_code);
+        }
+        assert_eq!(CCSwitchError::recorded("SOMETHING_NEW", String::new()).code(), "CONFIG");
+    }
+}
@@ -0,0 +1,18 @@
+use serde::Serialize;
+use serde_json::Value;
+
+// Progress events for tools wrapping the CLI; serialized one per line by `--json-stream`
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RequestEvent {
+    Selection { channel: String, model: String },
+    Retry { channel: String, attempt: u32, error: String },
+    Delta { content: String },
+    // Streaming throughput so far, sent about twice a second
+    Rate { tokens_per_second: f64 },
+    Usage { usage: Value },
+    Done { channel: String, model: String },
+    Error { code: String, message: String },
+}
+
+pub type EventSink = Box<dyn Fn(&RequestEvent) + Send + Sync>;
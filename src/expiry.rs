@@ -0,0 +1,99 @@
+use crate::config::{self, Channel, Config, EmailEvent, KeyExpiryConfig};
+use crate::email;
+use crate::i18n::tr;
+use log::warn;
+use reqwest::Client;
+use serde_json::json;
+use std::time::Duration;
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+// Whole days until the channel's key expires (negative once it has lapsed)
+pub fn days_left(channel: &Channel) -> Option<i64> {
+    let date = channel.key_expires_at.as_deref()?;
+    match parse_date(date) {
+        Some(expires_day) => Some(expires_day - config::now_secs() as i64 / SECS_PER_DAY),
+        None => {
+            warn!("Ignoring key_expires_at '{}' on channel {}: expected YYYY-MM-DD", date, channel.name);
+            None
+        }
+    }
+}
+
+// Human-readable warning when the key is expired or inside the warning window
+pub fn warning(channel: &Channel, settings: &KeyExpiryConfig) -> Option<String> {
+    match days_left(channel)? {
+        days if days < 0 => Some(tr("key-expired", &[("days", (-days).to_string())])),
+        0 => Some(tr("key-expires-today", &[])),
+        days if days <= settings.warn_days as i64 => Some(tr("key-expires-in", &[("days", days.to_string())])),
+        _ => None,
+    }
+}
+
+pub async fn notify(config: &Config, channels: &[&Channel]) {
+    let settings = &config.key_expiry;
+    let send_email = email::enabled_for(&config.email, EmailEvent::KeyExpiry);
+    if settings.webhook.is_none() && !send_email {
+        return;
+    }
+    
+    let expiring: Vec<_> = channels
+        .iter()
+        .filter_map(|channel| {
+            let message = warning(channel, settings)?;
+            Some(json!({
+                "channel": channel.name,
+                "key_expires_at": channel.key_expires_at,
+                "days_left": days_left(channel),
+                "message": message,
+            }))
+        })
+        .collect();
+    
+    if expiring.is_empty() {
+        return;
+    }
+    
+    if send_email {
+        let body: Vec<String> = expiring
+            .iter()
+            .map(|entry| format!("{}: {}", entry["channel"].as_str().unwrap_or_default(), entry["message"].as_str().unwrap_or_default()))
+            .collect();
+        email::notify(&config.email, EmailEvent::KeyExpiry, "[ccswitch] API keys expiring", &body.join("\n")).await;
+    }
+    
+    let Some(webhook) = &settings.webhook else {
+        return;
+    };
+    let client = Client::new();
+    let result = client
+        .post(webhook)
+        .timeout(Duration::from_secs(10))
+        .json(&json!({ "event": "key_expiry", "channels": expiring }))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    
+    if let Err(e) = result {
+        warn!("Failed to send key expiry notification: {}", e);
+    }
+}
+
+// Days since the Unix epoch for a `YYYY-MM-DD` date
+fn parse_date(date: &str) -> Option<i64> {
+    let mut parts = date.trim().splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    
+    // Howard Hinnant's days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146097 + day_of_era - 719468)
+}
@@ -0,0 +1,106 @@
+use regex::Regex;
+use serde_json::Value;
+use std::str::FromStr;
+
+// What `--extract` pulls out of a reply
+#[derive(Debug, Clone)]
+pub enum Extract {
+    // The first fenced code block, optionally only one tagged with this language
+    CodeBlock(Option<String>),
+    // The first valid JSON object or array
+    Json,
+    // The first match of the pattern; its first capture group when it has one
+    Regex(Regex),
+}
+
+impl FromStr for Extract {
+    type Err = String;
+    
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        match spec.split_once(':') {
+            None if spec == "codeblock" => Ok(Extract::CodeBlock(None)),
+            None if spec == "json" => Ok(Extract::Json),
+            Some(("codeblock", lang)) if !lang.is_empty() => Ok(Extract::CodeBlock(Some(lang.to_string()))),
+            Some(("regex", pattern)) => Regex::new(pattern)
+                .map(Extract::Regex)
+                .map_err(|e| format!("invalid pattern: {}", e)),
+            _ => Err("expected codeblock, codeblock:<lang>, json or regex:<pattern>".to_string()),
+        }
+    }
+}
+
+impl Extract {
+    pub fn apply(&self, content: &str) -> Option<String> {
+        match self {
+            Extract::CodeBlock(lang) => code_block(content, lang.as_deref()),
+            Extract::Json => json_segment(content),
+            Extract::Regex(regex) => {
+                let captures = regex.captures(content)?;
+                captures.get(1).or_else(|| captures.get(0)).map(|found| found.as_str().to_string())
+            }
+        }
+    }
+    
+    pub fn describe(&self) -> String {
+        match self {
+            Extract::CodeBlock(None) => "code block".to_string(),
+            Extract::CodeBlock(Some(lang)) => format!("{} code block", lang),
+            Extract::Json => "JSON".to_string(),
+            Extract::Regex(regex) => format!("match for /{}/", regex.as_str()),
+        }
+    }
+}
+
+fn code_block(content: &str, lang: Option<&str>) -> Option<String> {
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        let Some(info) = line.trim_start().strip_prefix("```") else {
+            continue;
+        };
+        let tag = info.split_whitespace().next().unwrap_or("");
+        // An unclosed block runs to the end of the reply, as in truncated output
+        let body: Vec<&str> = lines.by_ref().take_while(|line| !line.trim_start().starts_with("```")).collect();
+        if lang.is_none_or(|lang| tag.eq_ignore_ascii_case(lang)) {
+            return Some(body.join("\n"));
+        }
+    }
+    None
+}
+
+// The whole reply when it is JSON, otherwise the first object or array that parses
+fn json_segment(content: &str) -> Option<String> {
+    let trimmed = content.trim();
+    if serde_json::from_str::<Value>(trimmed).is_ok() {
+        return Some(trimmed.to_string());
+    }
+    content.char_indices()
+        .filter(|&(_, c)| c == '{' || c == '[')
+        .find_map(|(start, _)| {
+            let mut values = serde_json::Deserializer::from_str(&content[start..]).into_iter::<Value>();
+            values.next()?.ok()?;
+            Some(content[start..start + values.byte_offset()].to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn code_blocks() {
+        let reply = "Here you go:\n```python\nprint(1)\n```\nand in shell:\n```bash\necho 1\necho 2\n```\n";
+        assert_eq!(code_block(reply, None).as_deref(), Some("print(1)"));
+        assert_eq!(code_block(reply, Some("BASH")).as_deref(), Some("echo 1\necho 2"));
+        assert_eq!(code_block(reply, Some("rust")), None);
+        assert_eq!(code_block("```\ntruncated", None).as_deref(), Some("truncated"));
+        assert_eq!(code_block("no code here", None), None);
+    }
+    
+    #[test]
+    fn json_segments() {
+        assert_eq!(json_segment("  {\"a\": 1}\n").as_deref(), Some("{\"a\": 1}"));
+        assert_eq!(json_segment("Result: {\"a\": [1, 2]} done").as_deref(), Some("{\"a\": [1, 2]}"));
+        assert_eq!(json_segment("see [note] then [1, 2]").as_deref(), Some("[1, 2]"));
+        assert_eq!(json_segment("{broken} and none"), None);
+    }
+}
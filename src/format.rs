@@ -0,0 +1,110 @@
+use crate::client::{cache_tokens, usage_tokens};
+use crate::config::{DisplayConfig, LatencyUnit};
+use crate::i18n::tr;
+use crate::tokens::is_cjk;
+use serde_json::Value;
+
+pub struct Formatter<'a> {
+    display: &'a DisplayConfig,
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(display: &'a DisplayConfig) -> Self {
+        Self { display }
+    }
+    
+    pub fn latency(&self, ms: u64) -> String {
+        match self.display.latency_unit {
+            LatencyUnit::Ms => format!("{}ms", self.number(ms)),
+            LatencyUnit::S => format!("{:.2}s", ms as f64 / 1000.0),
+        }
+    }
+    
+    // Fractions of a cent stay visible for single requests
+    pub fn cost(&self, usd: f64) -> String {
+        let amount = usd * self.display.rate;
+        if amount > 0.0 && amount < 0.01 {
+            format!("{}{:.4}", self.display.currency, amount)
+        } else {
+            format!("{}{:.2}", self.display.currency, amount)
+        }
+    }
+    
+    pub fn number(&self, value: u64) -> String {
+        let digits = value.to_string();
+        
+        if self.display.thousands_separator.is_empty() {
+            return digits;
+        }
+        
+        let mut grouped = String::new();
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index).is_multiple_of(3) {
+                grouped.push_str(&self.display.thousands_separator);
+            }
+            grouped.push(digit);
+        }
+        grouped
+    }
+    
+    pub fn usage(&self, usage: &Value) -> String {
+        let estimated = usage.get("estimated").and_then(|v| v.as_bool()).unwrap_or(false);
+        
+        match usage_tokens(usage) {
+            (Some(prompt), Some(completion)) => {
+                let mut summary = tr("usage-summary", &[
+                    ("prompt", self.number(prompt)),
+                    ("completion", self.number(completion)),
+                    ("total", self.number(prompt + completion)),
+                ]);
+                if estimated {
+                    summary.push_str(&format!(" {}", tr("usage-estimated", &[])));
+                }
+                if let Some((cached, total)) = cache_tokens(usage).filter(|(_, total)| *total > 0) {
+                    summary.push_str(&format!(" {}", tr("usage-cached", &[
+                        ("cached", self.number(cached)),
+                        ("rate", format!("{:.0}%", cached as f64 * 100.0 / total as f64)),
+                    ])));
+                }
+                summary
+            }
+            _ => usage.to_string(),
+        }
+    }
+}
+
+pub fn rate(tokens_per_second: f64) -> String {
+    format!("{:.1} tok/s", tokens_per_second)
+}
+
+// Terminal cells taken by the text, counting CJK characters as two
+fn cells(text: &str) -> usize {
+    text.chars().map(|c| if is_cjk(c) { 2 } else { 1 }).sum()
+}
+
+// Pads to a column width
+pub fn pad(text: &str, width: usize) -> String {
+    format!("{}{}", text, " ".repeat(width.saturating_sub(cells(text))))
+}
+
+// Breaks text into lines of at most `width` terminal cells, keeping its own line breaks
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for c in paragraph.chars() {
+            if cells(&line) + cells(&c.to_string()) > width.max(2) {
+                // Break after the last space when there is one, so words stay whole
+                let carry = match line.rfind(' ') {
+                    Some(space) if space > 0 => line.split_off(space + 1),
+                    _ => String::new(),
+                };
+                lines.push(line.trim_end().to_string());
+                line = carry;
+            }
+            line.push(c);
+        }
+        lines.push(line);
+    }
+    lines
+}
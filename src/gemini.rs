@@ -0,0 +1,78 @@
+use crate::client::{Message, RequestOptions};
+use serde_json::{json, Value};
+
+// Accepts either the API root (".../v1beta") or a full model URL; the model in the request wins
+pub fn base_url(url: &str) -> &str {
+    match url.find("/models/") {
+        Some(index) => &url[..index],
+        None => url.trim_end_matches('/'),
+    }
+}
+
+pub fn endpoint(url: &str, model: &str, stream: bool) -> String {
+    if stream {
+        format!("{}/models/{}:streamGenerateContent?alt=sse", base_url(url), model)
+    } else {
+        format!("{}/models/{}:generateContent", base_url(url), model)
+    }
+}
+
+pub fn payload(messages: &[Message], options: &RequestOptions) -> Value {
+    let system: Vec<Value> = messages
+        .iter()
+        .filter(|message| message.role == "system")
+        .map(|message| json!({ "text": message.content }))
+        .collect();
+    let contents: Vec<Value> = messages
+        .iter()
+        .filter(|message| message.role != "system")
+        .map(|message| {
+            let role = if message.role == "assistant" { "model" } else { "user" };
+            json!({ "role": role, "parts": [{ "text": message.content }] })
+        })
+        .collect();
+    
+    let mut generation_config = json!({});
+    if let Some(max_tokens) = options.max_tokens {
+        generation_config["maxOutputTokens"] = json!(max_tokens);
+    }
+    if let Some(temperature) = options.temperature {
+        generation_config["temperature"] = json!(temperature);
+    }
+    
+    let mut payload = json!({
+        "contents": contents,
+        "generationConfig": generation_config
+    });
+    if !system.is_empty() {
+        payload["systemInstruction"] = json!({ "parts": system });
+    }
+    payload
+}
+
+// Text of the first candidate; thought summaries are not part of the reply
+pub fn content(response: &Value) -> Option<String> {
+    let parts = response.pointer("/candidates/0/content/parts")?.as_array()?;
+    Some(parts
+        .iter()
+        .filter(|part| part.get("thought").and_then(|t| t.as_bool()) != Some(true))
+        .filter_map(|part| part.get("text").and_then(|t| t.as_str()))
+        .collect())
+}
+
+// Renames `usageMetadata` counts to the OpenAI usage fields the rest of ccswitch reads
+pub fn usage(metadata: &Value) -> Value {
+    let count = |name: &str| metadata.get(name).and_then(|v| v.as_u64());
+    let prompt = count("promptTokenCount").unwrap_or(0);
+    let completion = count("candidatesTokenCount").unwrap_or(0) + count("thoughtsTokenCount").unwrap_or(0);
+    
+    let mut usage = json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": completion,
+        "total_tokens": count("totalTokenCount").unwrap_or(prompt + completion)
+    });
+    if let Some(cached) = count("cachedContentTokenCount") {
+        usage["prompt_tokens_details"] = json!({ "cached_tokens": cached });
+    }
+    usage
+}
@@ -0,0 +1,63 @@
+use crate::client::{Message, RequestOptions};
+use crate::translate;
+use serde_json::{json, Value};
+
+// TGI's own default of 100 new tokens cuts most replies short
+const DEFAULT_MAX_NEW_TOKENS: u32 = 1024;
+
+// The serverless Inference API names the model in the URL (https://api-inference.huggingface.co/models/<model>);
+// a TGI server or dedicated endpoint serves one model at its root
+pub fn endpoint(url: &str, model: &str) -> String {
+    let url = url.trim_end_matches('/');
+    if url.ends_with("/models") {
+        format!("{}/{}", url, model)
+    } else {
+        url.to_string()
+    }
+}
+
+// Text generation takes a single prompt: raw text to continue, or the conversation flattened
+// the same way as for completion endpoints
+pub fn payload(messages: &[Message], prompt: &str, options: &RequestOptions, translated: bool) -> Value {
+    let mut parameters = json!({
+        "max_new_tokens": options.max_tokens.unwrap_or(DEFAULT_MAX_NEW_TOKENS),
+        "return_full_text": false,
+        "details": true
+    });
+    if translated {
+        parameters["stop"] = json!([translate::COMPLETION_STOP]);
+    }
+    // TGI rejects a temperature of 0; greedy decoding is what it means
+    match options.temperature {
+        Some(temperature) if temperature > 0.0 => parameters["temperature"] = json!(temperature),
+        Some(_) => parameters["do_sample"] = json!(false),
+        None => {}
+    }
+    
+    json!({
+        "inputs": if translated { translate::messages_to_prompt(messages) } else { prompt.to_string() },
+        "parameters": parameters,
+        "stream": options.stream
+    })
+}
+
+// TGI answers with an object, the Inference API with a one-element array of them
+pub fn content(response: &Value) -> Option<String> {
+    let generation = response.get(0).unwrap_or(response);
+    generation.get("generated_text").and_then(|text| text.as_str()).map(str::to_string)
+}
+
+pub fn stream_delta(event: &Value) -> Option<String> {
+    let token = event.get("token")?;
+    if token.get("special").and_then(|special| special.as_bool()).unwrap_or(false) {
+        return None;
+    }
+    token.get("text").and_then(|text| text.as_str()).map(str::to_string)
+}
+
+// Only generated tokens are counted; the prompt is estimated with the rest of the missing usage
+pub fn usage(response: &Value) -> Option<Value> {
+    let generation = response.get(0).unwrap_or(response);
+    let generated = generation.pointer("/details/generated_tokens").and_then(|v| v.as_u64())?;
+    Some(json!({ "completion_tokens": generated }))
+}
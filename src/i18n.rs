@@ -0,0 +1,138 @@
+use crate::error::CCSwitchError;
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use log::warn;
+use std::env;
+use std::sync::OnceLock;
+use unic_langid::LanguageIdentifier;
+
+// Catalogs compiled into the binary; the first entry is the fallback for missing messages
+const CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("zh-CN", include_str!("../locales/zh-CN.ftl")),
+];
+
+struct Catalog {
+    selected: FluentBundle<FluentResource>,
+    fallback: FluentBundle<FluentResource>,
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+// Picks the catalog from the `language` setting, falling back to the environment's locale
+pub fn init(language: Option<&str>) {
+    let requested = language
+        .filter(|language| *language != "auto")
+        .map(|language| language.to_string())
+        .or_else(env_language);
+    let _ = CATALOG.set(load(requested.as_deref()));
+}
+
+pub fn tr(key: &str, args: &[(&str, String)]) -> String {
+    let catalog = CATALOG.get_or_init(|| load(None));
+    format_message(&catalog.selected, key, args)
+        .or_else(|| format_message(&catalog.fallback, key, args))
+        .unwrap_or_else(|| key.to_string())
+}
+
+// Localized counterpart of the error's Display output, which stays English for --json
+pub fn error_message(error: &CCSwitchError) -> String {
+    let with_detail = |detail: &str| vec![("detail", detail.to_string())];
+    match error {
+        CCSwitchError::Config(detail) => tr("error-config", &with_detail(detail)),
+        CCSwitchError::Channel(detail) => tr("error-channel", &with_detail(detail)),
+        CCSwitchError::Upstream { status, message } => {
+            tr("error-upstream", &[("status", status.to_string()), ("detail", message.clone())])
+        }
+        CCSwitchError::Provider { channel, kind, message, .. } => tr("error-provider", &[
+            ("kind", tr(kind.key(), &[])),
+            ("channel", channel.clone()),
+            ("detail", message.clone()),
+        ]),
+        CCSwitchError::Network(e) => tr("error-network", &with_detail(&e.to_string())),
+        CCSwitchError::StreamInterrupted(detail) => tr("error-stream-interrupted", &with_detail(detail)),
+        CCSwitchError::Serialization(e) => tr("error-serialization", &with_detail(&e.to_string())),
+        CCSwitchError::Io(e) => tr("error-io", &with_detail(&e.to_string())),
+        CCSwitchError::ChannelNotFound(name) => tr("error-channel-not-found", &[("name", name.clone())]),
+        CCSwitchError::NoAvailableChannels(model) => tr("error-no-channels", &[("model", model.clone())]),
+        CCSwitchError::AllChannelsFailed(failures) => {
+            let mut message = tr("error-all-failed", &[]);
+            for failure in failures {
+                message.push_str(&format!("\n  - {}", failure));
+            }
+            message
+        }
+        CCSwitchError::Update(detail) => tr("error-update", &with_detail(detail)),
+        CCSwitchError::Auth(detail) => tr("error-auth", &with_detail(detail)),
+        CCSwitchError::Cancelled => tr("error-cancelled", &[]),
+        CCSwitchError::ReadOnly(action) => tr("error-read-only", &[("action", action.clone())]),
+        CCSwitchError::BudgetExceeded(detail) => tr("error-budget", &with_detail(detail)),
+        CCSwitchError::NothingExtracted(what) => tr("error-nothing-extracted", &[("what", what.clone())]),
+        // Stored as it was displayed when it happened
+        CCSwitchError::Recorded { message, .. } => message.clone(),
+    }
+}
+
+fn env_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        // "zh_CN.UTF-8" -> "zh-CN"
+        .map(|value| value.split('.').next().unwrap_or_default().replace('_', "-"))
+}
+
+fn load(requested: Option<&str>) -> Catalog {
+    let (fallback_id, fallback_source) = CATALOGS[0];
+    let (selected_id, selected_source) = requested
+        .and_then(find_catalog)
+        .unwrap_or(CATALOGS[0]);
+    
+    Catalog {
+        selected: bundle(selected_id, selected_source),
+        fallback: bundle(fallback_id, fallback_source),
+    }
+}
+
+// Exact match first, then by primary language ("zh-TW" still beats English for a "zh" catalog)
+fn find_catalog(requested: &str) -> Option<(&'static str, &'static str)> {
+    let requested: LanguageIdentifier = requested.parse().ok()?;
+    CATALOGS
+        .iter()
+        .find(|(id, _)| id.parse::<LanguageIdentifier>().is_ok_and(|id| id == requested))
+        .or_else(|| {
+            CATALOGS.iter().find(|(id, _)| {
+                id.parse::<LanguageIdentifier>().is_ok_and(|id| id.language == requested.language)
+            })
+        })
+        .copied()
+}
+
+fn bundle(id: &str, source: &str) -> FluentBundle<FluentResource> {
+    let language: LanguageIdentifier = id.parse().expect("catalog ids are valid language tags");
+    let mut bundle = FluentBundle::new_concurrent(vec![language]);
+    // Isolation marks around arguments show up as stray characters in terminals
+    bundle.set_use_isolating(false);
+    
+    let resource = match FluentResource::try_new(source.to_string()) {
+        Ok(resource) => resource,
+        Err((resource, errors)) => {
+            warn!("Message catalog '{}' has {} syntax errors", id, errors.len());
+            resource
+        }
+    };
+    let _ = bundle.add_resource(resource);
+    bundle
+}
+
+fn format_message(bundle: &FluentBundle<FluentResource>, key: &str, args: &[(&str, String)]) -> Option<String> {
+    let pattern = bundle.get_message(key)?.value()?;
+    
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, value.clone());
+    }
+    
+    let mut errors = Vec::new();
+    Some(bundle.format_pattern(pattern, Some(&fluent_args), &mut errors).into_owned())
+}
@@ -0,0 +1,262 @@
+use crate::client::{APIClient, APIResponse, RequestOptions};
+use crate::config::{now_secs, Config, EmailEvent};
+use crate::email;
+use crate::error::{CCSwitchError, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use uuid::Uuid;
+
+// A running worker refreshes its heartbeat this often and checks for cancellation
+const HEARTBEAT_SECS: u64 = 5;
+// Without a heartbeat for this long the worker is assumed dead (e.g. the machine restarted)
+const STALE_SECS: u64 = HEARTBEAT_SECS * 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+    // The worker went away before finishing
+    Interrupted,
+}
+
+impl JobStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+            JobStatus::Interrupted => "interrupted",
+        }
+    }
+    
+    pub fn is_finished(self) -> bool {
+        !matches!(self, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub prompt: String,
+    pub model: Option<String>,
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    pub system: Option<String>,
+    // Name of the schedule that started this job, if any
+    #[serde(default)]
+    pub schedule: Option<String>,
+    pub status: JobStatus,
+    pub submitted_at: u64,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub heartbeat_at: Option<u64>,
+    pub response: Option<APIResponse>,
+    pub error: Option<String>,
+    // `CCSwitchError::code` of the failure, so `jobs result` exits as the request would have
+    #[serde(default)]
+    pub error_code: Option<String>,
+}
+
+impl Job {
+    pub fn new(prompt: String, options: &RequestOptions) -> Self {
+        Self {
+            id: format!("{}-{}", now_secs(), &Uuid::new_v4().simple().to_string()[..8]),
+            prompt,
+            model: options.model.clone(),
+            max_tokens: options.max_tokens,
+            temperature: options.temperature,
+            system: options.system.clone(),
+            schedule: None,
+            status: JobStatus::Queued,
+            submitted_at: now_secs(),
+            started_at: None,
+            finished_at: None,
+            heartbeat_at: None,
+            response: None,
+            error: None,
+            error_code: None,
+        }
+    }
+    
+    fn options(&self) -> RequestOptions {
+        RequestOptions {
+            model: self.model.clone(),
+            max_tokens: self.max_tokens,
+            temperature: self.temperature,
+            system: self.system.clone(),
+            ..RequestOptions::default()
+        }
+    }
+    
+    fn is_stale(&self) -> bool {
+        let last_sign_of_life = match self.status {
+            JobStatus::Queued => self.submitted_at,
+            JobStatus::Running => self.heartbeat_at.unwrap_or(self.submitted_at),
+            _ => return false,
+        };
+        now_secs().saturating_sub(last_sign_of_life) > STALE_SECS
+    }
+}
+
+pub fn jobs_dir() -> Result<PathBuf> {
+    Ok(Config::data_dir()?.join("jobs"))
+}
+
+pub fn save(job: &Job) -> Result<()> {
+    let dir = jobs_dir()?;
+    fs::create_dir_all(&dir)?;
+    
+    // Write then rename so a reader never sees half a file
+    let temp_path = dir.join(format!(".{}.json.tmp", job.id));
+    fs::write(&temp_path, serde_json::to_string_pretty(job)?)?;
+    fs::rename(temp_path, dir.join(format!("{}.json", job.id)))?;
+    Ok(())
+}
+
+pub fn load(id: &str) -> Result<Job> {
+    let path = jobs_dir()?.join(format!("{}.json", id));
+    if !path.exists() {
+        return Err(CCSwitchError::Config(format!("Job '{}' not found", id)));
+    }
+    
+    let mut job: Job = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if job.is_stale() {
+        job.status = JobStatus::Interrupted;
+        job.error = Some("the background worker stopped before the job finished".to_string());
+        job.error_code = Some(CCSwitchError::Cancelled.code().to_string());
+        save(&job)?;
+    }
+    Ok(job)
+}
+
+// Oldest first
+pub fn list() -> Result<Vec<Job>> {
+    let dir = jobs_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut jobs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        let Some(id) = name.strip_suffix(".json").filter(|id| !id.starts_with('.')) else {
+            continue;
+        };
+        match load(id) {
+            Ok(job) => jobs.push(job),
+            Err(e) => warn!("Skipping job {}: {}", id, e),
+        }
+    }
+    jobs.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(jobs)
+}
+
+// Returns false if the job had already finished
+pub fn cancel(id: &str) -> Result<bool> {
+    let mut job = load(id)?;
+    if job.status.is_finished() {
+        return Ok(false);
+    }
+    
+    job.status = JobStatus::Cancelled;
+    job.finished_at = Some(now_secs());
+    save(&job)?;
+    Ok(true)
+}
+
+// Puts a failed, interrupted or cancelled job back in the queue; the caller starts its worker
+pub fn retry(id: &str) -> Result<Job> {
+    let mut job = load(id)?;
+    if !matches!(job.status, JobStatus::Failed | JobStatus::Interrupted | JobStatus::Cancelled) {
+        return Err(CCSwitchError::Config(format!("Job '{}' is {}; only failed, interrupted or cancelled jobs can be retried", id, job.status.as_str())));
+    }
+    
+    job.status = JobStatus::Queued;
+    job.submitted_at = now_secs();
+    job.started_at = None;
+    job.finished_at = None;
+    job.heartbeat_at = None;
+    job.response = None;
+    job.error = None;
+    job.error_code = None;
+    save(&job)?;
+    Ok(job)
+}
+
+// The failure of a failed or interrupted job, with the code it had when it happened
+pub fn failure(job: &Job) -> Option<CCSwitchError> {
+    let error = job.error.clone()?;
+    Some(CCSwitchError::recorded(job.error_code.as_deref().unwrap_or_default(), error))
+}
+
+// Body of the background worker process started by `submit`
+pub async fn execute(client: &APIClient, id: &str) -> Result<()> {
+    let mut job = load(id)?;
+    if job.status != JobStatus::Queued {
+        return Ok(());
+    }
+    
+    // From here on every write starts from the file, so a `cancel` landing in between is never overwritten
+    info!("Running job {}", id);
+    job.status = JobStatus::Running;
+    job.started_at = Some(now_secs());
+    job.heartbeat_at = job.started_at;
+    save(&job)?;
+    
+    let options = job.options();
+    let request = client.make_request(&job.prompt, options);
+    tokio::pin!(request);
+    let mut heartbeat = tokio::time::interval(Duration::from_secs(HEARTBEAT_SECS));
+    
+    let result = loop {
+        tokio::select! {
+            result = &mut request => break result,
+            _ = heartbeat.tick() => {
+                let mut current = load(id)?;
+                if current.status != JobStatus::Running {
+                    info!("Job {} was {}", id, current.status.as_str());
+                    return Ok(());
+                }
+                current.heartbeat_at = Some(now_secs());
+                save(&current)?;
+            }
+        }
+    };
+    
+    // A cancel that raced with the reply wins
+    let mut job = load(id)?;
+    if job.status == JobStatus::Cancelled {
+        return Ok(());
+    }
+    
+    match result {
+        Ok(response) => {
+            job.status = JobStatus::Succeeded;
+            job.response = Some(response);
+        }
+        Err(e) => {
+            job.status = JobStatus::Failed;
+            job.error = Some(e.to_string());
+            job.error_code = Some(e.code().to_string());
+        }
+    }
+    job.finished_at = Some(now_secs());
+    save(&job)?;
+    
+    let body = match (&job.response, &job.error) {
+        (Some(response), _) => response.content.clone(),
+        (None, error) => error.clone().unwrap_or_default(),
+    };
+    let subject = format!("[ccswitch] Job {} {}", job.id, job.status.as_str());
+    email::notify(&client.get_channel_manager().config.email, EmailEvent::JobFinished, &subject, &body).await;
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use crate::tokens::is_cjk;
+use serde::{Deserialize, Serialize};
+
+// Languages `--language` can ask for and the detector can tell apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    Zh,
+    En,
+    Ja,
+    Ko,
+    Ru,
+    Fr,
+    De,
+    Es,
+    It,
+    Pt,
+}
+
+// Frequent function words, enough to separate Latin-script languages in a reply of a sentence or more
+const STOPWORDS: &[(Language, &[&str])] = &[
+    (Language::En, &["the", "and", "is", "are", "of", "to", "in", "that", "it", "with", "for", "this", "you", "not", "be", "have"]),
+    (Language::Fr, &["le", "la", "les", "et", "est", "des", "une", "un", "que", "pour", "dans", "pas", "vous", "sur", "avec", "qui"]),
+    (Language::De, &["der", "die", "das", "und", "ist", "nicht", "ein", "eine", "zu", "mit", "sie", "ich", "auf", "für", "den", "sich"]),
+    (Language::Es, &["el", "los", "las", "y", "es", "que", "una", "por", "para", "con", "no", "del", "se", "su", "como", "está"]),
+    (Language::It, &["il", "gli", "e", "è", "che", "una", "per", "con", "non", "della", "di", "sono", "questo", "come", "anche", "nel"]),
+    (Language::Pt, &["o", "os", "as", "e", "é", "que", "uma", "um", "para", "com", "não", "do", "da", "em", "você", "mais"]),
+];
+
+impl Language {
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Zh => "Chinese",
+            Language::En => "English",
+            Language::Ja => "Japanese",
+            Language::Ko => "Korean",
+            Language::Ru => "Russian",
+            Language::Fr => "French",
+            Language::De => "German",
+            Language::Es => "Spanish",
+            Language::It => "Italian",
+            Language::Pt => "Portuguese",
+        }
+    }
+    
+    // Appended to the last user message
+    pub fn instruction(self) -> String {
+        format!("Respond only in {}.", self.name())
+    }
+    
+    // Replies the detector cannot place (code, numbers, a word or two) are accepted
+    pub fn matches(self, text: &str) -> bool {
+        detect(text).is_none_or(|detected| detected == self)
+    }
+}
+
+// Best guess at the language of a reply, ignoring fenced code blocks
+pub fn detect(text: &str) -> Option<Language> {
+    let prose: String = text.split("```").step_by(2).collect::<Vec<_>>().join(" ");
+    
+    let (mut han, mut kana, mut hangul, mut cyrillic) = (0usize, 0usize, 0usize, 0usize);
+    for c in prose.chars() {
+        match c as u32 {
+            0x3040..=0x30FF => kana += 1,
+            0xAC00..=0xD7AF => hangul += 1,
+            0x0400..=0x04FF => cyrillic += 1,
+            _ if is_cjk(c) => han += 1,
+            _ => {}
+        }
+    }
+    
+    let words: Vec<String> = prose
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty() && word.chars().all(|c| c.is_ascii_alphabetic() || ('\u{00C0}'..='\u{024F}').contains(&c)))
+        .map(str::to_lowercase)
+        .collect();
+    
+    // Character counts turn into rough word counts: about two per CJK word, six per Russian one
+    let cjk_words = (han + kana + hangul) / 2;
+    let cyrillic_words = cyrillic / 6;
+    if cjk_words.max(cyrillic_words).max(words.len()) < 2 {
+        return None;
+    }
+    
+    if cjk_words >= words.len() && cjk_words >= cyrillic_words {
+        // Japanese mixes kanji with kana, Chinese has none
+        return Some(if hangul > han + kana {
+            Language::Ko
+        } else if kana * 10 >= han + kana {
+            Language::Ja
+        } else {
+            Language::Zh
+        });
+    }
+    if cyrillic_words > words.len() {
+        return Some(Language::Ru);
+    }
+    
+    let scores: Vec<(Language, usize)> = STOPWORDS.iter()
+        .map(|(language, stopwords)| (*language, words.iter().filter(|word| stopwords.contains(&word.as_str())).count()))
+        .collect();
+    let best = scores.iter().map(|(_, score)| *score).max().unwrap_or(0);
+    
+    // No stopwords, or a tie, is too little to go on
+    let mut leaders = scores.iter().filter(|(_, score)| *score == best);
+    match (leaders.next(), leaders.next()) {
+        (Some((language, _)), None) if best > 0 => Some(*language),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn detects_scripts_and_stopwords() {
+        assert_eq!(detect("这是一个用于测试的中文句子。"), Some(Language::Zh));
+        assert_eq!(detect("これはテストのための日本語の文です。"), Some(Language::Ja));
+        assert_eq!(detect("이것은 테스트를 위한 한국어 문장입니다."), Some(Language::Ko));
+        assert_eq!(detect("Это предложение на русском языке для проверки."), Some(Language::Ru));
+        assert_eq!(detect("This is the answer to the question, and it is not long."), Some(Language::En));
+        assert_eq!(detect("Le chat est sur la table et il dort dans la maison."), Some(Language::Fr));
+        assert_eq!(detect("Der Hund ist nicht in der Küche und das ist gut."), Some(Language::De));
+    }
+    
+    #[test]
+    fn code_and_short_replies_are_not_placed() {
+        assert_eq!(detect("42"), None);
+        assert_eq!(detect("OK"), None);
+        assert_eq!(detect("这是中文的说明。\n```\nthe and is are of to in that it with\n```"), Some(Language::Zh));
+        assert!(Language::En.matches("fn main() {}"));
+    }
+}
@@ -0,0 +1,71 @@
+//! Automatic switching between multiple model API channels.
+//!
+//! The `ccswitch` binary is a thin front end over this library; other programs can embed
+//! the same channel selection, health checking and failover:
+//!
+//! ```no_run
+//! # async fn run() -> ccswitch::Result<()> {
+//! let client = ccswitch::Client::new()?;
+//! let response = client.make_request("Hello", ccswitch::RequestOptions::default()).await?;
+//! println!("{} answered: {}", response.channel_used, response.content);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod channel;
+pub mod client;
+pub mod config;
+pub mod consensus;
+pub mod context;
+pub mod email;
+pub mod error;
+pub mod events;
+pub mod expiry;
+pub mod extract;
+pub mod format;
+pub mod i18n;
+pub mod jobs;
+pub mod language;
+pub mod metrics;
+pub mod pipeline;
+pub mod probe;
+pub mod providers;
+pub mod proxy;
+pub mod rank;
+pub mod report;
+pub mod routing;
+pub mod rpc;
+pub mod runs;
+pub mod schedule;
+pub mod serve;
+pub mod sessions;
+pub mod stats;
+pub mod update;
+
+mod anthropic;
+mod azure;
+mod bedrock;
+mod breaker;
+mod budget;
+mod cache;
+mod credentials;
+mod dns;
+mod gemini;
+mod huggingface;
+mod ollama;
+mod openrouter;
+mod postprocess;
+mod pricing;
+mod ratelimit;
+mod signing;
+mod tokens;
+mod translate;
+mod upstream;
+mod users;
+mod vllm;
+
+pub use channel::{ChannelFilter, ChannelManager, ChannelStatus, ChannelUpdate};
+pub use client::{APIClient as Client, APIResponse, Message, RequestOptions};
+pub use config::{Channel, Config};
+pub use error::{CCSwitchError, Result};
+pub use events::RequestEvent;
@@ -1,53 +1,267 @@
-mod config;
-mod channel;
-mod client;
-mod error;
-
-use clap::{Parser, Subcommand};
-use channel::ChannelManager;
-use client::{APIClient, RequestOptions};
-use error::Result;
-use log::info;
+use ccswitch::{channel, client, config, consensus, context, email, error, events, expiry, extract, format, i18n, jobs, language, metrics, pipeline, probe, providers, proxy, rank, report, rpc, runs, serve, sessions, stats, update};
+use clap::{ArgGroup, Parser, Subcommand};
+use channel::{ChannelManager, ChannelUpdate};
+use client::{APIClient, APIResponse, Message, RequestOptions};
+use config::{ApiFormat, EndpointKind};
+use providers::Provider;
+use error::{CCSwitchError, Result};
+use events::RequestEvent;
+use format::Formatter;
+use i18n::tr;
+use metrics::RequestMetrics;
+use update::{ReleaseChannel, UpdateOutcome, Updater};
+use log::{info, warn};
+use futures::future;
+use futures::stream::{self, StreamExt};
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 #[derive(Parser)]
 #[command(name = "ccswitch")]
 #[command(about = "A CLI tool for automatic switching between multiple model API channels")]
 #[command(version)]
 struct Cli {
+    /// Emit machine-readable JSON output (including errors)
+    #[arg(long, global = true)]
+    json: bool,
     #[command(subcommand)]
     command: Commands,
 }
 
+// Parsed once per run, so the size of the `request` variant costs nothing worth boxing for
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Add a new channel configuration
     Add {
         /// Channel name
         name: String,
-        /// API endpoint URL
-        url: String,
+        /// API endpoint URL (the preset's when --provider is given)
+        #[arg(required_unless_present = "provider")]
+        url: Option<String>,
         /// API key
         #[arg(short, long)]
         key: Option<String>,
-        /// Model name
+        /// Comma-separated model names the channel serves (all models when omitted)
+        #[arg(short, long = "model", value_delimiter = ',')]
+        models: Vec<String>,
+        /// API style exposed by the endpoint
+        #[arg(long, value_enum, default_value = "chat")]
+        endpoint_kind: EndpointKind,
+        /// Request and response format of the provider
+        #[arg(long, value_enum, default_value = "openai")]
+        api_format: ApiFormat,
+        /// Fill in the URL, models and request quirks of a known provider
+        #[arg(long, value_enum, conflicts_with_all = ["endpoint_kind", "api_format"])]
+        provider: Option<Provider>,
+        /// Probe the new channel and save its capabilities, to check that it works
+        #[arg(long)]
+        probe: bool,
+    },
+    /// Change a channel in place, keeping its priority, enabled state and other settings
+    #[command(group(ArgGroup::new("changes").required(true).multiple(true).args(["url", "key", "models", "priority"])))]
+    Update {
+        /// Channel name
+        name: String,
+        /// New API endpoint URL
+        #[arg(long)]
+        url: Option<String>,
+        /// New API key; `-` reads it from stdin
         #[arg(short, long)]
-        model: Option<String>,
+        key: Option<String>,
+        /// Comma-separated model names the channel serves
+        #[arg(short, long = "model", value_delimiter = ',')]
+        models: Option<Vec<String>>,
+        /// Priority (lower is tried first)
+        #[arg(long)]
+        priority: Option<u32>,
+    },
+    /// Set the priority of a channel (lower is tried first)
+    Priority {
+        /// Channel name
+        name: String,
+        value: u32,
     },
+    /// Move channels up and down interactively and save the order as their priorities
+    Reorder,
     /// List all configured channels
-    List,
-    /// Remove a channel
+    List {
+        /// Show removed channels that can still be restored
+        #[arg(long)]
+        removed: bool,
+        /// Only list channels carrying all of these comma-separated tags
+        #[arg(long, value_delimiter = ',', conflicts_with = "removed")]
+        tags: Vec<String>,
+    },
+    /// Show the full configuration of a channel
+    Show {
+        /// Channel name
+        name: String,
+    },
+    /// Remove a channel (it can be restored until the trash retention expires)
     Remove {
         /// Channel name to remove
         name: String,
     },
+    /// Restore a previously removed channel
+    Restore {
+        /// Channel name to restore
+        name: String,
+    },
     /// Test channel availability
     Test {
         /// Channel name to test (if not specified, test all)
         name: Option<String>,
+        /// Print each result as soon as it is known instead of in order
+        #[arg(long, conflicts_with = "name")]
+        stream_results: bool,
+        /// Also test disabled channels; auto-disabled ones that pass are re-enabled
+        #[arg(long, conflicts_with = "name")]
+        include_disabled: bool,
+    },
+    /// Probe every channel repeatedly and suggest a priority order
+    Rank {
+        /// Probes per channel
+        #[arg(long, default_value_t = 5)]
+        samples: usize,
+        /// Save the suggested priorities to the config
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Detect which features a channel supports and save them as its capabilities
+    Probe {
+        /// Channel name
+        channel: String,
+        /// Model to probe with (defaults to the channel's model)
+        #[arg(short, long)]
+        model: Option<String>,
+    },
+    /// Send one prompt to several channels at once and show the replies side by side
+    Compare {
+        prompt: String,
+        /// Comma-separated channel names (defaults to every enabled channel serving the model)
+        #[arg(long, value_delimiter = ',')]
+        channels: Vec<String>,
+        /// Preferred model name
+        #[arg(short, long)]
+        model: Option<String>,
+        /// Maximum tokens
+        #[arg(long)]
+        max_tokens: Option<u32>,
+        /// Temperature (0.0-2.0)
+        #[arg(short, long)]
+        temperature: Option<f32>,
+        /// System prompt sent before the prompt
+        #[arg(long)]
+        system: Option<String>,
     },
     /// Make a request with automatic channel switching
     Request {
         /// The prompt/message to send
+        #[arg(required_unless_present = "each")]
+        prompt: Option<String>,
+        /// Send every non-empty line of this file as an independent prompt
+        #[arg(long, conflicts_with = "prompt")]
+        each: Option<PathBuf>,
+        /// Number of prompts in flight at once with --each
+        #[arg(long, default_value_t = 4, requires = "each")]
+        concurrency: usize,
+        /// Also write the --each run report to this file as JSON
+        #[arg(long, requires = "each")]
+        report: Option<PathBuf>,
+        /// Preferred model name
+        #[arg(short, long)]
+        model: Option<String>,
+        /// Maximum tokens
+        #[arg(long)]
+        max_tokens: Option<u32>,
+        /// Temperature (0.0-2.0)
+        #[arg(short, long)]
+        temperature: Option<f32>,
+        /// Send the prompt as raw text to continue instead of a chat message
+        #[arg(long)]
+        completion: bool,
+        /// Never prompt to choose between equally ranked channels
+        #[arg(long)]
+        no_interactive: bool,
+        /// Emit newline-delimited JSON progress events on stdout
+        #[arg(long, conflicts_with = "each")]
+        json_stream: bool,
+        /// Stream the reply and print it as it arrives
+        #[arg(long, conflicts_with = "each")]
+        stream: bool,
+        /// Apply a named preset from the config (explicit flags take precedence)
+        #[arg(long)]
+        preset: Option<String>,
+        /// Request log probabilities, optionally with N top alternatives per token
+        #[arg(long, num_args = 0..=1, default_missing_value = "0")]
+        logprobs: Option<u8>,
+        /// System prompt sent before the user message (overrides the preset and config defaults)
+        #[arg(long, conflicts_with = "completion")]
+        system: Option<String>,
+        /// Always ask a channel, even if the response cache has a fresh reply
+        #[arg(long)]
+        no_cache: bool,
+        /// Add this exchange to a named conversation session, creating it if needed
+        #[arg(long, conflicts_with_all = ["each", "completion"])]
+        session: Option<String>,
+        /// Continue the most recently used conversation session
+        #[arg(long = "continue", conflicts_with_all = ["each", "completion", "session"])]
+        continue_session: bool,
+        /// Ask for the reply in this language and retry replies in another one
+        #[arg(long, value_enum)]
+        language: Option<language::Language>,
+        /// Send to the top two channels at once and keep the first reply (not for --stream)
+        #[arg(long)]
+        hedge: bool,
+        /// Ask this many channels and return the majority answer, or a judge's synthesis when they differ
+        #[arg(long, conflicts_with_all = ["each", "stream", "json_stream", "completion"])]
+        consensus: Option<usize>,
+        /// Only route to channels carrying all of these comma-separated tags
+        #[arg(long, value_delimiter = ',')]
+        tags: Vec<String>,
+        /// Provider-specific request field as KEY=VALUE, e.g. top_k=20 for vLLM (repeatable; sent to channels listing it in sampling_params)
+        #[arg(long = "param", value_parser = parse_param)]
+        params: Vec<(String, serde_json::Value)>,
+        /// Draft and refine the reply with a configured pipeline
+        #[arg(long, conflicts_with_all = ["each", "completion", "consensus"])]
+        pipeline: Option<String>,
+        /// Print only part of the reply: codeblock[:lang], json or regex:<pattern>
+        #[arg(long, conflicts_with_all = ["each", "stream", "json_stream"])]
+        extract: Option<extract::Extract>,
+        /// Seed the random, round_robin and weighted strategies so the same channels are picked every time
+        #[arg(long)]
+        selection_seed: Option<u64>,
+        /// Label the request's usage and history as KEY=VALUE, e.g. project=alpha (repeatable; see `stats --by-label`)
+        #[arg(long = "label", value_parser = parse_label)]
+        labels: Vec<(String, String)>,
+    },
+    /// Start an interactive multi-turn conversation
+    Chat {
+        /// Preferred model name
+        #[arg(short, long)]
+        model: Option<String>,
+        /// Maximum tokens per reply
+        #[arg(long)]
+        max_tokens: Option<u32>,
+        /// Temperature (0.0-2.0)
+        #[arg(short, long)]
+        temperature: Option<f32>,
+        /// Apply a named preset from the config (explicit flags take precedence)
+        #[arg(long)]
+        preset: Option<String>,
+        /// Resume or start a named conversation session that is saved after every turn
+        #[arg(long)]
+        session: Option<String>,
+        /// System prompt for the conversation (overrides the preset and config defaults)
+        #[arg(long)]
+        system: Option<String>,
+    },
+    /// Queue a request to run in the background and print its job ID
+    Submit {
+        /// The prompt to send
         prompt: String,
         /// Preferred model name
         #[arg(short, long)]
@@ -58,91 +272,832 @@ enum Commands {
         /// Temperature (0.0-2.0)
         #[arg(short, long)]
         temperature: Option<f32>,
+        /// Apply a named preset from the config (explicit flags take precedence)
+        #[arg(long)]
+        preset: Option<String>,
+        /// System prompt sent before the user message (overrides the preset and config defaults)
+        #[arg(long)]
+        system: Option<String>,
+    },
+    /// Inspect and manage background jobs started with `submit`
+    Jobs {
+        #[command(subcommand)]
+        command: JobsCommand,
+    },
+    /// Show request counts, token usage and latency per channel and model
+    Stats {
+        /// Time window to aggregate, ending now
+        #[arg(long, value_enum, default_value_t = stats::Period::Week)]
+        period: stats::Period,
+        /// Also group requests by the value of this `--label` key
+        #[arg(long)]
+        by_label: Option<String>,
+    },
+    /// Manage saved conversation sessions
+    Sessions {
+        #[command(subcommand)]
+        command: SessionsCommand,
+    },
+    /// Manage the stored artifacts of `request --each` runs
+    Runs {
+        #[command(subcommand)]
+        command: RunsCommand,
+    },
+    /// Serve JSON-RPC requests over stdin/stdout for editor integrations
+    Rpc,
+    /// Run a local OpenAI-compatible HTTP proxy that routes through the configured channels
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        /// Port to listen on
+        #[arg(short, long, default_value_t = 8080)]
+        port: u16,
+    },
+    /// Update ccswitch to the latest GitHub release
+    SelfUpdate {
+        /// Release channel to follow
+        #[arg(long, value_enum, default_value = "stable")]
+        channel: ReleaseChannel,
+    },
+}
+
+#[derive(Subcommand)]
+enum RunsCommand {
+    /// List stored runs
+    List,
+    /// Show the report of a run
+    Show {
+        /// Run ID
+        id: String,
+    },
+    /// Delete stored runs
+    Clean {
+        /// Only delete runs started more than this many days ago
+        #[arg(long)]
+        older_than_days: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SessionsCommand {
+    /// List sessions, most recently used first
+    List,
+    /// Print the transcript of a session
+    Show {
+        /// Session name
+        name: String,
+    },
+    /// Delete a session
+    Delete {
+        /// Session name
+        name: String,
+    },
+    /// Summarize a session with `context.summary_model`
+    Summarize {
+        /// Session name
+        name: String,
+        /// Store the summary in place of all but the last `--keep` turns
+        #[arg(long)]
+        replace: bool,
+        /// Recent turns kept word for word with `--replace`
+        #[arg(long, default_value_t = 4, requires = "replace")]
+        keep: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum JobsCommand {
+    /// List jobs
+    List,
+    /// Show the status and details of a job
+    Show {
+        /// Job ID
+        id: String,
+    },
+    /// Cancel a queued or running job
+    Cancel {
+        /// Job ID
+        id: String,
+    },
+    /// Print the reply of a finished job
+    Result {
+        /// Job ID
+        id: String,
+    },
+    /// Queue a failed, interrupted or cancelled job again
+    Retry {
+        /// Job ID
+        id: String,
+    },
+    /// Execute a queued job (used by `submit`)
+    #[command(hide = true)]
+    Run {
+        /// Job ID
+        id: String,
     },
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
     env_logger::init();
     
     let cli = Cli::parse();
+    let json = cli.json;
+    
+    let language = config::Config::load().ok().and_then(|config| config.language);
+    i18n::init(language.as_deref());
     
+    if let Err(e) = run(cli).await {
+        report_error(&e, json);
+        std::process::exit(e.exit_code());
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
     match cli.command {
-        Commands::Add { name, url, key, model } => {
+        Commands::Add { name, url, key, models, endpoint_kind, api_format, provider, probe } => {
             info!("Adding channel: {}", name);
             let mut manager = ChannelManager::new()?;
-            manager.add_channel(name.clone(), url, key, model)?;
-            println!("✓ Channel '{}' added successfully", name);
+            // clap requires a URL unless a provider is given
+            let url = url.or_else(|| provider.map(|provider| provider.url().to_string())).unwrap_or_default();
+            let models = match provider {
+                Some(provider) if models.is_empty() => provider.models(),
+                _ => models,
+            };
+            manager.add_channel(name.clone(), url, key, models, endpoint_kind, api_format)?;
+            if let Some(provider) = provider {
+                manager.config.update_channel(&name, |channel| provider.configure(channel))?;
+            }
+            println!("{}", tr("channel-added", &[("name", name.clone())]));
+            
+            if probe {
+                probe_and_save(&name, None, cli.json).await?;
+            }
+        }
+        Commands::Update { name, url, key, models, priority } => {
+            info!("Updating channel: {}", name);
+            let api_key = match key.as_deref() {
+                Some("-") => Some(read_key_from_stdin()?),
+                _ => key,
+            };
+            let mut manager = ChannelManager::new()?;
+            manager.update_channel(&name, ChannelUpdate { url, api_key, models, priority })?;
+            println!("{}", tr("channel-updated", &[("name", name)]));
+        }
+        Commands::Priority { name, value } => {
+            let mut manager = ChannelManager::new()?;
+            manager.update_channel(&name, ChannelUpdate { priority: Some(value), ..ChannelUpdate::default() })?;
+            println!("{}", tr("priority-set", &[("name", name), ("priority", value.to_string())]));
+        }
+        Commands::Reorder => {
+            let manager = ChannelManager::new()?;
+            manager.config.ensure_writable("reorder channels")?;
+            if !std::io::stdin().is_terminal() {
+                return Err(CCSwitchError::Config("reorder needs an interactive terminal; use `ccswitch priority` in scripts".to_string()));
+            }
+            let mut channels: Vec<_> = manager.list_channels().into_iter().collect();
+            channels.sort_by(|a, b| a.priority.cmp(&b.priority).then_with(|| a.name.cmp(&b.name)));
+            let names: Vec<String> = channels.into_iter().map(|channel| channel.name.clone()).collect();
+            
+            let Some(order) = reorder_interactively(names)? else {
+                println!("{}", tr("reorder-cancelled", &[]));
+                return Ok(());
+            };
+            // Positions become the priorities, as with `rank --apply`; the session may have been long,
+            // so only priorities are written over the config as it is now
+            save_priorities(order.iter().enumerate().map(|(index, name)| (name.as_str(), index as u32)))?;
+            println!("{}", tr("reorder-saved", &[]));
         }
-        Commands::List => {
+        Commands::List { removed: true, .. } => {
+            info!("Listing removed channels");
+            let manager = ChannelManager::new()?;
+            let retention_secs = manager.config.trash_retention_days * 24 * 60 * 60;
+            
+            if manager.config.removed.is_empty() {
+                println!("{}", tr("trash-empty", &[]));
+            } else {
+                println!("{}", tr("trash-header", &[]));
+                for removed in manager.config.removed.values() {
+                    let expires_in = (removed.removed_at + retention_secs).saturating_sub(config::now_secs());
+                    println!("  {}", tr("trash-entry", &[
+                        ("name", removed.channel.name.clone()),
+                        ("url", removed.channel.url.clone()),
+                        ("days", (expires_in / (24 * 60 * 60)).to_string()),
+                    ]));
+                }
+            }
+        }
+        Commands::List { removed: false, tags } => {
             info!("Listing all channels");
             let manager = ChannelManager::new()?;
-            let channels = manager.list_channels();
+            let channels: Vec<_> = manager.list_channels().into_iter().filter(|channel| channel.has_tags(&tags)).collect();
             
             if channels.is_empty() {
-                println!("No channels configured");
+                println!("{}", tr("channels-none", &[]));
             } else {
-                println!("Configured channels:");
+                println!("{}", tr("channels-header", &[]));
                 for channel in channels {
-                    let status = if channel.enabled { "enabled" } else { "disabled" };
-                    let model_info = channel.model.as_deref().unwrap_or("any");
-                    println!("  {} [{}] - {} (model: {})", 
-                        channel.name, status, channel.url, model_info);
+                    println!("  {}", tr("channel-entry", &[
+                        ("name", channel.name.clone()),
+                        ("status", status_label(channel)),
+                        ("url", channel.url.clone()),
+                        ("model", model_label(channel)),
+                        ("proxy", proxy::describe(channel)),
+                    ]));
+                    if !channel.tags.is_empty() {
+                        println!("    {}", tr("channel-tags", &[("tags", channel.tags.join(", "))]));
+                    }
+                    print_key_warning(channel, &manager.config.key_expiry);
                 }
             }
         }
+        Commands::Show { name } => {
+            let manager = ChannelManager::new()?;
+            let mut channel = manager.config.get_channel(&name)
+                .ok_or_else(|| CCSwitchError::ChannelNotFound(name.clone()))?
+                .clone();
+            channel.api_key = channel.api_key.as_deref().map(mask_key);
+            
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&channel)?);
+            } else {
+                print_channel_details(&channel);
+                print_key_warning(&channel, &manager.config.key_expiry);
+            }
+        }
         Commands::Remove { name } => {
             info!("Removing channel: {}", name);
             let mut manager = ChannelManager::new()?;
             manager.remove_channel(&name)?;
-            println!("✓ Channel '{}' removed successfully", name);
+            println!("{}", tr("channel-removed", &[("name", name.clone())]));
+            println!("  {}", tr("channel-undo-hint", &[("name", name)]));
         }
-        Commands::Test { name } => {
+        Commands::Restore { name } => {
+            info!("Restoring channel: {}", name);
+            let mut manager = ChannelManager::new()?;
+            manager.restore_channel(&name)?;
+            println!("{}", tr("channel-restored", &[("name", name)]));
+        }
+        Commands::Test { name, stream_results, include_disabled } => {
             info!("Testing channel availability");
             let manager = ChannelManager::new()?;
             
             match name {
                 Some(channel_name) => {
                     if let Some(channel) = manager.config.get_channel(&channel_name) {
-                        println!("Testing channel: {}", channel_name);
+                        println!("{}", tr("testing-channel", &[("name", channel_name.clone())]));
                         let status = manager.test_channel(channel).await;
-                        print_channel_status(&status);
+                        print_channel_status(&status, &Formatter::new(&manager.config.display));
+                        print_key_warning(channel, &manager.config.key_expiry);
+                        expiry::notify(&manager.config, &[channel]).await;
+                        notify_channels_down(&manager.config, std::slice::from_ref(&status)).await;
+                        reenable_recovered(&manager.config, std::slice::from_ref(&status))?;
                     } else {
-                        println!("❌ Channel '{}' not found", channel_name);
+                        return Err(CCSwitchError::ChannelNotFound(channel_name));
                     }
                 }
                 None => {
-                    println!("Testing all channels:");
-                    let results = manager.test_all_channels().await;
-                    for status in results {
-                        print_channel_status(&status);
-                    }
+                    println!("{}", tr("testing-all", &[]));
+                    let formatter = Formatter::new(&manager.config.display);
+                    let print = |status: &channel::ChannelStatus| {
+                        print_channel_status(status, &formatter);
+                        if let Some(channel) = manager.config.get_channel(&status.name) {
+                            print_key_warning(channel, &manager.config.key_expiry);
+                        }
+                    };
+                    
+                    let results = if stream_results {
+                        let mut results = Vec::new();
+                        let mut statuses = std::pin::pin!(manager.test_channels_streaming(include_disabled));
+                        while let Some(status) = statuses.next().await {
+                            print(&status);
+                            results.push(status);
+                        }
+                        results
+                    } else {
+                        let results = manager.test_all_channels(include_disabled).await;
+                        results.iter().for_each(print);
+                        results
+                    };
+                    print_test_summary(&results, &formatter);
+                    expiry::notify(&manager.config, &manager.list_channels()).await;
+                    notify_channels_down(&manager.config, &results).await;
+                    reenable_recovered(&manager.config, &results)?;
                 }
             }
         }
-        Commands::Request { prompt, model, max_tokens, temperature } => {
-            info!("Making request with prompt: {}", prompt);
+        Commands::Rank { samples, apply } => {
+            info!("Ranking channels with {} probes each", samples);
+            let manager = ChannelManager::new()?;
+            if apply {
+                manager.config.ensure_writable("apply suggested priorities")?;
+            }
+            let ranks = rank::rank_channels(&manager, samples.max(1)).await;
             
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&ranks)?);
+            } else {
+                print_ranks(&ranks, &Formatter::new(&manager.config.display));
+            }
+            
+            if apply {
+                save_priorities(ranks.iter().map(|rank| (rank.name.as_str(), rank.suggested_priority)))?;
+                if !cli.json {
+                    println!("\n{}", tr("rank-applied", &[]));
+                }
+            }
+        }
+        Commands::Probe { channel, model } => probe_and_save(&channel, model, cli.json).await?,
+        Commands::Compare { prompt, channels, model, max_tokens, temperature, system } => {
+            let client = APIClient::new()?;
+            let model = client.resolve_model(model.as_deref());
+            let channels = if channels.is_empty() {
+                let mut serving = client.get_channel_manager().config.get_channels_for_model(&model);
+                serving.sort_by_key(|ch| ch.priority);
+                serving.into_iter().map(|ch| ch.name.clone()).collect()
+            } else {
+                channels
+            };
+            if channels.is_empty() {
+                return Err(CCSwitchError::NoAvailableChannels(model));
+            }
+            
+            // Every channel answers the same request, so a cached reply would hide the difference
+            let options = RequestOptions {
+                model: Some(model),
+                max_tokens,
+                temperature,
+                system,
+                no_cache: true,
+                ..RequestOptions::default()
+            };
+            let results = future::join_all(channels.iter().map(|channel| {
+                let options = RequestOptions { channel: Some(channel.clone()), ..options.clone() };
+                let prompt = &prompt;
+                let client = &client;
+                async move {
+                    let start = Instant::now();
+                    let result = timed_request(client, prompt, options).await;
+                    (channel.clone(), start.elapsed().as_millis() as u64, result)
+                }
+            })).await;
+            
+            if cli.json {
+                let entries: Vec<serde_json::Value> = results.iter().map(|(channel, latency_ms, result)| match result {
+                    Ok(response) => serde_json::json!({ "channel": channel, "latency_ms": latency_ms, "response": response }),
+                    Err(e) => serde_json::json!({ "channel": channel, "latency_ms": latency_ms, "error": { "code": e.code(), "message": e.to_string() } }),
+                }).collect();
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+            } else {
+                print_comparison(&results, &Formatter::new(&client.get_channel_manager().config.display));
+            }
+        }
+        Commands::Request { prompt, each, concurrency, report, model, max_tokens, temperature, completion, no_interactive, json_stream, stream, preset, logprobs, system, no_cache, session, continue_session, language, hedge, consensus, tags, params, pipeline, extract, selection_seed, labels } => {
             let mut client = APIClient::new()?;
+            client.get_channel_manager_mut()
+                .set_interactive(each.is_none() && !json_stream && !no_interactive && std::io::stdin().is_terminal());
+            
+            let printer: Option<fn(&RequestEvent)> = if json_stream {
+                Some(print_event)
+            } else if stream && !cli.json {
+                Some(print_delta)
+            } else {
+                None
+            };
+            // Kept so a Ctrl-C can still show and record what the reply got to
+            let partial = Arc::new(Mutex::new(PartialReply::default()));
+            let tracked = partial.clone();
+            client.set_event_sink(Box::new(move |event: &RequestEvent| {
+                tracked.lock().unwrap().track(event);
+                if let Some(print) = printer {
+                    print(event);
+                }
+            }));
             let options = RequestOptions {
                 model,
                 max_tokens,
                 temperature,
-                stream: false,
+                stream,
+                interaction: if completion { EndpointKind::Completion } else { EndpointKind::Chat },
+                channel: None,
+                system,
+                logprobs,
+                events: None,
+                no_cache,
+                language,
+                hedge,
+                tags,
+                user: None,
+                pipeline_stage: None,
+                params: params.into_iter().collect(),
+                selection_seed,
+                labels: labels.into_iter().collect(),
+            };
+            let options = match &preset {
+                Some(name) => options.with_preset(client.get_channel_manager().config.get_preset(name)?),
+                None => options,
             };
             
-            match client.make_request(&prompt, options).await {
-                Ok(response) => {
-                    println!("✓ Response from {} (model: {}):", response.channel_used, response.model);
+            // Dropping the request future on Ctrl-C aborts the in-flight HTTP calls
+            let result = match each {
+                Some(path) => tokio::select! {
+                    result = run_each(&client, &path, concurrency, options, report.as_deref(), cli.json) => result,
+                    _ = tokio::signal::ctrl_c() => Err(CCSwitchError::Cancelled),
+                },
+                None => {
+                    let prompt = prompt.unwrap_or_default();
+                    info!("Making request with prompt: {}", prompt);
+                    
+                    // Raw completion text is not a conversation turn, so it is not recorded
+                    let mut session = match (session, continue_session) {
+                        _ if completion => None,
+                        (Some(name), _) => Some(sessions::load_or_create(&name, options.system.clone())?),
+                        (None, true) => Some(sessions::latest()?.resume_with(options.system.clone())),
+                        (None, false) => Some(sessions::Session::new(sessions::LAST_SESSION, options.system.clone())),
+                    };
+                    let mut messages = match &session {
+                        Some(session) => session.messages(),
+                        None => options.system.iter().map(|system| Message::system(system)).collect(),
+                    };
+                    messages.push(Message::user(&prompt));
+                    // The session keeps its whole history; only what is sent is trimmed
+                    let messages = context::fit(&client, &messages, &options).await?;
+                    
+                    let pipeline = match &pipeline {
+                        Some(name) => Some(client.get_channel_manager().config.get_pipeline(name)?),
+                        None => None,
+                    };
+                    let model = options.model.clone();
+                    let labels = options.labels.clone();
+                    let started = Instant::now();
+                    let request = async {
+                        match (consensus, pipeline) {
+                            (Some(count), _) => timed(&client, model.as_deref(), consensus::consensus_request(&client, &messages, options, count)).await,
+                            (None, Some(pipeline)) => timed(&client, model.as_deref(), pipeline::pipeline_request(&client, &messages, options, pipeline)).await,
+                            (None, None) => timed_chat_request(&client, &messages, options).await,
+                        }
+                    };
+                    let result = tokio::select! {
+                        result = request => result,
+                        _ = tokio::signal::ctrl_c() => Err(CCSwitchError::Cancelled),
+                    };
+                    if let (Ok(response), Some(session)) = (&result, &mut session) {
+                        session.record(&prompt, response);
+                        sessions::save(session)?;
+                    }
+                    if let Err(CCSwitchError::Cancelled) = &result {
+                        let partial = std::mem::take(&mut *partial.lock().unwrap());
+                        if stream && !json_stream && !cli.json && !partial.content.is_empty() {
+                            println!("\n{}", tr("request-cancelled-marker", &[]));
+                        }
+                        let channel = partial.channel.unwrap_or_default();
+                        let model = partial.model.unwrap_or_else(|| client.resolve_model(model.as_deref()));
+                        if let Some(session) = &mut session {
+                            session.record_cancelled(&prompt, &partial.content, &channel, &model, labels.clone());
+                            sessions::save(session)?;
+                        }
+                        stats::record(stats::UsageRecord {
+                            channel: Some(channel).filter(|channel| !channel.is_empty()),
+                            model,
+                            prompt_tokens: None,
+                            completion_tokens: None,
+                            latency_ms: started.elapsed().as_millis() as u64,
+                            success: false,
+                            error_code: Some(CCSwitchError::Cancelled.code().to_string()),
+                            cost: None,
+                            tokens_per_second: None,
+                            user: None,
+                            labels,
+                        }).await;
+                    }
+                    result.map(Some)
+                }
+            };
+            
+            if json_stream {
+                if let Err(e) = &result {
+                    print_event(&RequestEvent::Error {
+                        code: e.code().to_string(),
+                        message: e.to_string(),
+                    });
+                }
+                result?;
+            } else if let Some(mut response) = result? {
+                if let Some(extract) = &extract {
+                    response.content = extract.apply(&response.content)
+                        .ok_or_else(|| CCSwitchError::NothingExtracted(extract.describe()))?;
+                }
+                
+                if cli.json {
+                    println!("{}", serde_json::to_string_pretty(&response)?);
+                } else if extract.is_some() {
                     println!("{}", response.content);
+                } else if stream {
+                    // The text is already on screen
+                    println!("\n\n{}", response_header(&response));
                     
-                    if let Some(usage) = response.usage {
-                        println!("\nUsage: {}", usage);
+                    if let Some(usage) = usage_summary(&response, &Formatter::new(&client.get_channel_manager().config.display)) {
+                        println!("{}", tr("response-usage", &[("usage", usage)]));
                     }
+                    print_pipeline_stages(&response, &Formatter::new(&client.get_channel_manager().config.display));
+                } else {
+                    println!("{}:", response_header(&response));
+                    println!("{}", response.content);
+                    
+                    if let Some(usage) = usage_summary(&response, &Formatter::new(&client.get_channel_manager().config.display)) {
+                        println!("\n{}", tr("response-usage", &[("usage", usage)]));
+                    }
+                    print_pipeline_stages(&response, &Formatter::new(&client.get_channel_manager().config.display));
+                    if let Some(consensus) = &response.consensus {
+                        println!("{}", consensus_summary(consensus, &response));
+                    }
+                }
+            }
+        }
+        Commands::Rpc => {
+            let client = APIClient::new()?;
+            rpc::serve(client).await?;
+        }
+        Commands::Serve { host, port } => {
+            let addr = format!("{}:{}", host, port)
+                .parse()
+                .map_err(|e| CCSwitchError::Config(format!("Invalid listen address {}:{}: {}", host, port, e)))?;
+            let client = APIClient::new()?;
+            serve::serve(client, addr).await?;
+        }
+        Commands::Chat { model, max_tokens, temperature, preset, session, system } => {
+            let mut client = APIClient::new()?;
+            client.set_event_sink(Box::new(print_delta));
+            
+            let options = RequestOptions {
+                model,
+                max_tokens,
+                temperature,
+                stream: true,
+                system,
+                ..RequestOptions::default()
+            };
+            let options = match &preset {
+                Some(name) => options.with_preset(client.get_channel_manager().config.get_preset(name)?),
+                None => options,
+            };
+            
+            let session = match &session {
+                Some(name) => Some(sessions::load_or_create(name, options.system.clone())?),
+                None => None,
+            };
+            
+            run_chat(&client, options, session).await?;
+        }
+        Commands::Stats { period, by_label } => {
+            let summary = stats::summarize(config::now_secs().saturating_sub(period.seconds()), by_label.as_deref(), None).await?;
+            
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+            } else if summary.channels.is_empty() {
+                println!("{}", tr("stats-empty", &[]));
+            } else {
+                let display = config::Config::load()?.display;
+                let formatter = Formatter::new(&display);
+                println!("{}", tr("stats-by-channel", &[]));
+                print_usage_rows(&summary.channels, &formatter);
+                println!("\n{}", tr("stats-by-model", &[]));
+                print_usage_rows(&summary.models, &formatter);
+                if let Some(label) = &summary.label {
+                    println!("\n{}", tr("stats-by-label", &[("label", label.clone())]));
+                    print_usage_rows(&summary.labels, &formatter);
+                }
+            }
+        }
+        Commands::Sessions { command: SessionsCommand::List } => {
+            let sessions = sessions::list()?;
+            
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&sessions)?);
+            } else if sessions.is_empty() {
+                println!("{}", tr("sessions-empty", &[]));
+            } else {
+                for session in &sessions {
+                    let last_prompt = session.turns.last().map(|turn| preview(&turn.prompt, 60)).unwrap_or_default();
+                    println!("  {}", tr("sessions-entry", &[
+                        ("name", session.name.clone()),
+                        ("turns", session.turns.len().to_string()),
+                        ("prompt", last_prompt),
+                    ]));
+                }
+            }
+        }
+        Commands::Sessions { command: SessionsCommand::Show { name } } => {
+            let session = sessions::load(&name)?;
+            
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&session)?);
+            } else {
+                let display = config::Config::load()?.display;
+                let formatter = Formatter::new(&display);
+                if let Some(system) = &session.system {
+                    println!("[system] {}\n", system);
+                }
+                if let Some(summary) = &session.summary {
+                    println!("[summary] {}\n", summary);
                 }
-                Err(e) => {
-                    eprintln!("❌ Request failed: {}", e);
-                    std::process::exit(1);
+                for turn in &session.turns {
+                    println!(">> {}\n", turn.prompt);
+                    println!("{}\n", turn.reply);
+                    if turn.cancelled {
+                        println!("{}\n", tr("request-cancelled-marker", &[]));
+                    }
+                    let usage = turn.usage.as_ref().map(|usage| formatter.usage(usage)).unwrap_or_default();
+                    println!("   {}\n", tr("sessions-turn", &[
+                        ("channel", turn.channel.clone()),
+                        ("model", turn.model.clone()),
+                        ("usage", usage),
+                    ]).trim_end());
+                }
+            }
+        }
+        Commands::Sessions { command: SessionsCommand::Delete { name } } => {
+            sessions::delete(&name)?;
+            println!("{}", tr("sessions-deleted", &[("name", name)]));
+        }
+        Commands::Sessions { command: SessionsCommand::Summarize { name, replace, keep } } => {
+            let mut session = sessions::load(&name)?;
+            let client = APIClient::new()?;
+            // Turns kept as they are need no summary
+            let keep = if replace { keep } else { 0 };
+            let history = session.history(keep);
+            if history.is_empty() {
+                return Err(CCSwitchError::Config(format!("Session '{}' has no turns to summarize", name)));
+            }
+            
+            let model = session.turns.last().map(|turn| turn.model.clone()).unwrap_or_else(|| client.resolve_model(None));
+            let summary = context::summarize(&client, &history, &model).await?;
+            if replace {
+                session.compact(summary.clone(), keep);
+                sessions::save(&session)?;
+            }
+            
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "name": name, "summary": summary, "replaced": replace }))?);
+            } else {
+                println!("{}", summary);
+                if replace {
+                    println!("\n{}", tr("sessions-summarized", &[("name", name), ("turns", session.turns.len().to_string())]));
+                }
+            }
+        }
+        Commands::Submit { prompt, model, max_tokens, temperature, preset, system } => {
+            let options = RequestOptions {
+                model,
+                max_tokens,
+                temperature,
+                system,
+                ..RequestOptions::default()
+            };
+            let options = match &preset {
+                Some(name) => options.with_preset(config::Config::load()?.get_preset(name)?),
+                None => options,
+            };
+            
+            let job = jobs::Job::new(prompt, &options);
+            jobs::save(&job)?;
+            spawn_job_worker(&job.id)?;
+            
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&job)?);
+            } else {
+                println!("{}", job.id);
+            }
+        }
+        Commands::Jobs { command: JobsCommand::List } => {
+            let jobs = jobs::list()?;
+            
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&jobs)?);
+            } else if jobs.is_empty() {
+                println!("{}", tr("jobs-empty", &[]));
+            } else {
+                for job in &jobs {
+                    println!("  {}", tr("jobs-entry", &[
+                        ("id", job.id.clone()),
+                        ("status", job.status.as_str().to_string()),
+                        ("prompt", preview(&job.prompt, 60)),
+                    ]));
+                }
+            }
+        }
+        Commands::Jobs { command: JobsCommand::Show { id } } => {
+            let job = jobs::load(&id)?;
+            
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&job)?);
+            } else {
+                println!("{}", tr("jobs-status", &[("id", job.id.clone()), ("status", job.status.as_str().to_string())]));
+                println!("{}", tr("jobs-prompt", &[("prompt", preview(&job.prompt, 200))]));
+                if let Some(model) = &job.model {
+                    println!("{}", tr("jobs-model", &[("model", model.clone())]));
+                }
+                if let (Some(started), Some(finished)) = (job.started_at, job.finished_at) {
+                    let display = config::Config::load()?.display;
+                    let elapsed = Formatter::new(&display).latency(finished.saturating_sub(started) * 1000);
+                    println!("{}", tr("jobs-elapsed", &[("elapsed", elapsed)]));
+                }
+                if let Some(response) = &job.response {
+                    println!("{}", response_header(response));
+                }
+                if let Some(error) = &job.error {
+                    println!("{}", tr("jobs-error", &[("error", error.clone())]));
+                }
+            }
+        }
+        Commands::Jobs { command: JobsCommand::Cancel { id } } => {
+            if jobs::cancel(&id)? {
+                println!("{}", tr("jobs-cancelled", &[("id", id)]));
+            } else {
+                println!("{}", tr("jobs-already-finished", &[("id", id)]));
+            }
+        }
+        Commands::Jobs { command: JobsCommand::Result { id } } => {
+            let job = jobs::load(&id)?;
+            
+            match (&job.response, jobs::failure(&job)) {
+                (Some(response), _) if cli.json => println!("{}", serde_json::to_string_pretty(response)?),
+                (Some(response), _) => println!("{}", response.content),
+                (None, Some(error)) => return Err(error),
+                (None, None) => {
+                    println!("{}", tr("jobs-pending", &[("id", job.id.clone()), ("status", job.status.as_str().to_string())]));
+                }
+            }
+        }
+        Commands::Jobs { command: JobsCommand::Retry { id } } => {
+            let job = jobs::retry(&id)?;
+            spawn_job_worker(&job.id)?;
+            
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&job)?);
+            } else {
+                println!("{}", tr("jobs-retried", &[("id", job.id.clone())]));
+            }
+        }
+        Commands::Jobs { command: JobsCommand::Run { id } } => {
+            let client = APIClient::new()?;
+            jobs::execute(&client, &id).await?;
+        }
+        Commands::Runs { command: RunsCommand::List } => {
+            let runs = runs::list()?;
+            
+            if cli.json {
+                let runs: Vec<_> = runs.iter().map(|run| serde_json::json!({
+                    "id": run.id,
+                    "modified": run.modified,
+                    "report": run.report,
+                })).collect();
+                println!("{}", serde_json::to_string_pretty(&runs)?);
+            } else if runs.is_empty() {
+                println!("{}", tr("runs-empty", &[]));
+            } else {
+                let display = config::Config::load()?.display;
+                let formatter = Formatter::new(&display);
+                for run in &runs {
+                    match &run.report {
+                        Some(report) => println!("  {}", tr("runs-entry", &[
+                            ("id", run.id.clone()),
+                            ("succeeded", report.succeeded.to_string()),
+                            ("total", report.total.to_string()),
+                            ("elapsed", formatter.latency(report.wall_clock_ms)),
+                        ])),
+                        None => println!("  {}", tr("runs-interrupted", &[("id", run.id.clone())])),
+                    }
+                }
+            }
+        }
+        Commands::Runs { command: RunsCommand::Show { id } } => {
+            let report = runs::load(&id)?;
+            
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                let display = config::Config::load()?.display;
+                println!("{}", runs::runs_dir()?.join(&id).display());
+                print_run_report(&report, &Formatter::new(&display));
+            }
+        }
+        Commands::Runs { command: RunsCommand::Clean { older_than_days } } => {
+            let removed = runs::clean(older_than_days)?;
+            println!("{}", tr("runs-cleaned", &[("count", removed.to_string())]));
+        }
+        Commands::SelfUpdate { channel } => {
+            info!("Checking for updates on the {:?} channel", channel);
+            config::Config::load()?.ensure_writable("update ccswitch")?;
+            let updater = Updater::new(channel)?;
+            
+            match updater.run().await? {
+                UpdateOutcome::UpToDate(version) => {
+                    println!("{}", tr("update-current", &[("version", version.to_string())]));
+                }
+                UpdateOutcome::Updated { from, to } => {
+                    println!("{}", tr("update-done", &[("from", from.to_string()), ("to", to.to_string())]));
                 }
             }
         }
@@ -151,16 +1106,734 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-fn print_channel_status(status: &channel::ChannelStatus) {
+// Runs the job in a separate process that outlives this one
+fn spawn_job_worker(id: &str) -> Result<()> {
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command.args(["jobs", "run", id])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    
+    // Leave the terminal's process group so closing the shell doesn't take the worker with it
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    
+    command.spawn()?;
+    Ok(())
+}
+
+// Detects the channel's features and saves them as its capabilities
+async fn probe_and_save(channel: &str, model: Option<String>, json: bool) -> Result<()> {
+    let client = APIClient::new()?;
+    client.get_channel_manager().config.ensure_writable("save probed capabilities")?;
+    let target = client.get_channel_manager().config.get_channel(channel)
+        .ok_or_else(|| CCSwitchError::ChannelNotFound(channel.to_string()))?;
+    let model = model.or_else(|| target.named_models().next().map(String::from)).unwrap_or_else(|| client.resolve_model(None));
+    
+    if !json {
+        println!("{}", tr("probe-start", &[("name", channel.to_string()), ("model", model.clone())]));
+    }
+    let capabilities = probe::probe_channel(&client, target, &model).await?;
+    
+    let mut config = config::Config::load()?;
+    if let Some(target) = config.channels.get_mut(channel) {
+        target.capabilities = capabilities.clone();
+    }
+    config.save()?;
+    
+    if json {
+        println!("{}", serde_json::to_string_pretty(&capabilities)?);
+    } else {
+        print_capabilities(&capabilities);
+        println!("\n{}", tr("probe-saved", &[]));
+    }
+    Ok(())
+}
+
+// Lets the user move channels with `u N`/`d N` until they save (`s`, the new order) or quit (`q`, None)
+fn reorder_interactively(mut names: Vec<String>) -> Result<Option<Vec<String>>> {
+    loop {
+        println!("{}", tr("reorder-header", &[]));
+        for (index, name) in names.iter().enumerate() {
+            println!("  {}) {}", index + 1, name);
+        }
+        print!("{} ", tr("reorder-prompt", &[]));
+        std::io::stdout().flush()?;
+        
+        let mut input = String::new();
+        if std::io::stdin().read_line(&mut input)? == 0 {
+            return Ok(None);
+        }
+        let mut input = input.trim().chars();
+        let command = input.next();
+        let position: Option<usize> = input.as_str().trim().parse().ok().filter(|&position| (1..=names.len()).contains(&position));
+        match (command, position) {
+            (Some('s'), _) => return Ok(Some(names)),
+            (Some('q'), _) => return Ok(None),
+            (Some('u'), Some(position)) if position > 1 => names.swap(position - 1, position - 2),
+            (Some('d'), Some(position)) if position < names.len() => names.swap(position - 1, position),
+            // Already at the top or bottom
+            (Some('u' | 'd'), Some(_)) => {}
+            _ => println!("{}", tr("reorder-invalid", &[("count", names.len().to_string())])),
+        }
+        println!();
+    }
+}
+
+async fn run_chat(client: &APIClient, options: RequestOptions, mut session: Option<sessions::Session>) -> Result<()> {
+    let mut editor = rustyline::DefaultEditor::new()
+        .map_err(|e| CCSwitchError::Io(std::io::Error::other(e)))?;
+    let mut messages: Vec<Message> = match &session {
+        Some(session) => session.messages(),
+        None => options.system.iter().map(|system| Message::system(system)).collect(),
+    };
+    let mut last_channel: Option<String> = None;
+    
+    println!("{}", tr("chat-welcome", &[]));
+    if let Some(session) = session.as_ref().filter(|session| !session.turns.is_empty()) {
+        println!("{}", tr("chat-resumed", &[("name", session.name.clone()), ("turns", session.turns.len().to_string())]));
+    }
+    
+    loop {
+        // rustyline blocks; keep the runtime's other workers free while waiting for input
+        let line = match tokio::task::block_in_place(|| editor.readline(">> ")) {
+            Ok(line) => line,
+            // Ctrl-C at the prompt discards the line, Ctrl-D leaves
+            Err(rustyline::error::ReadlineError::Interrupted) => continue,
+            Err(rustyline::error::ReadlineError::Eof) => break,
+            Err(e) => return Err(CCSwitchError::Io(std::io::Error::other(e))),
+        };
+        let input = line.trim();
+        
+        match input {
+            "" => continue,
+            "/exit" | "/quit" => break,
+            "/clear" => {
+                messages.retain(|message| message.role == "system");
+                if let Some(session) = &mut session {
+                    session.clear();
+                    sessions::save(session)?;
+                }
+                println!("{}", tr("chat-cleared", &[]));
+                continue;
+            }
+            _ => {}
+        }
+        let _ = editor.add_history_entry(input);
+        
+        messages.push(Message::user(input));
+        // What is trimmed stays out of later turns too
+        messages = context::fit(client, &messages, &options).await?;
+        
+        // Ctrl-C cancels only this turn; the question is dropped so it is not sent again
+        let result = tokio::select! {
+            result = client.make_chat_request(&messages, options.clone()) => result,
+            _ = tokio::signal::ctrl_c() => Err(CCSwitchError::Cancelled),
+        };
+        
+        match result {
+            Ok(response) => {
+                println!();
+                if last_channel.as_deref() != Some(response.channel_used.as_str()) {
+                    println!("{}", tr("chat-via", &[("channel", response.channel_used.clone()), ("model", response.model.clone())]));
+                    last_channel = Some(response.channel_used.clone());
+                }
+                println!();
+                messages.push(Message::assistant(&response.content));
+                if let Some(session) = &mut session {
+                    session.record(input, &response);
+                    sessions::save(session)?;
+                }
+            }
+            Err(e) => {
+                messages.pop();
+                println!("\n❌ {}\n", i18n::error_message(&e));
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+async fn timed_request(client: &APIClient, prompt: &str, options: RequestOptions) -> Result<APIResponse> {
+    let mut messages: Vec<Message> = options.system.iter().map(|system| Message::system(system)).collect();
+    messages.push(Message::user(prompt));
+    timed_chat_request(client, &messages, options).await
+}
+
+async fn timed_chat_request(client: &APIClient, messages: &[Message], options: RequestOptions) -> Result<APIResponse> {
+    let model = options.model.clone();
+    timed(client, model.as_deref(), client.make_chat_request(messages, options)).await
+}
+
+// Runs a request, then exports how it went to the configured metrics sink
+async fn timed(client: &APIClient, model: Option<&str>, request: impl std::future::Future<Output = Result<APIResponse>>) -> Result<APIResponse> {
+    let resolved_model = client.resolve_model(model);
+    let start = Instant::now();
+    let result = request.await;
+    
+    let usage = result.as_ref().ok().and_then(|response| response.usage.as_ref());
+    let (prompt_tokens, completion_tokens) = usage.map(client::usage_tokens).unwrap_or((None, None));
+    let request_metrics = RequestMetrics {
+        channel: result.as_ref().ok().map(|response| response.channel_used.clone()),
+        model: resolved_model,
+        latency_ms: start.elapsed().as_millis() as u64,
+        prompt_tokens,
+        completion_tokens,
+        cached_tokens: usage.and_then(client::cache_tokens).map(|(cached, _)| cached),
+        success: result.is_ok(),
+    };
+    metrics::export(&client.get_channel_manager().config.metrics, &request_metrics).await;
+    
+    result
+}
+
+async fn run_each(client: &APIClient, path: &Path, concurrency: usize, options: RequestOptions, report_path: Option<&Path>, json: bool) -> Result<Option<APIResponse>> {
+    let content = std::fs::read_to_string(path)?;
+    let prompts: Vec<&str> = content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    let total = prompts.len();
+    
+    // Probe once up front, then spread the prompts round-robin over the healthy channels
+    let model = client.resolve_model(options.model.as_deref());
+    let manager = client.get_channel_manager();
+    let channels: Vec<String> = manager
+        .available_channels(&model, &options.channel_filter(&manager.config))
+        .await?
+        .into_iter()
+        .map(|ch| ch.name.clone())
+        .collect();
+    info!("Fanning out {} prompts over {} channels", total, channels.len());
+    
+    let started = Instant::now();
+    let mut report = report::RunReport::new(config::now_secs(), total);
+    
+    // Artifacts are a convenience; a read-only data directory must not stop the run
+    let mut artifacts = match runs::RunWriter::create(&prompts) {
+        Ok(writer) => Some(writer),
+        Err(e) => {
+            warn!("Not storing run artifacts: {}", e);
+            None
+        }
+    };
+    
+    let mut results = stream::iter(prompts.into_iter().enumerate())
+        .map(|(index, prompt)| {
+            let mut options = options.clone();
+            let channels = &channels;
+            async move {
+                // Channels at their `max_in_flight` or rate limit are passed over while another has room
+                let channel = (0..channels.len())
+                    .map(|offset| &channels[(index + offset) % channels.len()])
+                    .find(|name| manager.config.get_channel(name).is_some_and(|channel| !manager.is_saturated(channel) && !manager.is_rate_limited(channel)))
+                    .unwrap_or(&channels[index % channels.len()])
+                    .clone();
+                options.channel = Some(channel.clone());
+                let start = Instant::now();
+                let result = timed_request(client, prompt, options).await;
+                let timing = report::ItemTiming {
+                    index: index + 1,
+                    prompt: prompt.to_string(),
+                    channel,
+                    latency_ms: start.elapsed().as_millis() as u64,
+                };
+                (index, prompt, timing, result)
+            }
+        })
+        .buffer_unordered(concurrency.max(1));
+    
+    while let Some((index, prompt, timing, result)) = results.next().await {
+        let label = format!("[{}/{}]", index + 1, total);
+        
+        if json {
+            let line = match &result {
+                Ok(response) => serde_json::json!({ "index": index + 1, "prompt": prompt, "response": response }),
+                Err(e) => serde_json::json!({ "index": index + 1, "prompt": prompt, "error": { "code": e.code(), "message": e.to_string() } }),
+            };
+            println!("{}", line);
+        } else {
+            match &result {
+                Ok(response) => {
+                    println!("{} {}", label, tr("each-response", &[("prompt", prompt.to_string()), ("channel", response.channel_used.clone())]));
+                    println!("{}\n", response.content);
+                }
+                Err(e) => println!("{} ❌ {} - {}\n", label, prompt, i18n::error_message(e)),
+            }
+        }
+        
+        if let Some(artifacts) = &mut artifacts {
+            artifacts.record(index + 1, prompt, &result);
+        }
+        report.record(timing, &result);
+    }
+    report.wall_clock_ms = started.elapsed().as_millis() as u64;
+    
+    if let Some(artifacts) = &artifacts {
+        match artifacts.finish(&report) {
+            Ok(()) => info!("Stored run {} in {}", artifacts.id, artifacts.dir.display()),
+            Err(e) => warn!("Failed to store report for run {}: {}", artifacts.id, e),
+        }
+    }
+    
+    if !json {
+        print_run_report(&report, &Formatter::new(&client.get_channel_manager().config.display));
+        if let Some(artifacts) = &artifacts {
+            println!("  {}", tr("runs-stored", &[("id", artifacts.id.clone())]));
+        }
+    }
+    if let Some(path) = report_path {
+        std::fs::write(path, serde_json::to_string_pretty(&report)?)?;
+    }
+    
+    if report.failed > 0 {
+        return Err(CCSwitchError::Channel(tr("each-failed", &[("failed", report.failed.to_string()), ("total", total.to_string())])));
+    }
+    
+    Ok(None)
+}
+
+fn print_event(event: &RequestEvent) {
+    if let Ok(line) = serde_json::to_string(event) {
+        println!("{}", line);
+    }
+}
+
+// What a reply had got to when Ctrl-C stopped it
+#[derive(Default)]
+struct PartialReply {
+    content: String,
+    channel: Option<String>,
+    model: Option<String>,
+}
+
+impl PartialReply {
+    fn track(&mut self, event: &RequestEvent) {
+        match event {
+            RequestEvent::Selection { channel, model } => {
+                // A failover starts the reply over on the next channel
+                self.content.clear();
+                self.channel = Some(channel.clone());
+                self.model = Some(model.clone());
+            }
+            RequestEvent::Delta { content } => self.content.push_str(content),
+            _ => {}
+        }
+    }
+}
+
+fn print_delta(event: &RequestEvent) {
+    // The reply owns stdout, so the live rate only gets a status line when stdout is redirected
+    let status_line = || !std::io::stdout().is_terminal() && std::io::stderr().is_terminal();
+    
+    match event {
+        RequestEvent::Delta { content } => {
+            print!("{}", content);
+            let _ = std::io::stdout().flush();
+        }
+        RequestEvent::Rate { tokens_per_second } if status_line() => {
+            eprint!("\r\x1b[K{}", tr("stream-rate", &[("rate", format::rate(*tokens_per_second))]));
+        }
+        RequestEvent::Done { .. } if status_line() => eprint!("\r\x1b[K"),
+        _ => {}
+    }
+}
+
+// Values are read as JSON where they parse (numbers, booleans), as strings otherwise
+fn parse_param(arg: &str) -> std::result::Result<(String, serde_json::Value), String> {
+    let (key, value) = arg.split_once('=').ok_or_else(|| format!("expected KEY=VALUE, got '{}'", arg))?;
+    let value = serde_json::from_str(value).unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
+    Ok((key.to_string(), value))
+}
+
+fn parse_label(arg: &str) -> std::result::Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{}'", arg)),
+    }
+}
+
+// Keeps the key out of shell history and the process list, e.g. `pass show relay | ccswitch update relay --key -`
+fn read_key_from_stdin() -> Result<String> {
+    let mut key = String::new();
+    std::io::stdin().read_to_string(&mut key)?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(CCSwitchError::Config("No API key on stdin".to_string()));
+    }
+    Ok(key.to_string())
+}
+
+fn report_error(error: &CCSwitchError, json: bool) {
+    if json {
+        let mut output = serde_json::json!({
+            "error": {
+                "code": error.code(),
+                "exit_status": error.exit_code(),
+                "message": error.to_string(),
+            }
+        });
+        if let Some(kind) = error.provider_kind() {
+            output["error"]["kind"] = serde_json::json!(kind);
+        }
+        println!("{}", output);
+    } else {
+        eprintln!("❌ {} [{}]", i18n::error_message(error), error.code());
+    }
+}
+
+// Secret references are safe to show; literal keys are not
+fn mask_key(key: &str) -> String {
+    if key.starts_with("vault:") || key.starts_with("op://") {
+        return key.to_string();
+    }
+    
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 8 {
+        return "****".to_string();
+    }
+    format!("{}****{}", chars[..3].iter().collect::<String>(), chars[chars.len() - 4..].iter().collect::<String>())
+}
+
+fn print_channel_details(channel: &config::Channel) {
+    let field = |key: &str, value: String| println!("  {}{}", format::pad(&format!("{}:", tr(key, &[])), 14), value);
+    
+    println!("{}", channel.name);
+    if let Some(description) = &channel.description {
+        println!("  {}", description);
+    }
+    field("show-url", channel.url.clone());
+    field("show-status", status_label(channel));
+    field("show-model", model_label(channel));
+    field("show-priority", channel.priority.to_string());
+    field("show-endpoint", format!("{:?}", channel.endpoint_kind));
+    field("show-api-key", match (&channel.credential_helper, &channel.api_key) {
+        (Some(helper), _) => tr("show-api-key-helper", &[("helper", helper.clone())]),
+        (None, Some(key)) => key.clone(),
+        (None, None) => tr("show-api-key-none", &[]),
+    });
+    field("show-proxy", proxy::describe(channel));
+    if let Some(expires_at) = &channel.key_expires_at {
+        field("show-key-expires", expires_at.clone());
+    }
+    if !channel.tags.is_empty() {
+        field("show-tags", channel.tags.join(", "));
+    }
+    
+    if !channel.model_map.is_empty() {
+        println!("  {}:", tr("show-model-map", &[]));
+        for (requested, upstream) in &channel.model_map {
+            println!("    {} → {}", requested, upstream);
+        }
+    }
+    if !channel.metadata.is_empty() {
+        println!("  {}:", tr("show-metadata", &[]));
+        for (key, value) in &channel.metadata {
+            println!("    {}: {}", key, value);
+        }
+    }
+}
+
+fn status_label(channel: &config::Channel) -> String {
+    match channel.auto_disabled_until.filter(|_| channel.enabled && channel.is_auto_disabled()) {
+        Some(until) => {
+            let until = chrono::DateTime::from_timestamp(until as i64, 0)
+                .map(|until| until.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            tr("channel-auto-disabled", &[("until", until)])
+        }
+        None => tr(if channel.enabled { "channel-enabled" } else { "channel-disabled" }, &[]),
+    }
+}
+
+fn model_label(channel: &config::Channel) -> String {
+    if channel.models.is_empty() {
+        tr("model-any", &[])
+    } else {
+        channel.models.join(", ")
+    }
+}
+
+// First line of the text, cut to at most `max` characters
+fn preview(text: &str, max: usize) -> String {
+    let line = text.lines().next().unwrap_or("");
+    if line.chars().count() > max || line.len() < text.trim_end().len() {
+        format!("{}…", line.chars().take(max).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+fn response_header(response: &APIResponse) -> String {
+    let header = tr("response-from", &[("channel", response.channel_used.clone()), ("model", response.model.clone())]);
+    if response.cached {
+        format!("{} {}", header, tr("response-cached", &[]))
+    } else {
+        header
+    }
+}
+
+fn usage_summary(response: &APIResponse, formatter: &Formatter) -> Option<String> {
+    let mut summary = formatter.usage(response.usage.as_ref()?);
+    if let Some(cost) = response.cost {
+        summary.push_str(&format!(" {}", tr("usage-cost", &[("cost", formatter.cost(cost))])));
+    }
+    if let Some(rate) = response.tokens_per_second {
+        summary.push_str(&format!(" {}", tr("usage-rate", &[("rate", format::rate(rate))])));
+    }
+    Some(summary)
+}
+
+fn print_pipeline_stages(response: &APIResponse, formatter: &Formatter) {
+    for stage in &response.pipeline {
+        let mut usage = stage.usage.as_ref().map(|usage| formatter.usage(usage)).unwrap_or_else(|| "-".to_string());
+        if let Some(cost) = stage.cost {
+            usage.push_str(&format!(" {}", tr("usage-cost", &[("cost", formatter.cost(cost))])));
+        }
+        println!("  {}", tr("pipeline-stage", &[
+            ("stage", stage.stage.clone()),
+            ("channel", stage.channel.clone()),
+            ("model", stage.model.clone()),
+            ("usage", usage),
+        ]));
+    }
+}
+
+fn print_key_warning(channel: &config::Channel, settings: &config::KeyExpiryConfig) {
+    if let Some(warning) = expiry::warning(channel, settings) {
+        println!("    ⚠ {}", warning);
+    }
+}
+
+fn print_ranks(ranks: &[rank::ChannelRank], formatter: &Formatter) {
+    let latency = |ms: Option<u64>| ms.map(|ms| formatter.latency(ms)).unwrap_or_else(|| "-".to_string());
+    
+    for (index, rank) in ranks.iter().enumerate() {
+        println!("{}. {}", index + 1, tr("rank-entry", &[
+            ("name", rank.name.clone()),
+            ("score", format!("{:.1}", rank.score)),
+            ("success", format!("{:.0}%", rank.success_rate() * 100.0)),
+            ("p50", latency(rank.p50_ms)),
+            ("p95", latency(rank.p95_ms)),
+            ("errors", rank.error_rate.map_or_else(|| "-".to_string(), |rate| format!("{:.0}%", rate * 100.0))),
+            ("price", rank.price.map_or_else(|| "-".to_string(), |price| formatter.cost(price))),
+            ("current", rank.current_priority.to_string()),
+            ("suggested", rank.suggested_priority.to_string()),
+        ]));
+    }
+}
+
+// Columns when the terminal fits them, one block per channel otherwise
+fn print_comparison(results: &[(String, u64, Result<APIResponse>)], formatter: &Formatter) {
+    let columns: Vec<Vec<String>> = results.iter().map(|(channel, latency_ms, result)| {
+        let mut lines = vec![channel.clone()];
+        match result {
+            Ok(response) => {
+                let mut stats = formatter.latency(*latency_ms);
+                if let Some(cost) = response.cost {
+                    stats.push_str(&format!(" {}", tr("usage-cost", &[("cost", formatter.cost(cost))])));
+                }
+                lines.push(stats);
+                if let Some(usage) = &response.usage {
+                    lines.push(formatter.usage(usage));
+                }
+                lines.push(String::new());
+                lines.push(response.content.clone());
+            }
+            Err(e) => {
+                lines.push(formatter.latency(*latency_ms));
+                lines.push(String::new());
+                lines.push(format!("❌ {}", e));
+            }
+        }
+        lines
+    }).collect();
+    
+    let width: usize = std::env::var("COLUMNS").ok().and_then(|columns| columns.parse().ok()).unwrap_or(120);
+    let column_width = width.saturating_sub(3 * (columns.len() - 1)) / columns.len();
+    if columns.len() == 1 || column_width < 30 {
+        for lines in &columns {
+            println!("── {} ──", lines[0]);
+            println!("{}\n", lines[1..].join("\n"));
+        }
+        return;
+    }
+    
+    let wrapped: Vec<Vec<String>> = columns.iter()
+        .map(|lines| lines.iter().flat_map(|line| format::wrap(line, column_width)).collect())
+        .collect();
+    let height = wrapped.iter().map(Vec::len).max().unwrap_or(0);
+    for row in 0..height {
+        let cells: Vec<String> = wrapped.iter()
+            .map(|lines| format::pad(lines.get(row).map_or("", String::as_str), column_width))
+            .collect();
+        println!("{}", cells.join(" │ ").trim_end());
+        // Rule under the channel names
+        if row == 0 {
+            println!("{}", vec!["─".repeat(column_width); wrapped.len()].join("─┼─"));
+        }
+    }
+}
+
+fn consensus_summary(consensus: &consensus::Consensus, response: &APIResponse) -> String {
+    let answered = consensus.answers.iter().filter(|answer| answer.content.is_some()).count();
+    let args = [
+        ("agreeing", consensus.agreeing.to_string()),
+        ("answered", answered.to_string()),
+        ("asked", consensus.answers.len().to_string()),
+        ("channel", response.channel_used.clone()),
+    ];
+    match consensus.method {
+        consensus::ConsensusMethod::Majority => tr("consensus-majority", &args),
+        consensus::ConsensusMethod::Judge => tr("consensus-judge", &args),
+        consensus::ConsensusMethod::Single => tr("consensus-single", &args),
+    }
+}
+
+fn print_capabilities(capabilities: &config::Capabilities) {
+    let support = |supported: Option<bool>| match supported {
+        Some(true) => tr("probe-supported", &[]),
+        Some(false) => tr("probe-unsupported", &[]),
+        None => tr("probe-unknown", &[]),
+    };
+    
+    println!("  streaming:  {}", support(capabilities.streaming));
+    println!("  tools:      {}", support(capabilities.tools));
+    println!("  vision:     {}", support(capabilities.vision));
+    println!("  json_mode:  {}", support(capabilities.json_mode));
+    println!("  max_tokens: {}", capabilities.max_output_tokens.map_or_else(|| tr("probe-no-limit", &[]), |tokens| tokens.to_string()));
+}
+
+fn print_usage_rows(rows: &[stats::UsageRow], formatter: &Formatter) {
+    let width = rows.iter().map(|row| row.name.chars().count()).max().unwrap_or(0);
+    
+    for row in rows {
+        println!("  {} {}{}", format::pad(&row.name, width), tr("stats-entry", &[
+            ("requests", formatter.number(row.requests)),
+            ("failed", formatter.number(row.failures)),
+            ("prompt", formatter.number(row.prompt_tokens)),
+            ("completion", formatter.number(row.completion_tokens)),
+            ("latency", formatter.latency(row.avg_latency_ms)),
+            ("cost", formatter.cost(row.cost)),
+        ]), row.avg_tokens_per_second.map(|rate| format!(", {}", format::rate(rate))).unwrap_or_default());
+    }
+}
+
+fn print_run_report(report: &report::RunReport, formatter: &Formatter) {
+    println!("{}", tr("report-header", &[
+        ("succeeded", report.succeeded.to_string()),
+        ("total", report.total.to_string()),
+        ("elapsed", formatter.latency(report.wall_clock_ms)),
+        ("failovers", report.failovers.to_string()),
+    ]));
+    let mut tokens = tr("report-tokens", &[
+        ("prompt", formatter.number(report.prompt_tokens)),
+        ("completion", formatter.number(report.completion_tokens)),
+    ]);
+    if let Some(cost) = report.cost {
+        tokens.push_str(&format!(" {}", tr("usage-cost", &[("cost", formatter.cost(cost))])));
+    }
+    println!("  {}", tokens);
+    
+    for (name, tally) in &report.channels {
+        println!("  {}", tr("report-channel", &[
+            ("name", name.clone()),
+            ("succeeded", tally.succeeded.to_string()),
+            ("failed", tally.failed.to_string()),
+        ]));
+    }
+    
+    for item in &report.slowest {
+        println!("  {}", tr("report-slowest", &[
+            ("index", item.index.to_string()),
+            ("channel", item.channel.clone()),
+            ("latency", formatter.latency(item.latency_ms)),
+        ]));
+    }
+}
+
+// Writes priorities into the config as it is on disk now, so whatever changed it meanwhile
+// (auto-disable during probes, another shell) is kept
+fn save_priorities<'a>(priorities: impl IntoIterator<Item = (&'a str, u32)>) -> Result<()> {
+    let mut saved = config::Config::load()?;
+    for (name, priority) in priorities {
+        if let Some(channel) = saved.channels.get_mut(name) {
+            channel.priority = priority;
+        }
+    }
+    saved.save()
+}
+
+// A passing check brings an auto-disabled channel back before its time is up
+fn reenable_recovered(config: &config::Config, results: &[channel::ChannelStatus]) -> Result<()> {
+    let recovered: Vec<&str> = results.iter()
+        .filter(|status| status.available)
+        .filter(|status| config.get_channel(&status.name).is_some_and(|channel| channel.is_auto_disabled()))
+        .map(|status| status.name.as_str())
+        .collect();
+    if recovered.is_empty() {
+        return Ok(());
+    }
+    
+    let mut saved = config::Config::load()?;
+    for name in &recovered {
+        if let Some(channel) = saved.channels.get_mut(*name) {
+            channel.auto_disabled_until = None;
+        }
+    }
+    saved.save()?;
+    
+    for name in recovered {
+        println!("{}", tr("channel-reenabled", &[("name", name.to_string())]));
+    }
+    Ok(())
+}
+
+async fn notify_channels_down(config: &config::Config, results: &[channel::ChannelStatus]) {
+    let down: Vec<String> = results
+        .iter()
+        .filter(|status| !status.available)
+        .map(|status| format!("{}: {}", status.name, status.error.as_deref().unwrap_or_default()))
+        .collect();
+    
+    if !down.is_empty() {
+        let subject = format!("[ccswitch] {} channels unavailable", down.len());
+        email::notify(&config.email, config::EmailEvent::ChannelDown, &subject, &down.join("\n")).await;
+    }
+}
+
+fn print_test_summary(results: &[channel::ChannelStatus], formatter: &Formatter) {
+    let available = results.iter().filter(|status| status.available).count();
+    let mut summary = tr("test-summary", &[
+        ("available", available.to_string()),
+        ("failed", (results.len() - available).to_string()),
+    ]);
+    
+    let slowest = results
+        .iter()
+        .filter_map(|status| Some((status, status.response_time_ms?)))
+        .max_by_key(|(_, ms)| *ms);
+    if let Some((status, ms)) = slowest {
+        summary.push_str(&format!(" · {}", tr("test-slowest", &[("name", status.name.clone()), ("latency", formatter.latency(ms))])));
+    }
+    
+    println!("\n{}", summary);
+}
+
+fn print_channel_status(status: &channel::ChannelStatus, formatter: &Formatter) {
     let icon = if status.available { "✓" } else { "❌" };
     let mut message = format!("{} {} - {}", 
         icon, 
         status.name, 
-        if status.available { "Available" } else { "Unavailable" }
+        tr(if status.available { "status-available" } else { "status-unavailable" }, &[])
     );
     
     if let Some(response_time) = status.response_time_ms {
-        message.push_str(&format!(" ({}ms)", response_time));
+        message.push_str(&format!(" ({})", formatter.latency(response_time)));
     }
     
     if let Some(error) = &status.error {
@@ -0,0 +1,100 @@
+use crate::config::MetricsConfig;
+use log::{debug, warn};
+use reqwest::Client;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+const DEFAULT_PREFIX: &str = "ccswitch";
+
+#[derive(Debug)]
+pub struct RequestMetrics {
+    pub channel: Option<String>,
+    pub model: String,
+    pub latency_ms: u64,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    // Prompt tokens served from the provider's prompt cache
+    pub cached_tokens: Option<u64>,
+    pub success: bool,
+}
+
+// Export failures are logged and swallowed; monitoring must never break a request
+pub async fn export(config: &MetricsConfig, metrics: &RequestMetrics) {
+    let prefix = config.prefix.as_deref().unwrap_or(DEFAULT_PREFIX);
+    
+    if let Some(addr) = &config.statsd {
+        if let Err(e) = send_statsd(addr, prefix, metrics).await {
+            warn!("Failed to send metrics to statsd at {}: {}", addr, e);
+        }
+    }
+    
+    if let Some(url) = &config.pushgateway {
+        if let Err(e) = push_gateway(url, prefix, metrics).await {
+            warn!("Failed to push metrics to {}: {}", url, e);
+        }
+    }
+}
+
+async fn send_statsd(addr: &str, prefix: &str, metrics: &RequestMetrics) -> std::io::Result<()> {
+    let channel = sanitize(metrics.channel.as_deref().unwrap_or("none"));
+    let base = format!("{}.{}", prefix, channel);
+    
+    let mut lines = vec![
+        format!("{}.latency:{}|ms", base, metrics.latency_ms),
+        format!("{}.{}:1|c", base, if metrics.success { "success" } else { "failure" }),
+    ];
+    if let Some(tokens) = metrics.prompt_tokens {
+        lines.push(format!("{}.prompt_tokens:{}|c", base, tokens));
+    }
+    if let Some(tokens) = metrics.completion_tokens {
+        lines.push(format!("{}.completion_tokens:{}|c", base, tokens));
+    }
+    if let Some(tokens) = metrics.cached_tokens {
+        lines.push(format!("{}.cached_tokens:{}|c", base, tokens));
+    }
+    
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.send_to(lines.join("\n").as_bytes(), addr).await?;
+    debug!("Sent {} statsd metrics to {}", lines.len(), addr);
+    Ok(())
+}
+
+async fn push_gateway(url: &str, prefix: &str, metrics: &RequestMetrics) -> reqwest::Result<()> {
+    let prefix = prefix.replace(['.', '-'], "_");
+    let labels = format!("model=\"{}\"", metrics.model.replace('"', "'"));
+    
+    let mut body = String::new();
+    body.push_str(&format!("{}_request_latency_seconds{{{}}} {}\n", prefix, labels, metrics.latency_ms as f64 / 1000.0));
+    body.push_str(&format!("{}_request_success{{{}}} {}\n", prefix, labels, u8::from(metrics.success)));
+    if let Some(tokens) = metrics.prompt_tokens {
+        body.push_str(&format!("{}_request_prompt_tokens{{{}}} {}\n", prefix, labels, tokens));
+    }
+    if let Some(tokens) = metrics.completion_tokens {
+        body.push_str(&format!("{}_request_completion_tokens{{{}}} {}\n", prefix, labels, tokens));
+    }
+    if let Some(tokens) = metrics.cached_tokens {
+        body.push_str(&format!("{}_request_cached_tokens{{{}}} {}\n", prefix, labels, tokens));
+    }
+    
+    // Group by channel so each channel keeps its own last-run values
+    let channel = sanitize(metrics.channel.as_deref().unwrap_or("none"));
+    let endpoint = format!("{}/metrics/job/{}/channel/{}", url.trim_end_matches('/'), prefix, channel);
+    
+    Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()?
+        .post(&endpoint)
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?;
+    
+    debug!("Pushed metrics to {}", endpoint);
+    Ok(())
+}
+
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect()
+}
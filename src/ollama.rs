@@ -0,0 +1,73 @@
+use crate::client::{Message, RequestOptions};
+use serde_json::{json, Value};
+
+// Accepts the server root ("http://localhost:11434") or any of its API URLs
+pub fn base_url(url: &str) -> &str {
+    match url.find("/api/") {
+        Some(index) => &url[..index],
+        None => url.trim_end_matches('/'),
+    }
+}
+
+pub fn chat_url(url: &str) -> String {
+    format!("{}/api/chat", base_url(url))
+}
+
+pub fn tags_url(url: &str) -> String {
+    format!("{}/api/tags", base_url(url))
+}
+
+pub fn payload(model: &str, messages: &[Message], options: &RequestOptions) -> Value {
+    let mut model_options = json!({});
+    if let Some(max_tokens) = options.max_tokens {
+        model_options["num_predict"] = json!(max_tokens);
+    }
+    if let Some(temperature) = options.temperature {
+        model_options["temperature"] = json!(temperature);
+    }
+    
+    json!({
+        "model": model,
+        "messages": messages,
+        "stream": options.stream,
+        "options": model_options
+    })
+}
+
+pub fn content(response: &Value) -> Option<String> {
+    response.pointer("/message/content").and_then(|c| c.as_str()).map(str::to_string)
+}
+
+// Token counts arrive on the final (`done`) object only
+pub fn usage(response: &Value) -> Option<Value> {
+    let prompt = response.get("prompt_eval_count").and_then(|v| v.as_u64());
+    let completion = response.get("eval_count").and_then(|v| v.as_u64());
+    if prompt.is_none() && completion.is_none() {
+        return None;
+    }
+    
+    let (prompt, completion) = (prompt.unwrap_or(0), completion.unwrap_or(0));
+    Some(json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": completion,
+        "total_tokens": prompt + completion
+    }))
+}
+
+pub fn model_names(tags: &Value) -> Vec<String> {
+    tags.get("models")
+        .and_then(|models| models.as_array())
+        .map(|models| {
+            models
+                .iter()
+                .filter_map(|model| model.get("name").and_then(|n| n.as_str()))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Ollama lists "llama3:latest" for a model requested as "llama3"
+pub fn has_model(installed: &[String], requested: &str) -> bool {
+    installed.iter().any(|name| name == requested || name.strip_suffix(":latest") == Some(requested))
+}
@@ -0,0 +1,78 @@
+use crate::config::Channel;
+use crate::error::{CCSwitchError, ProviderErrorKind};
+use crate::upstream::{self, Diagnosis};
+use reqwest::{RequestBuilder, StatusCode};
+use serde_json::Value;
+
+pub fn headers(request: RequestBuilder, channel: &Channel) -> RequestBuilder {
+    let mut request = request;
+    if let Some(referer) = &channel.openrouter.referer {
+        request = request.header("HTTP-Referer", referer);
+    }
+    if let Some(title) = &channel.openrouter.title {
+        request = request.header("X-Title", title);
+    }
+    request
+}
+
+pub fn apply(payload: &mut Value, channel: &Channel) {
+    if let Some(provider) = &channel.openrouter.provider {
+        payload["provider"] = provider.clone();
+    }
+}
+
+// "Provider returned error" alone says little; the provider's name and its own words follow it
+pub fn message(body: &str) -> Option<String> {
+    let message = upstream::message(body)?;
+    let parsed: Value = serde_json::from_str(body).ok()?;
+    let metadata = parsed.pointer("/error/metadata");
+    let provider = metadata.and_then(|metadata| metadata.get("provider_name")).and_then(|name| name.as_str());
+    let raw = metadata.and_then(|metadata| metadata.get("raw")).and_then(|raw| raw.as_str());
+    Some(match (provider, raw) {
+        (Some(provider), Some(raw)) => format!("{} ({}: {})", message, provider, upstream::message(raw).unwrap_or_else(|| raw.chars().take(200).collect())),
+        (Some(provider), None) => format!("{} ({})", message, provider),
+        _ => message,
+    })
+}
+
+// OpenRouter gives its statuses fixed meanings, and wraps what the provider behind it said in `error.metadata.raw`
+pub fn diagnose(status: StatusCode, body: &str) -> Option<Diagnosis> {
+    let parsed: Option<Value> = serde_json::from_str(body).ok();
+    let error = parsed.as_ref().and_then(|parsed| parsed.get("error"));
+    let message = upstream::message(body).unwrap_or_default().to_lowercase();
+    
+    let kind = match status.as_u16() {
+        402 => Some(ProviderErrorKind::QuotaExhausted),
+        // Moderation lists its reasons; other 403s are key problems
+        403 if error.and_then(|error| error.pointer("/metadata/reasons")).is_some() => Some(ProviderErrorKind::ContentFiltered),
+        // The daily cap on free models lasts until the next day rather than the next minute
+        429 if message.contains("per-day") || message.contains("per day") => Some(ProviderErrorKind::QuotaExhausted),
+        // The chosen provider is down, timed out or answered with something unusable
+        408 | 502 => Some(ProviderErrorKind::Overloaded),
+        _ if message.contains("no endpoints found") => Some(ProviderErrorKind::ModelNotFound),
+        _ => None,
+    };
+    if let Some(kind) = kind {
+        return Some(Diagnosis::Provider(kind));
+    }
+    
+    let raw = error.and_then(|error| error.pointer("/metadata/raw")).and_then(|raw| raw.as_str());
+    upstream::diagnose(status, body).or_else(|| upstream::diagnose(status, raw?))
+}
+
+// A failure reported in a 200 reply or a stream event, with the HTTP status it stands for in `error.code`
+pub fn body_error(channel: &Channel, body: &Value) -> Option<CCSwitchError> {
+    let error = body.get("error").filter(|error| error.is_object())?;
+    let status = error.get("code")
+        .and_then(|code| code.as_u64())
+        .and_then(|code| StatusCode::from_u16(code as u16).ok())
+        .unwrap_or(StatusCode::BAD_GATEWAY);
+    let text = body.to_string();
+    let message = self::message(&text).unwrap_or_else(|| status.to_string());
+    
+    Some(match diagnose(status, &text) {
+        Some(Diagnosis::Provider(kind)) => CCSwitchError::Provider { channel: channel.name.clone(), kind, status, message },
+        Some(Diagnosis::Auth) => CCSwitchError::Auth(format!("{} rejected the credentials: {} - {}", channel.name, status, message)),
+        None => CCSwitchError::Upstream { status, message },
+    })
+}
@@ -0,0 +1,89 @@
+use crate::client::{usage_tokens, APIClient, APIResponse, Message, RequestOptions};
+use crate::config::{Pipeline, PipelineStage};
+use crate::error::Result;
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+const DEFAULT_REFINE_PROMPT: &str = "The reply above is a draft. Check it for mistakes and gaps, improve it, \
+    and reply with the final answer only.";
+
+// What one stage of a pipeline used, kept on the response beside the combined totals
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageUsage {
+    pub stage: String,
+    pub channel: String,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+}
+
+impl StageUsage {
+    fn new(stage: &str, response: &APIResponse) -> Self {
+        Self {
+            stage: stage.to_string(),
+            channel: response.channel_used.clone(),
+            model: response.model.clone(),
+            usage: response.usage.clone(),
+            cost: response.cost,
+        }
+    }
+}
+
+fn stage_options(name: &'static str, stage: &PipelineStage, options: &RequestOptions, stream: bool) -> RequestOptions {
+    RequestOptions {
+        model: stage.model.clone().or_else(|| options.model.clone()),
+        max_tokens: stage.max_tokens.or(options.max_tokens),
+        temperature: stage.temperature.or(options.temperature),
+        channel: stage.channel.clone(),
+        stream,
+        pipeline_stage: Some(name),
+        ..options.clone()
+    }
+}
+
+// Token counts of all stages added up, in OpenAI's shape
+fn combined_usage(stages: &[StageUsage]) -> Option<Value> {
+    let usages: Vec<&Value> = stages.iter().filter_map(|stage| stage.usage.as_ref()).collect();
+    if usages.is_empty() {
+        return None;
+    }
+    let (mut prompt, mut completion) = (0, 0);
+    for usage in &usages {
+        let (stage_prompt, stage_completion) = usage_tokens(usage);
+        prompt += stage_prompt.unwrap_or(0);
+        completion += stage_completion.unwrap_or(0);
+    }
+    let mut usage = json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": completion,
+        "total_tokens": prompt + completion,
+    });
+    if usages.len() < stages.len() || usages.iter().any(|usage| usage.get("estimated").and_then(|v| v.as_bool()).unwrap_or(false)) {
+        usage["estimated"] = json!(true);
+    }
+    Some(usage)
+}
+
+// Has the draft stage answer the conversation, then sends the draft back to the refine stage for the final reply.
+// Only the refine stage streams.
+pub async fn pipeline_request(client: &APIClient, messages: &[Message], options: RequestOptions, pipeline: &Pipeline) -> Result<APIResponse> {
+    let draft = client.make_chat_request(messages, stage_options("draft", &pipeline.draft, &options, false)).await?;
+    info!("Draft from {} ({} chars), refining", draft.channel_used, draft.content.chars().count());
+    
+    let mut refine_messages = messages.to_vec();
+    refine_messages.push(Message::assistant(&draft.content));
+    refine_messages.push(Message::user(pipeline.refine_prompt.as_deref().unwrap_or(DEFAULT_REFINE_PROMPT)));
+    let mut response = client.make_chat_request(&refine_messages, stage_options("refine", &pipeline.refine, &options, options.stream)).await?;
+    
+    let stages = vec![StageUsage::new("draft", &draft), StageUsage::new("refine", &response)];
+    response.usage = combined_usage(&stages);
+    response.cost = match (draft.cost, response.cost) {
+        (Some(draft), Some(refine)) => Some(draft + refine),
+        (draft, refine) => draft.or(refine),
+    };
+    response.pipeline = stages;
+    Ok(response)
+}
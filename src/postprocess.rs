@@ -0,0 +1,121 @@
+use crate::config::{Channel, PostProcessor};
+use crate::error::{CCSwitchError, Result};
+use regex::Regex;
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::{Mutex, OnceLock};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+// `replace` patterns compiled on first use, rather than for every reply
+static PATTERNS: OnceLock<Mutex<HashMap<String, Regex>>> = OnceLock::new();
+
+fn compile(channel: &Channel, pattern: &str) -> Result<Regex> {
+    let patterns = PATTERNS.get_or_init(Default::default);
+    if let Some(regex) = patterns.lock().unwrap().get(pattern) {
+        return Ok(regex.clone());
+    }
+    let regex = Regex::new(pattern).map_err(|e| CCSwitchError::Config(format!(
+        "Invalid post_process pattern '{}' on channel '{}': {}", pattern, channel.name, e
+    )))?;
+    patterns.lock().unwrap().insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+// Checked when the config is loaded or the channel changes, before any paid request comes back
+pub fn validate(channel: &Channel) -> Result<()> {
+    for processor in &channel.post_process {
+        if let PostProcessor::Replace { pattern, .. } = processor {
+            compile(channel, pattern)?;
+        }
+    }
+    Ok(())
+}
+
+// Runs the channel's post-processors over a reply in order
+pub async fn apply(channel: &Channel, content: String) -> Result<String> {
+    let mut content = content;
+    for processor in &channel.post_process {
+        content = match processor {
+            PostProcessor::StripThink => strip_think(&content),
+            PostProcessor::NormalizeWhitespace => normalize_whitespace(&content),
+            PostProcessor::Replace { pattern, replacement } => {
+                compile(channel, pattern)?.replace_all(&content, replacement.as_str()).into_owned()
+            }
+            PostProcessor::Command { command } => filter(channel, command, &content).await?,
+        };
+    }
+    Ok(content)
+}
+
+fn strip_think(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("<think>") {
+        result.push_str(&rest[..start]);
+        // A reply cut off mid-thought has no closing tag; drop everything after the opening one
+        rest = match rest[start..].find("</think>") {
+            Some(end) => &rest[start + end + "</think>".len()..],
+            None => "",
+        };
+    }
+    result.push_str(rest);
+    result.trim_start().to_string()
+}
+
+fn normalize_whitespace(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut blank_lines = 0;
+    for line in content.split('\n') {
+        let line: String = line.chars()
+            .filter(|c| !matches!(c, '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{FEFF}'))
+            .map(|c| if c != '\t' && c.is_whitespace() { ' ' } else { c })
+            .collect();
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_lines += 1;
+            if blank_lines > 1 {
+                continue;
+            }
+        } else {
+            blank_lines = 0;
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    result.trim().to_string()
+}
+
+// The reply goes to the command's stdin; a failing command fails the request rather than passing the reply through
+async fn filter(channel: &Channel, command: &str, content: &str) -> Result<String> {
+    let mut shell = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+    let mut child = shell
+        .args([if cfg!(windows) { "/C" } else { "-c" }, command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| CCSwitchError::Config(format!("Post-processor for '{}' could not be run: {}", channel.name, e)))?;
+    
+    // Written while the output is read, so a long reply cannot fill both pipes and stall
+    let stdin = child.stdin.take();
+    let write = async {
+        if let Some(mut stdin) = stdin {
+            // A filter may exit without reading everything; its exit status decides the outcome
+            let _ = stdin.write_all(content.as_bytes()).await;
+        }
+    };
+    let ((), output) = tokio::join!(write, child.wait_with_output());
+    let output = output?;
+    
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut message = format!("Post-processor for '{}' exited with {}", channel.name, output.status);
+        if !stderr.trim().is_empty() {
+            message.push_str(&format!(": {}", stderr.trim()));
+        }
+        return Err(CCSwitchError::Config(message));
+    }
+    
+    Ok(String::from_utf8_lossy(&output.stdout).trim_end().to_string())
+}
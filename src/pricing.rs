@@ -0,0 +1,93 @@
+use crate::client::usage_tokens;
+use crate::config::{Channel, Config, ModelPrice};
+use serde_json::Value;
+use std::collections::HashMap;
+
+// List prices in USD per million tokens (input, output); entries in `pricing` take precedence
+const DEFAULT_PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-5", 1.25, 10.0),
+    ("gpt-5-mini", 0.25, 2.0),
+    ("gpt-5-nano", 0.05, 0.4),
+    ("gpt-4.1", 2.0, 8.0),
+    ("gpt-4.1-mini", 0.4, 1.6),
+    ("gpt-4.1-nano", 0.1, 0.4),
+    ("gpt-4o", 2.5, 10.0),
+    ("gpt-4o-mini", 0.15, 0.6),
+    ("gpt-4-turbo", 10.0, 30.0),
+    ("gpt-4", 30.0, 60.0),
+    ("gpt-3.5-turbo", 0.5, 1.5),
+    ("o1", 15.0, 60.0),
+    ("o1-mini", 1.1, 4.4),
+    ("o3", 2.0, 8.0),
+    ("o3-mini", 1.1, 4.4),
+    ("o4-mini", 1.1, 4.4),
+    ("claude-opus-4", 15.0, 75.0),
+    ("claude-opus-4-5", 5.0, 25.0),
+    ("claude-sonnet-4", 3.0, 15.0),
+    ("claude-haiku-4-5", 1.0, 5.0),
+    ("claude-3-7-sonnet", 3.0, 15.0),
+    ("claude-3-5-sonnet", 3.0, 15.0),
+    ("claude-3-5-haiku", 0.8, 4.0),
+    ("claude-3-opus", 15.0, 75.0),
+    ("claude-3-haiku", 0.25, 1.25),
+];
+
+// Matches the model itself or a dated/versioned variant such as `gpt-4o-2024-08-06`
+fn covers(name: &str, model: &str) -> bool {
+    model.strip_prefix(name).is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+}
+
+fn configured(pricing: &HashMap<String, ModelPrice>, model: &str) -> Option<ModelPrice> {
+    pricing.iter()
+        .filter(|(name, _)| covers(name, model))
+        .max_by_key(|(name, _)| name.len())
+        .map(|(_, price)| *price)
+}
+
+// The most specific price the channel sets, then the global `pricing`, then the built-in table
+pub fn price(config: &Config, channel: Option<&Channel>, model: &str) -> Option<ModelPrice> {
+    channel.and_then(|channel| configured(&channel.pricing, model))
+        .or_else(|| configured(&config.pricing, model))
+        .or_else(|| {
+            DEFAULT_PRICES.iter()
+                .filter(|(name, _, _)| covers(name, model))
+                .max_by_key(|(name, _, _)| name.len())
+                .map(|&(_, input, output)| ModelPrice { input, output })
+        })
+}
+
+// Estimated cost in USD of a reply's usage, when the model has a price
+pub fn cost(config: &Config, channel: Option<&Channel>, model: &str, usage: &Value) -> Option<f64> {
+    let (prompt_tokens, completion_tokens) = match usage_tokens(usage) {
+        (None, None) => return None,
+        (prompt, completion) => (prompt.unwrap_or(0), completion.unwrap_or(0)),
+    };
+    let price = price(config, channel, model)?;
+    Some((prompt_tokens as f64 * price.input + completion_tokens as f64 * price.output) / 1_000_000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn prices_cover_dated_variants_only() {
+        assert!(covers("gpt-4o", "gpt-4o"));
+        assert!(covers("gpt-4o", "gpt-4o-2024-08-06"));
+        assert!(covers("gpt-4o", "gpt-4o-mini"));
+        assert!(!covers("gpt-4", "gpt-4o"));
+        assert!(!covers("o1", "o1mini"));
+        assert!(!covers("gpt-4o-mini", "gpt-4o"));
+    }
+    
+    #[test]
+    fn the_longest_name_wins() {
+        let pricing = HashMap::from([
+            ("gpt-4o".to_string(), ModelPrice { input: 2.5, output: 10.0 }),
+            ("gpt-4o-mini".to_string(), ModelPrice { input: 0.15, output: 0.6 }),
+        ]);
+        assert_eq!(configured(&pricing, "gpt-4o-mini-2024-07-18").map(|price| price.input), Some(0.15));
+        assert_eq!(configured(&pricing, "gpt-4o-2024-08-06").map(|price| price.input), Some(2.5));
+        assert!(configured(&pricing, "gpt-4").is_none());
+    }
+}
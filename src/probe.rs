@@ -0,0 +1,192 @@
+use crate::client::{self, APIClient};
+use crate::config::{now_secs, ApiFormat, Capabilities, Channel, EndpointKind};
+use crate::error::{CCSwitchError, Result};
+use log::info;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+// Largest max_tokens tried; a provider accepting it is treated as having no known limit
+const MAX_TOKENS_CEILING: u32 = 1 << 20;
+// The baseline request's max_tokens, known to be accepted once it succeeds
+const BASELINE_MAX_TOKENS: u32 = 16;
+
+// A 1x1 PNG for the vision probe
+const PIXEL_PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mP8z8DwHwAFBQIAX8jx0gAAAABJRU5ErkJggg==";
+
+struct Probe<'a> {
+    client: &'a APIClient,
+    channel: &'a Channel,
+    model: &'a str,
+    anthropic: bool,
+}
+
+// Detects features by sending small requests that need them; undeclared features keep their current value
+pub async fn probe_channel(client: &APIClient, channel: &Channel, model: &str) -> Result<Capabilities> {
+    let anthropic = match (channel.api_format, channel.endpoint_kind) {
+        (ApiFormat::Anthropic, _) => true,
+        (ApiFormat::Openai | ApiFormat::Azure | ApiFormat::Openrouter, EndpointKind::Chat) => false,
+        _ => return Err(CCSwitchError::Config(format!(
+            "Channel {} cannot be probed: only OpenAI-compatible chat and Anthropic channels are supported",
+            channel.name
+        ))),
+    };
+    let probe = Probe { client, channel, model, anthropic };
+    
+    // Without a working plain request every feature would look unsupported
+    probe.send(&probe.payload(json!("Reply with OK."), BASELINE_MAX_TOKENS)).await?;
+    
+    let mut capabilities = channel.capabilities.clone();
+    capabilities.streaming = Some(probe.streaming().await?);
+    capabilities.tools = Some(probe.tools().await?);
+    capabilities.vision = Some(probe.vision().await?);
+    capabilities.json_mode = probe.json_mode().await?.or(capabilities.json_mode);
+    capabilities.max_output_tokens = probe.max_output_tokens().await?;
+    capabilities.probed_at = Some(now_secs());
+    Ok(capabilities)
+}
+
+// A 4xx other than rate limiting means the provider turned the feature down
+fn refused(error: &CCSwitchError) -> bool {
+    match error {
+        CCSwitchError::Upstream { status, .. } => status.is_client_error() && *status != StatusCode::TOO_MANY_REQUESTS,
+        CCSwitchError::Provider { kind, status, .. } => status.is_client_error() && !kind.is_channel_specific(),
+        _ => false,
+    }
+}
+
+impl Probe<'_> {
+    fn payload(&self, content: Value, max_tokens: u32) -> Value {
+        json!({
+            "model": self.model,
+            "messages": [{ "role": "user", "content": content }],
+            "max_tokens": max_tokens
+        })
+    }
+    
+    async fn send(&self, payload: &Value) -> Result<reqwest::Response> {
+        self.client.send_raw(self.channel, self.model, payload).await
+    }
+    
+    // The reply body, or `None` when the provider refused the request
+    async fn send_json(&self, payload: &Value) -> Result<Option<Value>> {
+        match self.send(payload).await {
+            Ok(response) => {
+                let limit = self.client.get_channel_manager().config.max_response_bytes;
+                let body = client::read_limited(response, limit)
+                    .await
+                    .map_err(CCSwitchError::Network)?
+                    .ok_or_else(|| client::too_large(self.channel, limit))?;
+                let body = serde_json::from_slice(&body)
+                    .map_err(|e| CCSwitchError::Channel(format!("Failed to parse response: {}", e)))?;
+                Ok(Some(body))
+            }
+            Err(e) if refused(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn streaming(&self) -> Result<bool> {
+        info!("Probing streaming on {}", self.channel.name);
+        let mut payload = self.payload(json!("Reply with OK."), BASELINE_MAX_TOKENS);
+        payload["stream"] = json!(true);
+        
+        // Servers that ignore `stream` answer in one piece
+        match self.send(&payload).await {
+            Ok(response) => Ok(client::is_event_stream(&response)),
+            Err(e) if refused(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    
+    async fn tools(&self) -> Result<bool> {
+        info!("Probing tool use on {}", self.channel.name);
+        let mut payload = self.payload(json!("What time is it?"), 64);
+        
+        // Forcing a call tells real support apart from servers that drop unknown fields
+        if self.anthropic {
+            payload["tools"] = json!([{
+                "name": "get_time",
+                "description": "Returns the current time",
+                "input_schema": { "type": "object", "properties": {} }
+            }]);
+            payload["tool_choice"] = json!({ "type": "any" });
+        } else {
+            payload["tools"] = json!([{
+                "type": "function",
+                "function": {
+                    "name": "get_time",
+                    "description": "Returns the current time",
+                    "parameters": { "type": "object", "properties": {} }
+                }
+            }]);
+            payload["tool_choice"] = json!("required");
+        }
+        
+        let Some(reply) = self.send_json(&payload).await? else {
+            return Ok(false);
+        };
+        let called = if self.anthropic {
+            reply["content"].as_array()
+                .is_some_and(|blocks| blocks.iter().any(|block| block["type"] == "tool_use"))
+        } else {
+            reply.pointer("/choices/0/message/tool_calls")
+                .and_then(Value::as_array)
+                .is_some_and(|calls| !calls.is_empty())
+        };
+        Ok(called)
+    }
+    
+    async fn vision(&self) -> Result<bool> {
+        info!("Probing image input on {}", self.channel.name);
+        let question = json!({ "type": "text", "text": "What colour is this image? Answer in one word." });
+        let image = if self.anthropic {
+            json!({ "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": PIXEL_PNG } })
+        } else {
+            json!({ "type": "image_url", "image_url": { "url": format!("data:image/png;base64,{}", PIXEL_PNG) } })
+        };
+        
+        Ok(self.send_json(&self.payload(json!([image, question]), BASELINE_MAX_TOKENS)).await?.is_some())
+    }
+    
+    // The Messages API has no JSON mode to probe, so Anthropic channels stay undetermined
+    async fn json_mode(&self) -> Result<Option<bool>> {
+        if self.anthropic {
+            return Ok(None);
+        }
+        info!("Probing JSON mode on {}", self.channel.name);
+        let mut payload = self.payload(json!("Reply with a JSON object whose \"ok\" key is true."), 64);
+        payload["response_format"] = json!({ "type": "json_object" });
+        
+        let Some(reply) = self.send_json(&payload).await? else {
+            return Ok(Some(false));
+        };
+        let is_object = reply.pointer("/choices/0/message/content")
+            .and_then(Value::as_str)
+            .and_then(|content| serde_json::from_str::<Value>(content).ok())
+            .is_some_and(|content| content.is_object());
+        Ok(Some(is_object))
+    }
+    
+    // Binary search between the baseline and the ceiling; providers check max_tokens before generating
+    async fn max_output_tokens(&self) -> Result<Option<u32>> {
+        info!("Searching for the output token limit on {}", self.channel.name);
+        if self.accepts(MAX_TOKENS_CEILING).await? {
+            return Ok(None);
+        }
+        
+        let (mut accepted, mut rejected) = (BASELINE_MAX_TOKENS, MAX_TOKENS_CEILING);
+        while rejected - accepted > 1 {
+            let middle = accepted + (rejected - accepted) / 2;
+            if self.accepts(middle).await? {
+                accepted = middle;
+            } else {
+                rejected = middle;
+            }
+        }
+        Ok(Some(accepted))
+    }
+    
+    async fn accepts(&self, max_tokens: u32) -> Result<bool> {
+        Ok(self.send_json(&self.payload(json!("Reply with OK."), max_tokens)).await?.is_some())
+    }
+}
@@ -0,0 +1,80 @@
+use crate::config::{Channel, HealthCheck};
+use crate::vllm;
+use serde_json::{json, Map, Value};
+
+const VLLM_FIRST_REQUEST_TIMEOUT_SECONDS: u64 = 300;
+
+// Well-known providers that `add --provider` can set up without looking anything up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Provider {
+    /// DeepSeek (deepseek-chat, deepseek-reasoner)
+    Deepseek,
+    /// Alibaba Cloud Qwen through DashScope's OpenAI-compatible mode
+    Qwen,
+    /// Moonshot AI (Kimi)
+    Moonshot,
+    /// A self-hosted vLLM server, by default on localhost
+    Vllm,
+}
+
+impl Provider {
+    pub fn url(self) -> &'static str {
+        match self {
+            Provider::Deepseek => "https://api.deepseek.com/chat/completions",
+            Provider::Qwen => "https://dashscope.aliyuncs.com/compatible-mode/v1/chat/completions",
+            Provider::Moonshot => "https://api.moonshot.cn/v1/chat/completions",
+            Provider::Vllm => "http://localhost:8000/v1/chat/completions",
+        }
+    }
+    
+    pub fn models(self) -> Vec<String> {
+        let models: &[&str] = match self {
+            Provider::Deepseek => &["deepseek-chat", "deepseek-reasoner"],
+            Provider::Qwen => &["qwen-max", "qwen-plus", "qwen-turbo", "qwen-long", "qwen3-*"],
+            Provider::Moonshot => &["moonshot-v1-8k", "moonshot-v1-32k", "moonshot-v1-128k", "kimi-*"],
+            // Whatever the server was started with, read from `/v1/models`
+            Provider::Vllm => &[],
+        };
+        models.iter().map(|model| model.to_string()).collect()
+    }
+    
+    fn extra_body(self) -> Map<String, Value> {
+        let mut body = Map::new();
+        // Qwen3 models refuse non-streaming calls while thinking is on, which is their default
+        if self == Provider::Qwen {
+            body.insert("enable_thinking".to_string(), json!(false));
+        }
+        body
+    }
+    
+    fn max_temperature(self) -> Option<f32> {
+        match self {
+            // DeepSeek and vLLM accept OpenAI's full 0-2 range
+            Provider::Deepseek | Provider::Vllm => None,
+            // DashScope excludes 2 itself
+            Provider::Qwen => Some(1.99),
+            Provider::Moonshot => Some(1.0),
+        }
+    }
+    
+    // Request quirks of the provider; all of them take OpenAI chat requests, and the URL and models are set when the channel is added
+    pub fn configure(self, channel: &mut Channel) {
+        channel.extra_body = self.extra_body();
+        channel.max_temperature = self.max_temperature();
+        
+        if self == Provider::Vllm {
+            channel.discover_models = channel.models.is_empty();
+            // Listing models is free and fails while the server is still loading
+            channel.health_check = Some(HealthCheck {
+                method: "GET".to_string(),
+                path: "/v1/models".to_string(),
+                expected_status: None,
+                body_contains: None,
+            });
+            // Loading weights and capturing CUDA graphs can hold up the first reply for minutes
+            channel.first_request_timeout_seconds = Some(VLLM_FIRST_REQUEST_TIMEOUT_SECONDS);
+            // Left to the model's own generation config unless a request asks for them
+            channel.sampling_params = vllm::SAMPLING_PARAMS.iter().map(|param| param.to_string()).collect();
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::config::{Channel, Config, HttpVersion, IpVersion, TransportConfig};
+use crate::dns::{CachingResolver, IpVersionResolver};
+use crate::error::{CCSwitchError, Result};
+use crate::i18n::tr;
+use reqwest::{Client, Proxy};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+
+type ClientKey = (bool, TransportConfig);
+
+// One client per distinct proxy/transport combination, shared by the channels that use it
+pub struct HttpClients {
+    clients: HashMap<ClientKey, Client>,
+    resolver: Option<Arc<CachingResolver>>,
+}
+
+impl HttpClients {
+    pub fn new(timeout: Duration, config: &Config) -> Result<Self> {
+        Self::with_resolver(timeout, config, CachingResolver::from_config(&config.dns))
+    }
+    
+    // Shares another set's DNS cache, so one flush reaches every client in the process
+    pub fn with_resolver(timeout: Duration, config: &Config, resolver: Option<Arc<CachingResolver>>) -> Result<Self> {
+        let mut clients = HashMap::new();
+        
+        // Channels added later in the process start from the defaults
+        let keys = [(true, TransportConfig::default()), (false, TransportConfig::default())]
+            .into_iter()
+            .chain(config.channels.values().map(client_key));
+        for key in keys {
+            if let Entry::Vacant(entry) = clients.entry(key) {
+                let client = build_client(timeout, entry.key(), resolver.clone())?;
+                entry.insert(client);
+            }
+        }
+        
+        Ok(Self { clients, resolver })
+    }
+    
+    pub fn dns_cache(&self) -> Option<Arc<CachingResolver>> {
+        self.resolver.clone()
+    }
+    
+    // False when the DNS cache is off, so there is nothing to clear
+    pub fn clear_dns_cache(&self) -> bool {
+        let Some(resolver) = &self.resolver else {
+            return false;
+        };
+        resolver.clear();
+        true
+    }
+    
+    pub fn for_channel(&self, channel: &Channel) -> &Client {
+        self.clients
+            .get(&client_key(channel))
+            .unwrap_or_else(|| &self.clients[&(channel.use_proxy, TransportConfig::default())])
+    }
+}
+
+fn client_key(channel: &Channel) -> ClientKey {
+    (channel.use_proxy, channel.transport.clone())
+}
+
+fn build_client(timeout: Duration, (use_proxy, transport): &ClientKey, resolver: Option<Arc<CachingResolver>>) -> Result<Client> {
+    let mut builder = Client::builder()
+        .timeout(timeout)
+        .no_proxy();
+    
+    if transport.ip_version != IpVersion::Auto {
+        builder = builder.dns_resolver(IpVersionResolver::new(resolver, transport.ip_version));
+    } else if let Some(resolver) = resolver {
+        builder = builder.dns_resolver(resolver);
+    }
+    
+    // Resolve proxies ourselves so `list` reports exactly what requests will use,
+    // instead of reqwest's platform-dependent defaults
+    if *use_proxy {
+        builder = builder.proxy(Proxy::custom(proxy_for_url));
+    }
+    
+    builder = match transport.http_version {
+        HttpVersion::Auto => builder,
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2 => builder.http2_prior_knowledge(),
+    };
+    if transport.http2_adaptive_window {
+        builder = builder.http2_adaptive_window(true);
+    }
+    if let Some(seconds) = transport.tcp_keepalive_seconds {
+        builder = builder.tcp_keepalive(Duration::from_secs(seconds));
+    }
+    if let Some(seconds) = transport.pool_idle_timeout_seconds {
+        builder = builder.pool_idle_timeout(Duration::from_secs(seconds));
+    }
+    if let Some(ms) = transport.connect_timeout_ms {
+        builder = builder.connect_timeout(Duration::from_millis(ms));
+    }
+    
+    builder.build().map_err(CCSwitchError::Network)
+}
+
+pub fn describe(channel: &Channel) -> String {
+    if !channel.use_proxy {
+        return tr("proxy-disabled", &[]);
+    }
+    
+    match Url::parse(&channel.url).ok().and_then(|url| proxy_for_url(&url)) {
+        Some(proxy) => tr("proxy-via", &[("proxy", redact(&proxy))]),
+        None => tr("proxy-direct", &[]),
+    }
+}
+
+fn proxy_for_url(url: &Url) -> Option<Url> {
+    let host = url.host_str()?;
+    if bypassed(host) {
+        return None;
+    }
+    
+    let scheme_var = if url.scheme() == "https" { "HTTPS_PROXY" } else { "HTTP_PROXY" };
+    let value = env_var(scheme_var).or_else(|| env_var("ALL_PROXY"))?;
+    
+    // Bare `host:port` is common in corporate setups
+    Url::parse(&value)
+        .ok()
+        .filter(|proxy| proxy.has_host())
+        .or_else(|| Url::parse(&format!("http://{}", value)).ok())
+}
+
+fn bypassed(host: &str) -> bool {
+    let Some(no_proxy) = env_var("NO_PROXY") else {
+        return false;
+    };
+    
+    let host = host.trim_start_matches('[').trim_end_matches(']').to_lowercase();
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().to_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            if entry == "*" {
+                return true;
+            }
+            let domain = entry.trim_start_matches("*.").trim_start_matches('.');
+            host == domain || host.ends_with(&format!(".{}", domain))
+        })
+}
+
+// Upper-case wins, but the lower-case spelling is just as common
+fn env_var(name: &str) -> Option<String> {
+    env::var(name)
+        .or_else(|_| env::var(name.to_lowercase()))
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+fn redact(proxy: &Url) -> String {
+    let mut shown = proxy.clone();
+    if shown.password().is_some() {
+        let _ = shown.set_password(Some("***"));
+    }
+    shown.to_string()
+}
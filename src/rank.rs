@@ -0,0 +1,128 @@
+use crate::channel::ChannelManager;
+use crate::config::{now_secs, Channel};
+use crate::pricing;
+use crate::stats::{self, Period, UsageRow};
+use log::warn;
+use serde::Serialize;
+
+// Weights of the composite score (out of 100): probed availability, recorded error rate, latency and price
+const AVAILABILITY_WEIGHT: f64 = 40.0;
+const RELIABILITY_WEIGHT: f64 = 20.0;
+const LATENCY_WEIGHT: f64 = 20.0;
+const COST_WEIGHT: f64 = 20.0;
+
+#[derive(Debug, Serialize)]
+pub struct ChannelRank {
+    pub name: String,
+    pub samples: usize,
+    pub successes: usize,
+    pub p50_ms: Option<u64>,
+    pub p95_ms: Option<u64>,
+    // Share of the requests recorded over the past week that failed; None without usage stats
+    pub error_rate: Option<f64>,
+    // USD per million tokens, input and output averaged, for the channel's first model with a price
+    pub price: Option<f64>,
+    pub score: f64,
+    pub current_priority: u32,
+    pub suggested_priority: u32,
+}
+
+impl ChannelRank {
+    pub fn success_rate(&self) -> f64 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        self.successes as f64 / self.samples as f64
+    }
+}
+
+// Probes every enabled channel `samples` times and orders them best first
+pub async fn rank_channels(manager: &ChannelManager, samples: usize) -> Vec<ChannelRank> {
+    let mut channels: Vec<&Channel> = manager.list_channels().into_iter().filter(|ch| ch.is_active()).collect();
+    channels.sort_by_key(|ch| ch.priority);
+    
+    let recorded = match stats::summarize(now_secs().saturating_sub(Period::Week.seconds()), None, None).await {
+        Ok(summary) => summary.channels,
+        Err(e) => {
+            warn!("Ranking without recorded usage: {}", e);
+            Vec::new()
+        }
+    };
+    
+    let mut ranks = Vec::new();
+    for channel in channels {
+        let mut latencies = Vec::new();
+        for _ in 0..samples {
+            let status = manager.test_channel(channel).await;
+            if status.available {
+                latencies.push(status.response_time_ms.unwrap_or_default());
+            }
+        }
+        latencies.sort_unstable();
+        
+        let rank = ChannelRank {
+            name: channel.name.clone(),
+            samples,
+            successes: latencies.len(),
+            p50_ms: percentile(&latencies, 50),
+            p95_ms: percentile(&latencies, 95),
+            error_rate: recorded.iter().find(|row| row.name == channel.name).and_then(error_rate),
+            price: price(manager, channel),
+            score: 0.0,
+            current_priority: channel.priority,
+            suggested_priority: 0,
+        };
+        ranks.push(rank);
+    }
+    
+    // Prices only mean something next to each other
+    let cheapest = ranks.iter().filter_map(|rank| rank.price).min_by(f64::total_cmp);
+    for rank in &mut ranks {
+        rank.score = score(rank, cheapest);
+    }
+    
+    ranks.sort_by(|a, b| b.score.total_cmp(&a.score));
+    for (index, rank) in ranks.iter_mut().enumerate() {
+        rank.suggested_priority = index as u32;
+    }
+    ranks
+}
+
+fn error_rate(row: &UsageRow) -> Option<f64> {
+    (row.requests > 0).then(|| row.failures as f64 / row.requests as f64)
+}
+
+fn price(manager: &ChannelManager, channel: &Channel) -> Option<f64> {
+    let config = &manager.config;
+    channel.named_models()
+        .chain(config.default_model.as_deref())
+        .find_map(|model| pricing::price(config, Some(channel), model))
+        .map(|price| (price.input + price.output) / 2.0)
+}
+
+fn score(rank: &ChannelRank, cheapest: Option<f64>) -> f64 {
+    // A channel answering within a second keeps most of its latency points
+    let latency = match rank.p95_ms {
+        Some(ms) => 1000.0 / (1000.0 + ms as f64),
+        None => 0.0,
+    };
+    // Without recorded traffic the probes are all there is to go on
+    let reliability = rank.error_rate.map_or(rank.success_rate(), |rate| 1.0 - rate);
+    // The cheapest channel gets full points; an unknown price counts as middling
+    let cost = match (rank.price, cheapest) {
+        (Some(price), Some(cheapest)) if price > 0.0 => cheapest / price,
+        (Some(_), Some(_)) => 1.0,
+        (None, Some(_)) => 0.5,
+        (_, None) => 1.0,
+    };
+    rank.success_rate() * AVAILABILITY_WEIGHT + reliability * RELIABILITY_WEIGHT + latency * LATENCY_WEIGHT + cost * COST_WEIGHT
+}
+
+// Nearest-rank percentile of sorted samples
+fn percentile(sorted: &[u64], pct: usize) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let index = (pct * sorted.len()).div_ceil(100).max(1) - 1;
+    sorted.get(index).copied()
+}
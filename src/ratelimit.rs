@@ -0,0 +1,103 @@
+use crate::config::Channel;
+use log::debug;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Token buckets holding up to a minute's allowance, refilled continuously
+struct Buckets {
+    requests: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Buckets {
+    fn full(channel: &Channel) -> Self {
+        Buckets {
+            requests: channel.requests_per_minute.unwrap_or_default() as f64,
+            tokens: channel.tokens_per_minute.unwrap_or_default() as f64,
+            updated: Instant::now(),
+        }
+    }
+    
+    fn refill(&mut self, channel: &Channel) {
+        let now = Instant::now();
+        let minutes = (now - self.updated).as_secs_f64() / 60.0;
+        self.updated = now;
+        if let Some(rpm) = channel.requests_per_minute {
+            self.requests = (self.requests + rpm as f64 * minutes).min(rpm as f64);
+        }
+        if let Some(tpm) = channel.tokens_per_minute {
+            self.tokens = (self.tokens + tpm as f64 * minutes).min(tpm as f64);
+        }
+    }
+    
+    // Time until a request may go out: one request left and the token allowance not overdrawn
+    fn wait(&self, channel: &Channel) -> Option<Duration> {
+        let mut minutes: f64 = 0.0;
+        if let Some(rpm) = channel.requests_per_minute.filter(|_| self.requests < 1.0) {
+            minutes = minutes.max((1.0 - self.requests) / rpm.max(1) as f64);
+        }
+        if let Some(tpm) = channel.tokens_per_minute.filter(|_| self.tokens <= 0.0) {
+            minutes = minutes.max((1.0 - self.tokens) / tpm.max(1) as f64);
+        }
+        (minutes > 0.0).then(|| Duration::from_secs_f64(minutes * 60.0))
+    }
+}
+
+// Client-side RPM/TPM allowances per channel, kept for the life of the process
+#[derive(Default)]
+pub struct RateLimiters {
+    buckets: Mutex<HashMap<String, Buckets>>,
+}
+
+fn is_limited(channel: &Channel) -> bool {
+    channel.requests_per_minute.is_some() || channel.tokens_per_minute.is_some()
+}
+
+impl RateLimiters {
+    // How long until the channel has room for another request, if it has none now
+    pub fn blocked_for(&self, channel: &Channel) -> Option<Duration> {
+        if !is_limited(channel) {
+            return None;
+        }
+        let mut buckets = self.buckets.lock().unwrap();
+        let buckets = buckets.entry(channel.name.clone()).or_insert_with(|| Buckets::full(channel));
+        buckets.refill(channel);
+        buckets.wait(channel)
+    }
+    
+    // Waits for room, then takes one request and the estimated prompt tokens
+    pub async fn acquire(&self, channel: &Channel, tokens: u64) {
+        if !is_limited(channel) {
+            return;
+        }
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let buckets = buckets.entry(channel.name.clone()).or_insert_with(|| Buckets::full(channel));
+                buckets.refill(channel);
+                match buckets.wait(channel) {
+                    Some(wait) => wait,
+                    None => {
+                        buckets.requests -= 1.0;
+                        buckets.tokens -= tokens as f64;
+                        return;
+                    }
+                }
+            };
+            debug!("Channel {} is over its rate limit, waiting {}ms", channel.name, wait.as_millis());
+            tokio::time::sleep(wait).await;
+        }
+    }
+    
+    // Settles the difference between the estimate and the tokens the reply reported; negative refunds
+    pub fn adjust_tokens(&self, channel: &Channel, delta: i64) {
+        if channel.tokens_per_minute.is_none() {
+            return;
+        }
+        if let Some(buckets) = self.buckets.lock().unwrap().get_mut(&channel.name) {
+            buckets.tokens -= delta as f64;
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::client::{usage_tokens, APIResponse};
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Slowest items kept in a report
+const SLOWEST_ITEMS: usize = 3;
+
+// Outcome of a batch of prompts, comparable across runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RunReport {
+    pub started_at: u64,
+    pub wall_clock_ms: u64,
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    // Channels abandoned mid-request in favour of another
+    pub failovers: usize,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    // Estimated from the pricing table; absent when no reply had a known price
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    pub channels: BTreeMap<String, ChannelTally>,
+    pub slowest: Vec<ItemTiming>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ChannelTally {
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemTiming {
+    pub index: usize,
+    pub prompt: String,
+    pub channel: String,
+    pub latency_ms: u64,
+}
+
+impl RunReport {
+    pub fn new(started_at: u64, total: usize) -> Self {
+        Self { started_at, total, ..Self::default() }
+    }
+    
+    pub fn record(&mut self, item: ItemTiming, result: &Result<APIResponse>) {
+        match result {
+            Ok(response) => {
+                self.succeeded += 1;
+                self.failovers += response.skipped_channels.len();
+                self.channels.entry(response.channel_used.clone()).or_default().succeeded += 1;
+                
+                let (prompt, completion) = response.usage.as_ref().map(usage_tokens).unwrap_or((None, None));
+                self.prompt_tokens += prompt.unwrap_or(0);
+                self.completion_tokens += completion.unwrap_or(0);
+                if let Some(cost) = response.cost {
+                    self.cost = Some(self.cost.unwrap_or(0.0) + cost);
+                }
+            }
+            Err(_) => {
+                self.failed += 1;
+                self.channels.entry(item.channel.clone()).or_default().failed += 1;
+            }
+        }
+        
+        self.slowest.push(item);
+        self.slowest.sort_by_key(|item| std::cmp::Reverse(item.latency_ms));
+        self.slowest.truncate(SLOWEST_ITEMS);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::config::{Channel, Config, RoutingStrategyKind};
+use crate::pricing;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// What a strategy may consult besides the channels themselves
+pub struct RoutingContext<'a> {
+    pub config: &'a Config,
+    pub model: &'a str,
+    // Smoothed latency of recent successful requests and probes
+    pub latencies: &'a HashMap<String, Duration>,
+    // `--selection-seed`: strategies with a random or rotating choice make it from this alone
+    pub seed: Option<u64>,
+}
+
+// Orders the candidates for a request, most preferred first; health, circuits and limits are then
+// checked in that order, so later channels are the failover sequence
+pub trait RoutingStrategy: Send + Sync {
+    fn order(&self, channels: &mut [&Channel], context: &RoutingContext);
+}
+
+pub fn strategy(kind: RoutingStrategyKind) -> Box<dyn RoutingStrategy> {
+    match kind {
+        RoutingStrategyKind::Priority => Box::new(Priority),
+        RoutingStrategyKind::RoundRobin => Box::new(RoundRobin::default()),
+        RoutingStrategyKind::Latency => Box::new(Latency),
+        RoutingStrategyKind::Cost => Box::new(Cost),
+        RoutingStrategyKind::Random => Box::new(Random),
+        RoutingStrategyKind::Weighted => Box::new(Weighted::default()),
+    }
+}
+
+// Every strategy breaks ties by priority
+fn by_priority(channels: &mut [&Channel]) {
+    channels.sort_by_key(|ch| ch.priority);
+}
+
+pub struct Priority;
+
+impl RoutingStrategy for Priority {
+    fn order(&self, channels: &mut [&Channel], _context: &RoutingContext) {
+        by_priority(channels);
+    }
+}
+
+// Each request starts one channel further along the priority order
+#[derive(Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl RoutingStrategy for RoundRobin {
+    fn order(&self, channels: &mut [&Channel], context: &RoutingContext) {
+        by_priority(channels);
+        if !channels.is_empty() {
+            let turn = match context.seed {
+                Some(seed) => (seed % channels.len() as u64) as usize,
+                None => self.next.fetch_add(1, Ordering::Relaxed) % channels.len(),
+            };
+            channels.rotate_left(turn);
+        }
+    }
+}
+
+// Fastest first; channels not measured yet come last, so each gets tried once something fails
+pub struct Latency;
+
+impl RoutingStrategy for Latency {
+    fn order(&self, channels: &mut [&Channel], context: &RoutingContext) {
+        by_priority(channels);
+        channels.sort_by_key(|ch| context.latencies.get(&ch.name).copied().unwrap_or(Duration::MAX));
+    }
+}
+
+// Cheapest first by the channel's price for the model; unpriced channels come last
+pub struct Cost;
+
+impl RoutingStrategy for Cost {
+    fn order(&self, channels: &mut [&Channel], context: &RoutingContext) {
+        by_priority(channels);
+        channels.sort_by(|a, b| {
+            let price = |channel: &Channel| pricing::price(context.config, Some(channel), context.model)
+                .map_or(f64::INFINITY, |price| price.input + price.output);
+            price(a).total_cmp(&price(b))
+        });
+    }
+}
+
+pub struct Random;
+
+impl RoutingStrategy for Random {
+    fn order(&self, channels: &mut [&Channel], context: &RoutingContext) {
+        // A seeded shuffle needs the same starting order every time
+        by_priority(channels);
+        let mut rng = context.seed.map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed);
+        rng.shuffle(channels);
+    }
+}
+
+// Smooth weighted round robin: over any stretch of requests each channel goes first in proportion
+// to its weight, evenly interleaved rather than in runs; the rest follow by weight
+#[derive(Default)]
+pub struct Weighted {
+    current: Mutex<HashMap<String, i64>>,
+}
+
+impl Weighted {
+    // Advances the rotation by one request; returns the index of the channel that goes first
+    fn step(current: &mut HashMap<String, i64>, channels: &[&Channel], total: i64) -> usize {
+        let mut chosen = 0;
+        let mut best = i64::MIN;
+        for (index, channel) in channels.iter().enumerate() {
+            let score = current.entry(channel.name.clone()).or_default();
+            *score += i64::from(channel.weight);
+            if *score > best {
+                best = *score;
+                chosen = index;
+            }
+        }
+        *current.get_mut(&channels[chosen].name).unwrap() -= total;
+        chosen
+    }
+}
+
+impl RoutingStrategy for Weighted {
+    fn order(&self, channels: &mut [&Channel], context: &RoutingContext) {
+        by_priority(channels);
+        channels.sort_by_key(|ch| std::cmp::Reverse(ch.weight));
+        
+        let total: i64 = channels.iter().map(|ch| i64::from(ch.weight)).sum();
+        if total == 0 {
+            return;
+        }
+        let chosen = match context.seed {
+            // The pick a fresh rotation makes on request `seed`, leaving the shared one alone
+            Some(seed) => {
+                let mut current = HashMap::new();
+                let mut chosen = 0;
+                for _ in 0..=seed % total as u64 {
+                    chosen = Self::step(&mut current, channels, total);
+                }
+                chosen
+            }
+            None => Self::step(&mut self.current.lock().unwrap(), channels, total),
+        };
+        channels[..=chosen].rotate_right(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    fn channel(name: &str, priority: u32, weight: u32) -> Channel {
+        serde_json::from_value(json!({
+            "name": name,
+            "url": format!("https://{}.example/v1/chat/completions", name),
+            "api_key": null,
+            "enabled": true,
+            "priority": priority,
+            "weight": weight,
+        }))
+        .unwrap()
+    }
+    
+    fn first(strategy: &dyn RoutingStrategy, channels: &[Channel], seed: Option<u64>) -> String {
+        let config = Config::default();
+        let latencies = HashMap::new();
+        let context = RoutingContext { config: &config, model: "m", latencies: &latencies, seed };
+        let mut order: Vec<&Channel> = channels.iter().collect();
+        strategy.order(&mut order, &context);
+        order[0].name.clone()
+    }
+    
+    #[test]
+    fn weighted_splits_first_choices_by_weight() {
+        let channels = [channel("cheap", 1, 4), channel("premium", 0, 1)];
+        let weighted = Weighted::default();
+        let firsts: Vec<String> = (0..5).map(|_| first(&weighted, &channels, None)).collect();
+        assert_eq!(firsts.iter().filter(|name| *name == "cheap").count(), 4);
+        assert_eq!(firsts.iter().filter(|name| *name == "premium").count(), 1);
+        // Smooth: the premium turn falls inside the cycle, not at either end
+        assert_ne!(firsts[0], "premium");
+        assert_ne!(firsts[4], "premium");
+    }
+    
+    #[test]
+    fn round_robin_rotates_in_priority_order() {
+        let channels = [channel("c", 2, 1), channel("a", 0, 1), channel("b", 1, 1)];
+        let round_robin = RoundRobin::default();
+        let firsts: Vec<String> = (0..4).map(|_| first(&round_robin, &channels, None)).collect();
+        assert_eq!(firsts, ["a", "b", "c", "a"]);
+    }
+    
+    #[test]
+    fn a_seed_repeats_the_choice() {
+        let channels: Vec<Channel> = (0..6).map(|index| channel(&format!("ch{}", index), index, 1 + index)).collect();
+        for kind in [RoutingStrategyKind::Random, RoutingStrategyKind::RoundRobin, RoutingStrategyKind::Weighted] {
+            let strategy = strategy(kind);
+            let mut choices = std::collections::HashSet::new();
+            for seed in 0..20 {
+                let choice = first(strategy.as_ref(), &channels, Some(seed));
+                assert!((0..5).all(|_| first(strategy.as_ref(), &channels, Some(seed)) == choice), "{:?} seed {}", kind, seed);
+                choices.insert(choice);
+            }
+            // Different seeds still spread the traffic
+            assert!(choices.len() > 1, "{:?}", kind);
+        }
+    }
+}
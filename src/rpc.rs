@@ -0,0 +1,239 @@
+use crate::client::{APIClient, RequestOptions};
+use crate::config::EndpointKind;
+use crate::error::{CCSwitchError, Result};
+use crate::events::RequestEvent;
+use crate::language::Language;
+use log::{debug, info};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{self, AsyncBufReadExt, BufReader};
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const APPLICATION_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct RequestParams {
+    prompt: String,
+    model: Option<String>,
+    max_tokens: Option<u32>,
+    temperature: Option<f32>,
+    #[serde(default)]
+    completion: bool,
+    channel: Option<String>,
+    system: Option<String>,
+    preset: Option<String>,
+    logprobs: Option<u8>,
+    language: Option<Language>,
+    #[serde(default)]
+    hedge: bool,
+    #[serde(default)]
+    tags: Vec<String>,
+    // Provider-specific fields such as vLLM's `top_k`
+    #[serde(default)]
+    params: serde_json::Map<String, Value>,
+    selection_seed: Option<u64>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+}
+
+impl From<RequestParams> for RequestOptions {
+    fn from(params: RequestParams) -> Self {
+        Self {
+            model: params.model,
+            max_tokens: params.max_tokens,
+            temperature: params.temperature,
+            stream: false,
+            interaction: if params.completion { EndpointKind::Completion } else { EndpointKind::Chat },
+            channel: params.channel,
+            system: params.system,
+            logprobs: params.logprobs,
+            events: None,
+            no_cache: false,
+            language: params.language,
+            hedge: params.hedge,
+            tags: params.tags,
+            user: None,
+            pipeline_stage: None,
+            params: params.params,
+            selection_seed: params.selection_seed,
+            labels: params.labels,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TestParams {
+    name: Option<String>,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl From<CCSwitchError> for RpcError {
+    fn from(error: CCSwitchError) -> Self {
+        Self {
+            code: APPLICATION_ERROR,
+            message: error.to_string(),
+            data: Some(json!({ "code": error.code() })),
+        }
+    }
+}
+
+fn invalid_params(error: serde_json::Error) -> RpcError {
+    RpcError {
+        code: INVALID_PARAMS,
+        message: format!("Invalid params: {}", error),
+        data: None,
+    }
+}
+
+// Line-delimited JSON-RPC 2.0 over stdin/stdout, one request per line, handled in order
+pub async fn serve(mut client: APIClient) -> Result<()> {
+    // Id of the `stream` call in progress; its events are forwarded as notifications
+    let streaming: Arc<Mutex<Option<Value>>> = Arc::new(Mutex::new(None));
+    
+    let sink_target = Arc::clone(&streaming);
+    client.set_event_sink(Box::new(move |event: &RequestEvent| {
+        if let Some(id) = sink_target.lock().unwrap().clone() {
+            write_message(&json!({
+                "jsonrpc": "2.0",
+                "method": "event",
+                "params": { "id": id, "event": event },
+            }));
+        }
+    }));
+    
+    info!("RPC mode ready, reading requests from stdin");
+    let mut lines = BufReader::new(io::stdin()).lines();
+    
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        debug!("RPC request: {}", line);
+        
+        let request: RpcRequest = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                write_response(Value::Null, Err(RpcError {
+                    code: PARSE_ERROR,
+                    message: format!("Parse error: {}", e),
+                    data: None,
+                }));
+                continue;
+            }
+        };
+        
+        let result = match request.method.as_str() {
+            "list_channels" => Ok(list_channels(&client)),
+            "test" => test(&client, request.params).await,
+            "request" => make_request(&client, request.params, false).await,
+            "stream" => {
+                *streaming.lock().unwrap() = Some(request.id.clone());
+                let result = make_request(&client, request.params, true).await;
+                *streaming.lock().unwrap() = None;
+                result
+            }
+            method => Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("Method not found: {}", method),
+                data: None,
+            }),
+        };
+        
+        write_response(request.id, result);
+    }
+    
+    Ok(())
+}
+
+fn list_channels(client: &APIClient) -> Value {
+    // Never hand API keys to the embedding process
+    let channels: Vec<Value> = client.get_channel_manager()
+        .list_channels()
+        .into_iter()
+        .map(|channel| json!({
+            "name": channel.name,
+            "url": channel.url,
+            // Kept for clients written before channels served several models
+            "model": channel.models.first(),
+            "models": channel.models,
+            "enabled": channel.enabled,
+            "priority": channel.priority,
+            "endpoint_kind": channel.endpoint_kind,
+        }))
+        .collect();
+    
+    json!(channels)
+}
+
+async fn test(client: &APIClient, params: Value) -> std::result::Result<Value, RpcError> {
+    let params: TestParams = if params.is_null() {
+        TestParams { name: None }
+    } else {
+        serde_json::from_value(params).map_err(invalid_params)?
+    };
+    let manager = client.get_channel_manager();
+    
+    let statuses = match params.name {
+        Some(name) => {
+            let channel = manager.config
+                .get_channel(&name)
+                .ok_or(CCSwitchError::ChannelNotFound(name))?;
+            vec![manager.test_channel(channel).await]
+        }
+        None => manager.test_all_channels(false).await,
+    };
+    
+    Ok(json!(statuses))
+}
+
+async fn make_request(client: &APIClient, params: Value, stream: bool) -> std::result::Result<Value, RpcError> {
+    let params: RequestParams = serde_json::from_value(params).map_err(invalid_params)?;
+    let prompt = params.prompt.clone();
+    
+    let preset = params.preset.clone();
+    
+    let mut options = RequestOptions::from(params);
+    options.stream = stream;
+    if let Some(name) = preset {
+        options = options.with_preset(client.get_channel_manager().config.get_preset(&name)?);
+    }
+    
+    let response = client.make_request(&prompt, options).await?;
+    Ok(json!(response))
+}
+
+fn write_response(id: Value, result: std::result::Result<Value, RpcError>) {
+    let message = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => {
+            let mut body = json!({ "code": error.code, "message": error.message });
+            if let Some(data) = error.data {
+                body["data"] = data;
+            }
+            json!({ "jsonrpc": "2.0", "id": id, "error": body })
+        }
+    };
+    write_message(&message);
+}
+
+fn write_message(message: &Value) {
+    println!("{}", message);
+}
@@ -0,0 +1,138 @@
+use crate::client::APIResponse;
+use crate::config::{now_secs, Config};
+use crate::error::{CCSwitchError, Result};
+use crate::report::RunReport;
+use log::warn;
+use serde_json::json;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use uuid::Uuid;
+
+const REPORT_FILE: &str = "report.json";
+
+pub fn runs_dir() -> Result<PathBuf> {
+    Ok(Config::data_dir()?.join("runs"))
+}
+
+// Writes one run's inputs, raw responses and report under `runs/<id>/`
+pub struct RunWriter {
+    pub id: String,
+    pub dir: PathBuf,
+    responses: File,
+}
+
+impl RunWriter {
+    pub fn create(prompts: &[&str]) -> Result<Self> {
+        // Seconds first so directory listings sort by start time
+        let id = format!("{}-{}", now_secs(), &Uuid::new_v4().simple().to_string()[..8]);
+        let dir = runs_dir()?.join(&id);
+        fs::create_dir_all(&dir)?;
+        
+        fs::write(dir.join("inputs.txt"), prompts.join("\n") + "\n")?;
+        let responses = File::create(dir.join("responses.jsonl"))?;
+        
+        Ok(Self { id, dir, responses })
+    }
+    
+    pub fn record(&mut self, index: usize, prompt: &str, result: &Result<APIResponse>) {
+        let line = match result {
+            Ok(response) => json!({ "index": index, "prompt": prompt, "response": response }),
+            Err(e) => json!({ "index": index, "prompt": prompt, "error": { "code": e.code(), "message": e.to_string() } }),
+        };
+        if let Err(e) = writeln!(self.responses, "{}", line) {
+            warn!("Failed to record response {} of run {}: {}", index, self.id, e);
+        }
+    }
+    
+    pub fn finish(&self, report: &RunReport) -> Result<()> {
+        fs::write(self.dir.join(REPORT_FILE), serde_json::to_string_pretty(report)?)?;
+        Ok(())
+    }
+}
+
+// A stored run; `report` is missing when the run was interrupted before it finished
+pub struct StoredRun {
+    pub id: String,
+    // Unix seconds of the last write to the run's files
+    pub modified: u64,
+    pub report: Option<RunReport>,
+}
+
+fn run_path(id: &str) -> Result<PathBuf> {
+    if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+        return Err(CCSwitchError::Config(format!("Invalid run ID '{}'", id)));
+    }
+    Ok(runs_dir()?.join(id))
+}
+
+fn modified_secs(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+    modified.duration_since(UNIX_EPOCH).ok().map(|elapsed| elapsed.as_secs())
+}
+
+// Every run directory, finished or not, oldest first
+pub fn list() -> Result<Vec<StoredRun>> {
+    let dir = runs_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut runs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let id = entry.file_name().to_string_lossy().to_string();
+        let path = entry.path();
+        // Appending responses doesn't touch the directory itself, so its files count too
+        let modified = fs::read_dir(&path)?
+            .filter_map(|file| file.ok())
+            .filter_map(|file| modified_secs(&file.path()))
+            .chain(modified_secs(&path))
+            .max()
+            .unwrap_or(0);
+        let report = if path.join(REPORT_FILE).exists() {
+            match load(&id) {
+                Ok(report) => Some(report),
+                Err(e) => {
+                    warn!("Unreadable report for run {}: {}", id, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        runs.push(StoredRun { id, modified, report });
+    }
+    runs.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(runs)
+}
+
+pub fn load(id: &str) -> Result<RunReport> {
+    let dir = run_path(id)?;
+    if !dir.exists() {
+        return Err(CCSwitchError::Config(format!("Run '{}' not found", id)));
+    }
+    let path = dir.join(REPORT_FILE);
+    if !path.exists() {
+        return Err(CCSwitchError::Config(format!("Run '{}' was interrupted before writing a report", id)));
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+// Deletes runs last written more than `older_than_days` ago, or all of them; returns how many were removed
+pub fn clean(older_than_days: Option<u64>) -> Result<usize> {
+    let cutoff = older_than_days.map(|days| now_secs().saturating_sub(days * 24 * 60 * 60));
+    let mut removed = 0;
+    
+    for run in list()? {
+        if cutoff.is_none_or(|cutoff| run.modified < cutoff) {
+            fs::remove_dir_all(run_path(&run.id)?)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
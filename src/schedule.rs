@@ -0,0 +1,277 @@
+use crate::client::{APIClient, Message, RequestOptions};
+use crate::config::{Channel, Schedule, ScheduleOutput};
+use crate::email;
+use crate::error::{CCSwitchError, Result};
+use crate::jobs::{self, Job, JobStatus};
+use chrono::Local;
+use futures::future;
+use log::{debug, info, warn};
+use serde_json::json;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Accepts the usual five cron fields as well as the `cron` crate's six/seven-field form
+fn parse(name: &str, expression: &str) -> Result<cron::Schedule> {
+    let fields: Vec<&str> = expression.split_whitespace().collect();
+    let normalized = match fields.as_slice() {
+        [minute, hour, day, month, weekday] => {
+            format!("0 {} {} {} {} {}", minute, hour, day, month, crate_weekdays(weekday))
+        }
+        _ => expression.to_string(),
+    };
+    cron::Schedule::from_str(&normalized)
+        .map_err(|e| CCSwitchError::Config(format!("Schedule '{}' has an invalid cron expression '{}': {}", name, expression, e)))
+}
+
+// Standard cron counts weekdays 0-7 from Sunday (both 0 and 7); the `cron` crate counts 1-7 from Sunday.
+// Numeric items are spelled out as a list in the crate's numbering; names and anything else pass through.
+fn crate_weekdays(field: &str) -> String {
+    field
+        .split(',')
+        .map(|item| match standard_days(item) {
+            Some(days) => days.iter().map(|day| (day + 1).to_string()).collect::<Vec<_>>().join(","),
+            None => item.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// The days (0 = Sunday) a numeric item like `5`, `1-5`, `*/2` or `1-7/2` stands for
+fn standard_days(item: &str) -> Option<Vec<u32>> {
+    let (range, step) = match item.split_once('/') {
+        Some((range, step)) => (range, step.parse::<u32>().ok().filter(|&step| step > 0)?),
+        None if item == "*" => return None,
+        None => (item, 1),
+    };
+    let (start, end) = match range {
+        "*" => (0, 6),
+        _ => match range.split_once('-') {
+            Some((start, end)) => (start.parse::<u32>().ok()?, end.parse::<u32>().ok()?),
+            None => {
+                let day = range.parse::<u32>().ok()?;
+                // `5/2` means from Friday on
+                (day, if step > 1 { 6 } else { day })
+            }
+        },
+    };
+    if start > end || end > 7 {
+        return None;
+    }
+    let mut days: Vec<u32> = (start..=end).step_by(step as usize).map(|day| day % 7).collect();
+    days.sort_unstable();
+    days.dedup();
+    Some(days)
+}
+
+// Starts one task per enabled schedule; bad expressions or presets fail before anything runs
+pub fn spawn_all(client: Arc<APIClient>) -> Result<()> {
+    let config = &client.get_channel_manager().config;
+    
+    let mut parsed = Vec::new();
+    for (name, schedule) in config.schedules.iter().filter(|(_, schedule)| schedule.enabled) {
+        let cron = parse(name, &schedule.cron)?;
+        if let Some(preset) = schedule.preset.as_ref().filter(|preset| !config.presets.contains_key(*preset)) {
+            return Err(CCSwitchError::Config(format!("Schedule '{}' uses unknown preset '{}'", name, preset)));
+        }
+        if matches!(schedule.output, ScheduleOutput::Email { .. }) && config.email.smtp_host.is_none() {
+            return Err(CCSwitchError::Config(format!("Schedule '{}' sends email but email.smtp_host is not set", name)));
+        }
+        parsed.push((name.clone(), schedule.clone(), cron));
+    }
+    
+    for (name, schedule, cron) in parsed {
+        info!("Scheduling '{}' ({})", name, schedule.cron);
+        let client = client.clone();
+        tokio::spawn(async move {
+            while let Some(next) = cron.upcoming(Local).next() {
+                tokio::time::sleep((next - Local::now()).to_std().unwrap_or_default()).await;
+                if let Err(e) = run(&client, &name, &schedule).await {
+                    warn!("Scheduled run of '{}' failed: {}", name, e);
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+// Pings every active channel that cold-starts once per `serve.keep_alive_seconds`, starting now
+pub fn spawn_keep_alive(client: Arc<APIClient>) {
+    let Some(seconds) = client.get_channel_manager().config.serve.keep_alive_seconds else {
+        return;
+    };
+    info!("Sending keep-alive probes every {}s", seconds);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(seconds.max(1)));
+        // A probe stuck on a loading model must not pile up more behind it
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let channels = client.get_channel_manager().list_channels()
+                .into_iter()
+                .filter(|channel| channel.is_active() && channel.cold_starts());
+            future::join_all(channels.map(|channel| keep_alive(&client, channel))).await;
+        }
+    });
+}
+
+// The smallest real request, since only generating loads a serverless or on-demand model
+async fn keep_alive(client: &APIClient, channel: &Channel) {
+    let model = match channel.named_models().next() {
+        Some(model) => model.to_string(),
+        None => match client.get_channel_manager().installed_models(channel).await.and_then(|models| models.into_iter().next()) {
+            Some(model) => model,
+            None => {
+                debug!("No model to keep warm on channel {}", channel.name);
+                return;
+            }
+        },
+    };
+    let options = RequestOptions {
+        model: Some(model),
+        channel: Some(channel.name.clone()),
+        max_tokens: Some(1),
+        temperature: None,
+        no_cache: true,
+        // Told apart from real traffic in `stats --by-label ccswitch`
+        labels: [("ccswitch".to_string(), "keep-alive".to_string())].into(),
+        ..RequestOptions::default()
+    };
+    match client.make_chat_request(&[Message::user("ping")], options).await {
+        Ok(_) => debug!("Keep-alive probe answered by channel {}", channel.name),
+        Err(e) => warn!("Keep-alive probe to channel {} failed: {}", channel.name, e),
+    }
+}
+
+// One run, recorded as a background job so it shows up in `ccswitch jobs`
+pub async fn run(client: &APIClient, name: &str, schedule: &Schedule) -> Result<Job> {
+    let options = RequestOptions {
+        model: schedule.model.clone(),
+        max_tokens: None,
+        temperature: None,
+        ..RequestOptions::default()
+    };
+    let options = match &schedule.preset {
+        Some(preset) => options.with_preset(client.get_channel_manager().config.get_preset(preset)?),
+        None => options,
+    };
+    
+    let mut job = Job::new(schedule.prompt.clone(), &options);
+    job.schedule = Some(name.to_string());
+    jobs::save(&job)?;
+    jobs::execute(client, &job.id).await?;
+    let job = jobs::load(&job.id)?;
+    
+    let error = match (&job.status, &job.response) {
+        (JobStatus::Succeeded, Some(response)) => deliver(client, name, &job, &response.content, &schedule.output).await
+            .err()
+            .map(|e| e.to_string()),
+        (JobStatus::Cancelled, _) => None,
+        _ => Some(job.error.clone().unwrap_or_else(|| job.status.as_str().to_string())),
+    };
+    
+    match error {
+        Some(error) => {
+            notify_failure(name, &job, &error, schedule.failure_webhook.as_deref()).await;
+            Err(CCSwitchError::Channel(error))
+        }
+        None => Ok(job),
+    }
+}
+
+async fn deliver(client: &APIClient, name: &str, job: &Job, content: &str, output: &ScheduleOutput) -> Result<()> {
+    match output {
+        ScheduleOutput::File { path } => {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "## {} {}\n\n{}\n", Local::now().format("%Y-%m-%d %H:%M:%S"), name, content)?;
+            Ok(())
+        }
+        ScheduleOutput::Webhook { url } => {
+            let response = job.response.as_ref();
+            reqwest::Client::new()
+                .post(url)
+                .timeout(Duration::from_secs(10))
+                .json(&json!({
+                    "event": "schedule_result",
+                    "schedule": name,
+                    "job": job.id,
+                    "content": content,
+                    "channel": response.map(|response| &response.channel_used),
+                    "model": response.map(|response| &response.model),
+                }))
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(CCSwitchError::Network)?;
+            Ok(())
+        }
+        ScheduleOutput::Email { to } => {
+            let settings = &client.get_channel_manager().config.email;
+            let to = if to.is_empty() { &settings.to } else { to };
+            email::send(settings, to, &format!("[ccswitch] {}", name), content).await
+        }
+    }
+}
+
+async fn notify_failure(name: &str, job: &Job, error: &str, webhook: Option<&str>) {
+    let Some(webhook) = webhook else {
+        return;
+    };
+    
+    let result = reqwest::Client::new()
+        .post(webhook)
+        .timeout(Duration::from_secs(10))
+        .json(&json!({ "event": "schedule_failed", "schedule": name, "job": job.id, "error": error }))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    
+    if let Err(e) = result {
+        warn!("Failed to send schedule failure notification: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, Utc, Weekday};
+    use std::collections::HashSet;
+    
+    fn weekdays(expression: &str) -> HashSet<Weekday> {
+        let schedule = parse("test", expression).unwrap();
+        schedule.upcoming(Utc).take(21).map(|time| time.weekday()).collect()
+    }
+    
+    #[test]
+    fn weekday_range_is_monday_to_friday() {
+        let expected = HashSet::from([Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri]);
+        assert_eq!(weekdays("0 7 * * 1-5"), expected);
+        assert_eq!(weekdays("0 7 * * MON-FRI"), expected);
+    }
+    
+    #[test]
+    fn zero_and_seven_are_sunday() {
+        assert_eq!(weekdays("0 7 * * 0"), HashSet::from([Weekday::Sun]));
+        assert_eq!(weekdays("0 7 * * 7"), HashSet::from([Weekday::Sun]));
+    }
+    
+    #[test]
+    fn lists_ranges_and_steps_are_remapped() {
+        assert_eq!(weekdays("0 7 * * 1,3,5"), HashSet::from([Weekday::Mon, Weekday::Wed, Weekday::Fri]));
+        assert_eq!(weekdays("0 7 * * 5-7"), HashSet::from([Weekday::Fri, Weekday::Sat, Weekday::Sun]));
+        assert_eq!(weekdays("0 7 * * */2"), HashSet::from([Weekday::Sun, Weekday::Tue, Weekday::Thu, Weekday::Sat]));
+        assert_eq!(weekdays("0 7 * * *").len(), 7);
+    }
+    
+    #[test]
+    fn six_field_expressions_are_left_alone() {
+        assert_eq!(weekdays("0 0 7 * * 2"), HashSet::from([Weekday::Mon]));
+    }
+    
+    #[test]
+    fn invalid_weekdays_are_rejected() {
+        assert!(parse("test", "0 7 * * 8").is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use crate::client::{APIResponse, Message};
+use crate::config::{now_secs, Config};
+use crate::context;
+use crate::error::{CCSwitchError, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+// Plain `request` calls keep their exchange here so `request --continue` can pick it up
+pub const LAST_SESSION: &str = "last";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Turn {
+    pub prompt: String,
+    pub reply: String,
+    pub channel: String,
+    pub model: String,
+    pub usage: Option<Value>,
+    pub at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection_seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    // Ctrl-C stopped the reply; `reply` holds what had streamed by then
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub name: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub system: Option<String>,
+    // Stands in for the turns `sessions summarize --replace` dropped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub turns: Vec<Turn>,
+}
+
+impl Session {
+    pub fn new(name: &str, system: Option<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            created_at: now_secs(),
+            updated_at: now_secs(),
+            system,
+            summary: None,
+            turns: Vec::new(),
+        }
+    }
+    
+    // The transcript as chat messages, ready for the next user turn
+    pub fn messages(&self) -> Vec<Message> {
+        let mut messages: Vec<Message> = self.system.iter().map(|system| Message::system(system)).collect();
+        messages.extend(self.history(0));
+        messages
+    }
+    
+    // The summary and the turns, leaving out the last `skip` turns
+    pub fn history(&self, skip: usize) -> Vec<Message> {
+        let mut messages: Vec<Message> = self.summary.iter().map(|summary| context::summary_message(summary)).collect();
+        // A cancelled question was never answered, so it is not sent as part of the conversation
+        for turn in self.turns[..self.turns.len().saturating_sub(skip)].iter().filter(|turn| !turn.cancelled) {
+            messages.push(Message::user(&turn.prompt));
+            messages.push(Message::assistant(&turn.reply));
+        }
+        messages
+    }
+    
+    pub fn record(&mut self, prompt: &str, response: &APIResponse) {
+        self.updated_at = now_secs();
+        self.turns.push(Turn {
+            prompt: prompt.to_string(),
+            reply: response.content.clone(),
+            channel: response.channel_used.clone(),
+            model: response.model.clone(),
+            usage: response.usage.clone(),
+            at: self.updated_at,
+            selection_seed: response.selection_seed,
+            labels: response.labels.clone(),
+            cancelled: false,
+        });
+    }
+    
+    pub fn record_cancelled(&mut self, prompt: &str, partial: &str, channel: &str, model: &str, labels: BTreeMap<String, String>) {
+        self.updated_at = now_secs();
+        self.turns.push(Turn {
+            prompt: prompt.to_string(),
+            reply: partial.to_string(),
+            channel: channel.to_string(),
+            model: model.to_string(),
+            usage: None,
+            at: self.updated_at,
+            selection_seed: None,
+            labels,
+            cancelled: true,
+        });
+    }
+    
+    // A `--system` given when resuming replaces the stored prompt from then on
+    pub fn resume_with(mut self, system: Option<String>) -> Self {
+        if system.is_some() {
+            self.system = system;
+        }
+        self
+    }
+    
+    // Keeps the last `keep` turns, with `summary` standing in for the rest
+    pub fn compact(&mut self, summary: String, keep: usize) {
+        self.updated_at = now_secs();
+        self.summary = Some(summary);
+        self.turns.drain(..self.turns.len().saturating_sub(keep));
+    }
+    
+    pub fn clear(&mut self) {
+        self.updated_at = now_secs();
+        self.summary = None;
+        self.turns.clear();
+    }
+}
+
+pub fn sessions_dir() -> Result<PathBuf> {
+    Ok(Config::config_dir()?.join("sessions"))
+}
+
+fn session_path(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(CCSwitchError::Config(format!("Invalid session name '{}'", name)));
+    }
+    Ok(sessions_dir()?.join(format!("{}.json", name)))
+}
+
+pub fn load(name: &str) -> Result<Session> {
+    let path = session_path(name)?;
+    if !path.exists() {
+        return Err(CCSwitchError::Config(format!("Session '{}' not found", name)));
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+pub fn load_or_create(name: &str, system: Option<String>) -> Result<Session> {
+    if session_path(name)?.exists() {
+        Ok(load(name)?.resume_with(system))
+    } else {
+        Ok(Session::new(name, system))
+    }
+}
+
+pub fn save(session: &Session) -> Result<()> {
+    let path = session_path(&session.name)?;
+    let dir = sessions_dir()?;
+    fs::create_dir_all(&dir)?;
+    
+    // Write then rename so an interrupted save never truncates the transcript
+    let temp_path = dir.join(format!(".{}.json.tmp", session.name));
+    fs::write(&temp_path, serde_json::to_string_pretty(session)?)?;
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+pub fn delete(name: &str) -> Result<()> {
+    let path = session_path(name)?;
+    if !path.exists() {
+        return Err(CCSwitchError::Config(format!("Session '{}' not found", name)));
+    }
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+// Most recently updated first
+pub fn list() -> Result<Vec<Session>> {
+    let dir = sessions_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut sessions = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        let Some(name) = name.strip_suffix(".json").filter(|name| !name.starts_with('.')) else {
+            continue;
+        };
+        match load(name) {
+            Ok(session) => sessions.push(session),
+            Err(e) => warn!("Skipping session {}: {}", name, e),
+        }
+    }
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.updated_at));
+    Ok(sessions)
+}
+
+pub fn latest() -> Result<Session> {
+    list()?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CCSwitchError::Config("No session to continue".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn session(turns: usize) -> Session {
+        let mut session = Session::new("test", Some("Be brief.".to_string()));
+        for turn in 0..turns {
+            session.turns.push(Turn {
+                prompt: format!("question {}", turn),
+                reply: format!("answer {}", turn),
+                channel: "alpha".to_string(),
+                model: "m".to_string(),
+                usage: None,
+                at: 0,
+                selection_seed: None,
+                labels: BTreeMap::new(),
+                cancelled: false,
+            });
+        }
+        session
+    }
+    
+    #[test]
+    fn a_compacted_session_sends_its_summary_first() {
+        let mut session = session(5);
+        assert_eq!(session.history(2).len(), 6);
+        
+        session.compact("they asked five questions".to_string(), 2);
+        assert_eq!(session.turns.len(), 2);
+        let messages = session.messages();
+        assert_eq!(messages.len(), 6);
+        assert_eq!(messages[0].content, "Be brief.");
+        assert!(messages[1].role == "system" && messages[1].content.ends_with("they asked five questions"));
+        assert_eq!(messages[2].content, "question 3");
+    }
+    
+    #[test]
+    fn a_cancelled_turn_is_kept_but_not_sent() {
+        let mut session = session(1);
+        session.record_cancelled("question 1", "half an ans", "alpha", "m", BTreeMap::new());
+        assert_eq!(session.turns.len(), 2);
+        let messages = session.messages();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[2].content, "answer 0");
+    }
+}
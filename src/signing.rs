@@ -0,0 +1,52 @@
+use crate::config::{now_secs, Channel, SigningAlgorithm, SigningConfig};
+use hmac::{Hmac, Mac};
+use reqwest::RequestBuilder;
+use serde_json::Value;
+use sha2::{Sha256, Sha512};
+
+// Serialize the payload ourselves so the signature covers exactly the bytes that are sent
+pub fn json_body(request: RequestBuilder, channel: &Channel, payload: &Value) -> RequestBuilder {
+    let body = serde_json::to_vec(payload).unwrap_or_default();
+    
+    sign(request, channel, &body)
+        .header("Content-Type", "application/json")
+        .body(body)
+}
+
+pub fn sign(request: RequestBuilder, channel: &Channel, body: &[u8]) -> RequestBuilder {
+    let Some(signing) = &channel.signing else {
+        return request;
+    };
+    
+    match &signing.timestamp_header {
+        // Binding the timestamp into the MAC stops captured requests from being replayed later
+        Some(timestamp_header) => {
+            let timestamp = now_secs().to_string();
+            let mut message = format!("{}.", timestamp).into_bytes();
+            message.extend_from_slice(body);
+            request
+                .header(timestamp_header.as_str(), timestamp)
+                .header(signing.header.as_str(), signature(signing, &message))
+        }
+        None => request.header(signing.header.as_str(), signature(signing, body)),
+    }
+}
+
+fn signature(signing: &SigningConfig, message: &[u8]) -> String {
+    let digest = match signing.algorithm {
+        SigningAlgorithm::HmacSha256 => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(signing.secret.as_bytes())
+                .expect("HMAC accepts keys of any length");
+            mac.update(message);
+            mac.finalize().into_bytes().to_vec()
+        }
+        SigningAlgorithm::HmacSha512 => {
+            let mut mac = Hmac::<Sha512>::new_from_slice(signing.secret.as_bytes())
+                .expect("HMAC accepts keys of any length");
+            mac.update(message);
+            mac.finalize().into_bytes().to_vec()
+        }
+    };
+    
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
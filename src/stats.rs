@@ -0,0 +1,296 @@
+use crate::config::{now_secs, Config};
+use crate::error::Result;
+use clap::ValueEnum;
+use log::warn;
+use rusqlite::{params, Connection, Row};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+impl Period {
+    pub fn seconds(self) -> u64 {
+        const DAY: u64 = 24 * 60 * 60;
+        match self {
+            Period::Day => DAY,
+            Period::Week => 7 * DAY,
+            Period::Month => 30 * DAY,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UsageRecord {
+    pub channel: Option<String>,
+    pub model: String,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    pub latency_ms: u64,
+    pub success: bool,
+    pub error_code: Option<String>,
+    // Estimated from `pricing`; None when the model has no price
+    pub cost: Option<f64>,
+    // Streamed replies only
+    pub tokens_per_second: Option<f64>,
+    // Proxy user the request was made for
+    pub user: Option<String>,
+    // `--label` pairs the spend is attributed to
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageRow {
+    // Channel or model name, depending on the grouping
+    pub name: String,
+    pub requests: u64,
+    pub failures: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub avg_latency_ms: u64,
+    // Estimated USD, from requests whose model has a price
+    pub cost: f64,
+    // Average over streamed replies, None if there were none
+    pub avg_tokens_per_second: Option<f64>,
+}
+
+// One time slice of `series`, starting at `at`
+#[derive(Debug, Serialize)]
+pub struct UsageBucket {
+    pub at: u64,
+    pub requests: u64,
+    pub failures: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageSummary {
+    pub since: u64,
+    pub channels: Vec<UsageRow>,
+    pub models: Vec<UsageRow>,
+    // Grouped by the value of this label, when asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<UsageRow>,
+}
+
+fn open() -> Result<Connection> {
+    let dir = Config::data_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    
+    let connection = Connection::open(dir.join("usage.db")).map_err(io::Error::other)?;
+    // `submit` workers and `serve` may write at the same time
+    connection.busy_timeout(Duration::from_secs(5)).map_err(io::Error::other)?;
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS requests (
+            at INTEGER NOT NULL,
+            channel TEXT,
+            model TEXT NOT NULL,
+            prompt_tokens INTEGER,
+            completion_tokens INTEGER,
+            latency_ms INTEGER NOT NULL,
+            success INTEGER NOT NULL,
+            error_code TEXT,
+            cost REAL,
+            tokens_per_second REAL,
+            user TEXT,
+            labels TEXT
+        );
+        CREATE INDEX IF NOT EXISTS requests_at ON requests (at);
+        CREATE TABLE IF NOT EXISTS health_checks (
+            at INTEGER NOT NULL,
+            channel TEXT NOT NULL,
+            success INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS health_checks_channel ON health_checks (channel, at);",
+    ).map_err(io::Error::other)?;
+    
+    // Columns added after the table was first created
+    for (column, kind) in [("cost", "REAL"), ("tokens_per_second", "REAL"), ("user", "TEXT"), ("labels", "TEXT")] {
+        if connection.prepare(&format!("SELECT {column} FROM requests LIMIT 0")).is_err() {
+            connection.execute_batch(&format!("ALTER TABLE requests ADD COLUMN {column} {kind}")).map_err(io::Error::other)?;
+        }
+    }
+    Ok(connection)
+}
+
+// Opened on first use and shared by every request of the process
+static CONNECTION: Mutex<Option<Connection>> = Mutex::new(None);
+
+// Runs a query on a blocking thread, since SQLite may wait up to the busy timeout for other writers
+async fn query<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let mut shared = CONNECTION.lock().unwrap();
+        let connection = match &mut *shared {
+            Some(connection) => connection,
+            empty => empty.insert(open()?),
+        };
+        Ok(f(connection).map_err(io::Error::other)?)
+    }).await.map_err(io::Error::other)?
+}
+
+// Failures are logged and swallowed; bookkeeping must never break a request
+pub async fn record(record: UsageRecord) {
+    // A JSON object, so `stats --by-label` can pick one key out with json_extract
+    let labels = (!record.labels.is_empty()).then(|| serde_json::to_string(&record.labels).unwrap_or_default());
+    let result = query(move |connection| {
+        connection.execute(
+            "INSERT INTO requests (at, channel, model, prompt_tokens, completion_tokens, latency_ms, success, error_code, cost, tokens_per_second, user, labels)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                now_secs(),
+                record.channel,
+                record.model,
+                record.prompt_tokens,
+                record.completion_tokens,
+                record.latency_ms,
+                record.success,
+                record.error_code,
+                record.cost,
+                record.tokens_per_second,
+                record.user,
+                labels,
+            ],
+        )
+    }).await;
+    
+    if let Err(e) = result {
+        warn!("Failed to record usage: {}", e);
+    }
+}
+
+// Kept for auto-disable, which has to see failures across separate `ccswitch` runs
+pub async fn record_health(channel: &str, success: bool) {
+    let channel = channel.to_string();
+    let result = query(move |connection| {
+        connection.execute(
+            "INSERT INTO health_checks (at, channel, success) VALUES (?1, ?2, ?3)",
+            params![now_secs(), channel, success],
+        )
+    }).await;
+    
+    if let Err(e) = result {
+        warn!("Failed to record health check: {}", e);
+    }
+}
+
+// Failed health checks since the given time that no passing check has followed
+pub async fn health_failures(channel: &str, since: u64) -> Result<u64> {
+    let channel = channel.to_string();
+    query(move |connection| {
+        connection.query_row(
+            "SELECT COUNT(*) FROM health_checks
+             WHERE channel = ?1 AND success = 0 AND at >= ?2
+               AND at >= (SELECT COALESCE(MAX(at), 0) FROM health_checks WHERE channel = ?1 AND success = 1)",
+            params![channel, since],
+            |row| row.get(0),
+        )
+    }).await
+}
+
+// Estimated USD spent since the given time, optionally only on one channel or for one proxy user
+pub async fn spend(since: u64, channel: Option<&str>, user: Option<&str>) -> Result<f64> {
+    let channel = channel.map(str::to_string);
+    let user = user.map(str::to_string);
+    query(move |connection| {
+        connection.query_row(
+            "SELECT COALESCE(SUM(cost), 0) FROM requests WHERE at >= ?1 AND (?2 IS NULL OR channel = ?2) AND (?3 IS NULL OR user = ?3)",
+            params![since, channel, user],
+            |row| row.get(0),
+        )
+    }).await
+}
+
+// With `label`, requests are also grouped by that label's value; ones without it count as "-".
+// With `user`, only that proxy user's requests count.
+pub async fn summarize(since: u64, label: Option<&str>, user: Option<&str>) -> Result<UsageSummary> {
+    let label = label.map(str::to_string);
+    let user = user.map(str::to_string);
+    query(move |connection| {
+        let labels = match &label {
+            // Quoted so keys like `team.name` are one key rather than a path
+            Some(key) => group(connection, "COALESCE(json_extract(labels, ?3), '-')", since, &user, Some(format!("$.\"{}\"", key)))?,
+            None => Vec::new(),
+        };
+        Ok(UsageSummary {
+            since,
+            channels: group(connection, "COALESCE(channel, '-')", since, &user, None)?,
+            models: group(connection, "model", since, &user, None)?,
+            label,
+            labels,
+        })
+    }).await
+}
+
+fn group(connection: &Connection, column: &str, since: u64, user: &Option<String>, path: Option<String>) -> rusqlite::Result<Vec<UsageRow>> {
+    let sql = format!(
+        "SELECT {column}, COUNT(*), SUM(1 - success), COALESCE(SUM(prompt_tokens), 0), COALESCE(SUM(completion_tokens), 0), AVG(latency_ms), COALESCE(SUM(cost), 0), AVG(tokens_per_second)
+         FROM requests WHERE at >= ?1 AND (?2 IS NULL OR user = ?2) GROUP BY 1 ORDER BY 2 DESC"
+    );
+    let mut statement = connection.prepare(&sql)?;
+    let rows = match path {
+        Some(path) => statement.query_map(params![since, user, path], usage_row)?.collect(),
+        None => statement.query_map(params![since, user], usage_row)?.collect(),
+    };
+    rows
+}
+
+// Requests since the given time in slices of `bucket` seconds, oldest first; empty slices are left out
+pub async fn series(since: u64, bucket: u64, user: Option<&str>) -> Result<Vec<UsageBucket>> {
+    let user = user.map(str::to_string);
+    query(move |connection| {
+        let mut statement = connection.prepare(
+            "SELECT at / ?2 * ?2, COUNT(*), SUM(1 - success), COALESCE(SUM(prompt_tokens), 0), COALESCE(SUM(completion_tokens), 0), COALESCE(SUM(cost), 0)
+             FROM requests WHERE at >= ?1 AND (?3 IS NULL OR user = ?3) GROUP BY 1 ORDER BY 1",
+        )?;
+        let rows = statement.query_map(params![since, bucket, user], |row| {
+            Ok(UsageBucket {
+                at: row.get(0)?,
+                requests: row.get(1)?,
+                failures: row.get(2)?,
+                prompt_tokens: row.get(3)?,
+                completion_tokens: row.get(4)?,
+                cost: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }).await
+}
+
+// Health checks since the given time per channel, as (checks, failed)
+pub async fn health_checks(since: u64) -> Result<HashMap<String, (u64, u64)>> {
+    query(move |connection| {
+        let mut statement = connection.prepare(
+            "SELECT channel, COUNT(*), SUM(1 - success) FROM health_checks WHERE at >= ?1 GROUP BY channel",
+        )?;
+        let rows = statement.query_map(params![since], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?;
+        rows.collect()
+    }).await
+}
+
+fn usage_row(row: &Row) -> rusqlite::Result<UsageRow> {
+    Ok(UsageRow {
+        name: row.get(0)?,
+        requests: row.get(1)?,
+        failures: row.get(2)?,
+        prompt_tokens: row.get(3)?,
+        completion_tokens: row.get(4)?,
+        avg_latency_ms: row.get::<_, f64>(5)?.round() as u64,
+        cost: row.get(6)?,
+        avg_tokens_per_second: row.get(7)?,
+    })
+}
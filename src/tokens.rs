@@ -0,0 +1,27 @@
+// Rough token counts for providers that omit `usage`. BPE tokenizers average about
+// four characters per token for English text, while CJK text is close to one token
+// per character, so the two are counted separately.
+pub fn estimate_tokens(text: &str) -> u64 {
+    let mut cjk_chars = 0u64;
+    let mut other_chars = 0u64;
+    
+    for c in text.chars() {
+        if is_cjk(c) {
+            cjk_chars += 1;
+        } else {
+            other_chars += 1;
+        }
+    }
+    
+    cjk_chars + other_chars.div_ceil(4)
+}
+
+pub fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF      // Hiragana, Katakana
+        | 0x3400..=0x4DBF    // CJK Extension A
+        | 0x4E00..=0x9FFF    // CJK Unified Ideographs
+        | 0xAC00..=0xD7AF    // Hangul syllables
+        | 0xF900..=0xFAFF    // CJK Compatibility Ideographs
+        | 0xFF00..=0xFFEF)   // Full-width forms
+}
@@ -0,0 +1,30 @@
+use crate::client::Message;
+
+// Stop sequence that keeps a completion model from writing the next user turn itself
+pub const COMPLETION_STOP: &str = "\nUser:";
+
+pub fn messages_to_prompt(messages: &[Message]) -> String {
+    let mut prompt = String::new();
+
+    for message in messages {
+        let speaker = match message.role.as_str() {
+            "system" => "System",
+            "assistant" => "Assistant",
+            _ => "User",
+        };
+        prompt.push_str(&format!("{}: {}\n\n", speaker, message.content.trim()));
+    }
+
+    prompt.push_str("Assistant:");
+    prompt
+}
+
+pub fn completion_to_reply(text: &str) -> String {
+    // Servers that ignore `stop` keep going and invent further turns
+    let reply = match text.find(COMPLETION_STOP) {
+        Some(end) => &text[..end],
+        None => text,
+    };
+
+    reply.trim().to_string()
+}
@@ -0,0 +1,239 @@
+use crate::error::{CCSwitchError, Result};
+use clap::ValueEnum;
+use log::{debug, info};
+use minisign_verify::{PublicKey, Signature};
+use reqwest::Client;
+use semver::Version;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::time::Duration;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/hanlife02/ccswitch/releases";
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+const SIGNATURE_ASSET: &str = "SHA256SUMS.minisig";
+// Minisign public key that signs each release's SHA256SUMS, baked in when release binaries are built
+const UPDATE_PUBLIC_KEY: Option<&str> = option_env!("CCSWITCH_UPDATE_PUBLIC_KEY");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReleaseChannel {
+    Stable,
+    Prerelease,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    prerelease: bool,
+    draft: bool,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+pub enum UpdateOutcome {
+    UpToDate(Version),
+    Updated { from: Version, to: Version },
+}
+
+pub struct Updater {
+    client: Client,
+    channel: ReleaseChannel,
+}
+
+impl Updater {
+    pub fn new(channel: ReleaseChannel) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(120))
+            .user_agent(concat!("ccswitch/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(CCSwitchError::Network)?;
+
+        Ok(Self { client, channel })
+    }
+
+    pub async fn run(&self) -> Result<UpdateOutcome> {
+        let current = Version::parse(env!("CARGO_PKG_VERSION"))
+            .map_err(|e| CCSwitchError::Update(format!("Invalid current version: {}", e)))?;
+
+        let (release, latest) = self.latest_release().await?;
+
+        if latest <= current {
+            info!("Already running the latest {:?} release ({})", self.channel, current);
+            return Ok(UpdateOutcome::UpToDate(current));
+        }
+
+        // The checksums come from the same release as the binary, so only a signature makes them trustworthy
+        let public_key = UPDATE_PUBLIC_KEY.ok_or_else(|| CCSwitchError::Update(
+            "This build has no update signing key; download the release manually".to_string()
+        ))?;
+
+        let asset_name = platform_asset_name();
+        let asset = find_asset(&release, &asset_name)?;
+        let checksums = find_asset(&release, CHECKSUMS_ASSET)?;
+        let signature = find_asset(&release, SIGNATURE_ASSET)?;
+
+        info!("Downloading {} from release {}", asset_name, release.tag_name);
+        let binary = self.download(&asset.browser_download_url).await?;
+        let checksum_file = self.download(&checksums.browser_download_url).await?;
+        let signature_file = self.download(&signature.browser_download_url).await?;
+
+        verify_signature(public_key, &checksum_file, &String::from_utf8_lossy(&signature_file))?;
+        verify_checksum(&binary, &String::from_utf8_lossy(&checksum_file), &asset_name)?;
+        replace_current_exe(&binary)?;
+
+        Ok(UpdateOutcome::Updated { from: current, to: latest })
+    }
+
+    async fn latest_release(&self) -> Result<(Release, Version)> {
+        let releases: Vec<Release> = self.client
+            .get(RELEASES_URL)
+            .header("Accept", "application/vnd.github+json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        releases
+            .into_iter()
+            .filter(|release| !release.draft)
+            .filter(|release| self.channel == ReleaseChannel::Prerelease || !release.prerelease)
+            .filter_map(|release| {
+                let version = Version::parse(release.tag_name.trim_start_matches('v')).ok()?;
+                Some((release, version))
+            })
+            .max_by(|(_, a), (_, b)| a.cmp(b))
+            .ok_or_else(|| CCSwitchError::Update(format!("No {:?} releases found", self.channel)))
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        debug!("Downloading {}", url);
+
+        let bytes = self.client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        Ok(bytes.to_vec())
+    }
+}
+
+fn platform_asset_name() -> String {
+    format!("ccswitch-{}-{}{}", env::consts::ARCH, env::consts::OS, env::consts::EXE_SUFFIX)
+}
+
+fn find_asset<'a>(release: &'a Release, name: &str) -> Result<&'a Asset> {
+    release.assets
+        .iter()
+        .find(|asset| asset.name == name)
+        .ok_or_else(|| CCSwitchError::Update(format!("Release {} has no '{}' asset", release.tag_name, name)))
+}
+
+fn verify_signature(public_key: &str, checksum_file: &[u8], signature: &str) -> Result<()> {
+    let public_key = PublicKey::from_base64(public_key)
+        .map_err(|e| CCSwitchError::Update(format!("Invalid update signing key: {}", e)))?;
+    let signature = Signature::decode(signature)
+        .map_err(|e| CCSwitchError::Update(format!("Invalid {}: {}", SIGNATURE_ASSET, e)))?;
+
+    public_key
+        .verify(checksum_file, &signature, false)
+        .map_err(|e| CCSwitchError::Update(format!("{} is not signed by the release key: {}", CHECKSUMS_ASSET, e)))
+}
+
+fn verify_checksum(binary: &[u8], checksum_file: &str, asset_name: &str) -> Result<()> {
+    // SHA256SUMS uses the `sha256sum` format: "<hex digest>  <file name>"
+    let expected = checksum_file
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, name)| name.trim().trim_start_matches('*') == asset_name)
+        .map(|(digest, _)| digest.to_lowercase())
+        .ok_or_else(|| CCSwitchError::Update(format!("No checksum listed for '{}'", asset_name)))?;
+
+    let actual: String = Sha256::digest(binary)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    if actual != expected {
+        return Err(CCSwitchError::Update(format!(
+            "Checksum mismatch for '{}': expected {}, got {}", asset_name, expected, actual
+        )));
+    }
+
+    Ok(())
+}
+
+fn replace_current_exe(binary: &[u8]) -> Result<()> {
+    let exe = env::current_exe()?;
+    let dir = exe.parent()
+        .ok_or_else(|| CCSwitchError::Update("Could not determine executable directory".to_string()))?;
+
+    // Write next to the current binary so the final rename stays on one filesystem
+    let staged = dir.join(format!(".ccswitch-update-{}", uuid::Uuid::new_v4()));
+    fs::write(&staged, binary)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+
+    // Windows refuses to overwrite a running executable, but allows renaming it
+    #[cfg(windows)]
+    {
+        let previous = exe.with_extension("old.exe");
+        let _ = fs::remove_file(&previous);
+        fs::rename(&exe, &previous)?;
+    }
+
+    if let Err(e) = fs::rename(&staged, &exe) {
+        let _ = fs::remove_file(&staged);
+        // Put the running binary back so the install is left as it was
+        #[cfg(windows)]
+        if let Err(restore) = fs::rename(exe.with_extension("old.exe"), &exe) {
+            return Err(CCSwitchError::Update(format!(
+                "Failed to replace {}: {}; restoring the previous version also failed: {}", exe.display(), e, restore
+            )));
+        }
+        return Err(CCSwitchError::Update(format!("Failed to replace {}: {}", exe.display(), e)));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // From the minisign-verify documentation: a prehashed signature of "test"
+    const PUBLIC_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1633700835\tfile:test\tprehashed
+wLMDjy9FLAuxZ3q4NlEvkgtyhrr0gtTu6KC4KBJdITbbOeAi1zBIYo0v4iTgt8jJpIidRJnp94ABQkJAgAooBQ==";
+
+    #[test]
+    fn checksums_must_carry_the_release_signature() {
+        assert!(verify_signature(PUBLIC_KEY, b"test", SIGNATURE).is_ok());
+        assert!(verify_signature(PUBLIC_KEY, b"tampered", SIGNATURE).is_err());
+        assert!(verify_signature(PUBLIC_KEY, b"test", "not a signature").is_err());
+    }
+
+    #[test]
+    fn checksum_is_matched_by_file_name() {
+        let binary = b"test";
+        let sums = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08  ccswitch-x86_64-linux\n";
+        assert!(verify_checksum(binary, sums, "ccswitch-x86_64-linux").is_ok());
+        assert!(verify_checksum(b"other", sums, "ccswitch-x86_64-linux").is_err());
+        assert!(verify_checksum(binary, sums, "ccswitch-aarch64-linux").is_err());
+    }
+}
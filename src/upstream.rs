@@ -0,0 +1,240 @@
+use crate::config::ApiFormat;
+use crate::error::ProviderErrorKind;
+use crate::openrouter;
+use reqwest::StatusCode;
+use serde_json::Value;
+
+// What an error payload says went wrong, once provider and relay dialects are set aside
+pub enum Diagnosis {
+    Auth,
+    Provider(ProviderErrorKind),
+}
+
+// Matched against the error's code, type and message together, lowercased; earlier groups win,
+// so a rate limit that mentions quota is still a rate limit
+const AUTH_SIGNALS: &[&str] = &[
+    "invalid_api_key", "invalid api key", "incorrect api key", "invalid x-api-key", "api key not valid",
+    "authentication", "unauthorized", "permission_error", "unauthenticated", "permission_denied", "invalid_token",
+    "无效的令牌", "令牌已过期", "令牌无效",
+];
+
+const RATE_LIMIT_SIGNALS: &[&str] = &[
+    "rate_limit", "rate limit", "resource_exhausted", "too many requests", "请求过于频繁", "请求频率",
+];
+
+const QUOTA_SIGNALS: &[&str] = &[
+    "insufficient_quota", "insufficient_user_quota", "pre_consume_token_quota_failed", "billing_hard_limit",
+    "quota", "billing", "credit balance", "balance is too low", "余额不足", "额度不足", "额度已用尽",
+];
+
+const OVERLOAD_SIGNALS: &[&str] = &[
+    "overloaded", "server is busy", "capacity", "负载已饱和", "服务繁忙",
+];
+
+const CONTEXT_SIGNALS: &[&str] = &[
+    "context_length_exceeded", "maximum context length", "context window", "prompt is too long",
+    "request_too_large", "too many tokens", "上下文长度",
+];
+
+const MODEL_SIGNALS: &[&str] = &[
+    "model_not_found", "model not found", "no such model", "unknown model", "is not found for api version", "无可用渠道",
+];
+
+const FILTER_SIGNALS: &[&str] = &[
+    "content_filter", "content_policy", "content management policy", "safety", "敏感词",
+];
+
+const INVALID_REQUEST_SIGNALS: &[&str] = &[
+    "invalid_request_error", "invalid_argument", "invalid_request", "bad_request",
+];
+
+// The provider's own explanation: OpenAI, Anthropic and Gemini nest it under `error`,
+// relays put it at the top level under various names
+pub fn message(body: &str) -> Option<String> {
+    let body: Value = serde_json::from_str(body).ok()?;
+    ["/error/message", "/message", "/msg", "/error_msg", "/detail", "/error"]
+        .iter()
+        .find_map(|pointer| body.pointer(pointer)?.as_str())
+        .map(|message| message.trim().to_string())
+        .filter(|message| !message.is_empty())
+}
+
+// "quota exhausted: <provider message>", for health reports
+pub fn summary(diagnosis: &Diagnosis, body: &str) -> String {
+    let kind = match diagnosis {
+        Diagnosis::Auth => "credentials rejected".to_string(),
+        Diagnosis::Provider(kind) => kind.to_string(),
+    };
+    let detail = message(body).unwrap_or_else(|| body.trim().to_string());
+    match detail.chars().take(200).collect::<String>() {
+        detail if detail.is_empty() => kind,
+        detail => format!("{}: {}", kind, detail),
+    }
+}
+
+pub fn message_for(format: ApiFormat, body: &str) -> Option<String> {
+    match format {
+        ApiFormat::Openrouter => openrouter::message(body),
+        _ => message(body),
+    }
+}
+
+// Providers whose statuses carry their own meaning are read by their own rules first
+pub fn diagnose_for(format: ApiFormat, status: StatusCode, body: &str) -> Option<Diagnosis> {
+    match format {
+        ApiFormat::Openrouter => openrouter::diagnose(status, body),
+        _ => diagnose(status, body),
+    }
+}
+
+pub fn diagnose(status: StatusCode, body: &str) -> Option<Diagnosis> {
+    let parsed: Option<Value> = serde_json::from_str(body).ok();
+    let text = match &parsed {
+        Some(parsed) => {
+            // OpenAI `error.code`/`error.type`, Anthropic `error.type`, Gemini `error.status`, relay top-level `code`
+            let fields: Vec<String> = ["/error/code", "/error/type", "/error/status", "/code", "/type"]
+                .iter()
+                .filter_map(|pointer| parsed.pointer(pointer))
+                .filter_map(|value| match value {
+                    Value::String(value) => Some(value.clone()),
+                    Value::Number(value) => Some(value.to_string()),
+                    _ => None,
+                })
+                .chain(message(body))
+                .collect();
+            fields.join(" ")
+        }
+        None => body.to_string(),
+    }
+    .to_lowercase();
+    let mentions = |signals: &[&str]| signals.iter().any(|signal| text.contains(signal));
+    
+    if mentions(AUTH_SIGNALS) {
+        return Some(Diagnosis::Auth);
+    }
+    let kind = if mentions(RATE_LIMIT_SIGNALS) {
+        ProviderErrorKind::RateLimited
+    } else if mentions(QUOTA_SIGNALS) {
+        ProviderErrorKind::QuotaExhausted
+    } else if mentions(OVERLOAD_SIGNALS) {
+        ProviderErrorKind::Overloaded
+    } else if mentions(CONTEXT_SIGNALS) || status == StatusCode::PAYLOAD_TOO_LARGE {
+        ProviderErrorKind::ContextTooLong
+    } else if mentions(MODEL_SIGNALS) {
+        ProviderErrorKind::ModelNotFound
+    } else if mentions(FILTER_SIGNALS) {
+        ProviderErrorKind::ContentFiltered
+    } else if status == StatusCode::SERVICE_UNAVAILABLE {
+        // Relays answer 503 for a model none of their channels serve, so the body is read first
+        ProviderErrorKind::Overloaded
+    } else if status == StatusCode::TOO_MANY_REQUESTS {
+        ProviderErrorKind::RateLimited
+    } else if mentions(INVALID_REQUEST_SIGNALS) && status.is_client_error() {
+        ProviderErrorKind::InvalidRequest
+    } else {
+        return None;
+    };
+    Some(Diagnosis::Provider(kind))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn kind(status: u16, body: &str) -> Option<ProviderErrorKind> {
+        match diagnose(StatusCode::from_u16(status).unwrap(), body) {
+            Some(Diagnosis::Provider(kind)) => Some(kind),
+            _ => None,
+        }
+    }
+    
+    fn is_auth(status: u16, body: &str) -> bool {
+        matches!(diagnose(StatusCode::from_u16(status).unwrap(), body), Some(Diagnosis::Auth))
+    }
+    
+    #[test]
+    fn openai_errors() {
+        assert!(is_auth(401, r#"{"error":{"message":"Incorrect API key provided: sk-abc***xyz. You can find your API key at https://platform.openai.com/account/api-keys.","type":"invalid_request_error","param":null,"code":"invalid_api_key"}}"#));
+        assert_eq!(
+            kind(429, r#"{"error":{"message":"You exceeded your current quota, please check your plan and billing details.","type":"insufficient_quota","param":null,"code":"insufficient_quota"}}"#),
+            Some(ProviderErrorKind::QuotaExhausted)
+        );
+        assert_eq!(
+            kind(429, r#"{"error":{"message":"Rate limit reached for gpt-4o in organization org-abc on tokens per min (TPM): Limit 30000, Used 29000, Requested 2000.","type":"tokens","param":null,"code":"rate_limit_exceeded"}}"#),
+            Some(ProviderErrorKind::RateLimited)
+        );
+        assert_eq!(
+            kind(400, r#"{"error":{"message":"This model's maximum context length is 8192 tokens. However, your messages resulted in 9000 tokens. Please reduce the length of the messages.","type":"invalid_request_error","param":"messages","code":"context_length_exceeded"}}"#),
+            Some(ProviderErrorKind::ContextTooLong)
+        );
+        assert_eq!(
+            kind(404, r#"{"error":{"message":"The model `gpt-5-turbo` does not exist or you do not have access to it.","type":"invalid_request_error","param":null,"code":"model_not_found"}}"#),
+            Some(ProviderErrorKind::ModelNotFound)
+        );
+        assert_eq!(
+            kind(400, r#"{"error":{"message":"The response was filtered due to the prompt triggering Azure OpenAI's content management policy.","type":null,"param":"prompt","code":"content_filter","status":400}}"#),
+            Some(ProviderErrorKind::ContentFiltered)
+        );
+    }
+    
+    #[test]
+    fn anthropic_errors() {
+        assert!(is_auth(401, r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#));
+        assert_eq!(
+            kind(529, r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#),
+            Some(ProviderErrorKind::Overloaded)
+        );
+        assert_eq!(
+            kind(429, r#"{"type":"error","error":{"type":"rate_limit_error","message":"Number of request tokens has exceeded your per-minute rate limit"}}"#),
+            Some(ProviderErrorKind::RateLimited)
+        );
+        assert_eq!(
+            kind(400, r#"{"type":"error","error":{"type":"invalid_request_error","message":"Your credit balance is too low to access the Anthropic API. Please go to Plans & Billing to upgrade or purchase credits."}}"#),
+            Some(ProviderErrorKind::QuotaExhausted)
+        );
+        assert_eq!(
+            kind(400, r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 215000 tokens > 200000 maximum"}}"#),
+            Some(ProviderErrorKind::ContextTooLong)
+        );
+    }
+    
+    #[test]
+    fn gemini_errors() {
+        assert!(is_auth(400, r#"{"error":{"code":400,"message":"API key not valid. Please pass a valid API key.","status":"INVALID_ARGUMENT"}}"#));
+        assert_eq!(
+            kind(429, r#"{"error":{"code":429,"message":"Resource has been exhausted (e.g. check quota).","status":"RESOURCE_EXHAUSTED"}}"#),
+            Some(ProviderErrorKind::RateLimited)
+        );
+        assert_eq!(
+            kind(404, r#"{"error":{"code":404,"message":"models/gemini-9-pro is not found for API version v1beta, or is not supported for generateContent.","status":"NOT_FOUND"}}"#),
+            Some(ProviderErrorKind::ModelNotFound)
+        );
+        assert_eq!(
+            kind(400, r#"{"error":{"code":400,"message":"Invalid JSON payload received. Unknown name \"foo\": Cannot find field.","status":"INVALID_ARGUMENT"}}"#),
+            Some(ProviderErrorKind::InvalidRequest)
+        );
+    }
+    
+    #[test]
+    fn relay_errors() {
+        assert!(is_auth(401, r#"{"code":401,"msg":"无效的令牌"}"#));
+        assert_eq!(
+            kind(403, r#"{"error":{"message":"用户额度不足, 剩余额度: $0.012","type":"new_api_error","param":"","code":"insufficient_user_quota"}}"#),
+            Some(ProviderErrorKind::QuotaExhausted)
+        );
+        assert_eq!(
+            kind(503, r#"{"error":{"message":"当前分组上游负载已饱和，请稍后再试","type":"new_api_error","param":"","code":null}}"#),
+            Some(ProviderErrorKind::Overloaded)
+        );
+        assert_eq!(
+            kind(503, r#"{"error":{"message":"当前分组 default 下对于模型 gpt-x 无可用渠道","type":"new_api_error","param":"","code":null}}"#),
+            Some(ProviderErrorKind::ModelNotFound)
+        );
+    }
+    
+    #[test]
+    fn unrecognised_errors_are_left_to_the_status() {
+        assert!(diagnose(StatusCode::INTERNAL_SERVER_ERROR, r#"{"error":{"message":"Internal error"}}"#).is_none());
+        assert!(diagnose(StatusCode::BAD_GATEWAY, "<html><body>Bad Gateway</body></html>").is_none());
+    }
+}
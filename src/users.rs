@@ -0,0 +1,52 @@
+use crate::client::APIResponse;
+use crate::config::{now_secs, Config};
+use crate::error::{CCSwitchError, Result};
+use serde_json::{json, Value};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+// Each proxy user's state lives in its own directory so nothing is read across users
+fn user_dir(user: &str) -> Result<PathBuf> {
+    if user.is_empty() || user.starts_with('.') || user.contains(['/', '\\']) {
+        return Err(CCSwitchError::Config(format!("Invalid user name '{}'", user)));
+    }
+    Ok(Config::data_dir()?.join("users").join(user))
+}
+
+pub fn record(user: &str, prompt: &str, result: &Result<APIResponse>) -> Result<()> {
+    let mut entry = json!({ "at": now_secs(), "prompt": prompt, "success": result.is_ok() });
+    match result {
+        Ok(response) => {
+            entry["channel"] = json!(response.channel_used);
+            entry["model"] = json!(response.model);
+            entry["reply"] = json!(response.content);
+            entry["usage"] = json!(response.usage);
+            if let Some(seed) = response.selection_seed {
+                entry["selection_seed"] = json!(seed);
+            }
+            if !response.labels.is_empty() {
+                entry["labels"] = json!(response.labels);
+            }
+        }
+        Err(e) => entry["error"] = json!(e.to_string()),
+    }
+    
+    let dir = user_dir(user)?;
+    fs::create_dir_all(&dir)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(dir.join("history.jsonl"))?;
+    writeln!(file, "{}", entry)?;
+    Ok(())
+}
+
+// The user's most recent requests, newest last
+pub fn history(user: &str, limit: usize) -> Result<Vec<Value>> {
+    let path = user_dir(user)?.join("history.jsonl");
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let content = fs::read_to_string(path)?;
+    let entries: Vec<Value> = content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect();
+    Ok(entries[entries.len().saturating_sub(limit)..].to_vec())
+}
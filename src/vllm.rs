@@ -0,0 +1,88 @@
+use crate::config::VllmConfig;
+use serde_json::Value;
+use std::time::Duration;
+
+// Routing waits on `/metrics`, so a slow or hung server is given up on quickly
+pub const METRICS_TIMEOUT: Duration = Duration::from_secs(2);
+// How long one reading stands in for the server's load
+pub const LOAD_CACHE: Duration = Duration::from_secs(5);
+
+// Provider-specific sampling fields vLLM accepts on top of OpenAI's
+pub const SAMPLING_PARAMS: &[&str] = &["top_k", "min_p", "repetition_penalty"];
+
+// The server root, from any of its OpenAI-style URLs ("http://gpu-box:8000/v1/chat/completions")
+fn base_url(url: &str) -> &str {
+    match url.find("/v1/") {
+        Some(index) => &url[..index],
+        None => url.trim_end_matches('/'),
+    }
+}
+
+pub fn models_url(url: &str) -> String {
+    format!("{}/v1/models", base_url(url))
+}
+
+pub fn metrics_url(url: &str) -> String {
+    format!("{}/metrics", base_url(url))
+}
+
+pub fn model_ids(models: &Value) -> Vec<String> {
+    models.get("data")
+        .and_then(|data| data.as_array())
+        .map(|data| {
+            data.iter()
+                .filter_map(|model| model.get("id").and_then(|id| id.as_str()))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Load figures from the Prometheus text at `/metrics`, over all models the server runs
+#[derive(Debug, Default)]
+pub struct GpuLoad {
+    // Share of the KV cache in use, 0.0-1.0; the fullest model counts
+    pub cache_usage: Option<f64>,
+    pub waiting_requests: Option<f64>,
+}
+
+impl GpuLoad {
+    pub fn parse(metrics: &str) -> Self {
+        let mut load = GpuLoad::default();
+        for line in metrics.lines().filter(|line| !line.starts_with('#')) {
+            let Some((name, value)) = line.rsplit_once(' ') else {
+                continue;
+            };
+            let Ok(value) = value.parse::<f64>() else {
+                continue;
+            };
+            let name = name.split('{').next().unwrap_or(name);
+            match name {
+                // Renamed from the first to the second in vLLM 0.10
+                "vllm:gpu_cache_usage_perc" | "vllm:kv_cache_usage_perc" => {
+                    load.cache_usage = Some(load.cache_usage.map_or(value, |usage| usage.max(value)));
+                }
+                "vllm:num_requests_waiting" => {
+                    load.waiting_requests = Some(load.waiting_requests.unwrap_or(0.0) + value);
+                }
+                _ => {}
+            }
+        }
+        load
+    }
+    
+    // Why the server should not take more requests now, if it should not
+    pub fn saturated(&self, limits: &VllmConfig) -> Option<String> {
+        if let (Some(usage), Some(max)) = (self.cache_usage, limits.max_gpu_cache_usage) {
+            if usage > max {
+                return Some(format!("KV cache {:.0}% full (limit {:.0}%)", usage * 100.0, max * 100.0));
+            }
+        }
+        if let (Some(waiting), Some(max)) = (self.waiting_requests, limits.max_waiting_requests) {
+            if waiting > max as f64 {
+                return Some(format!("{} requests waiting (limit {})", waiting, max));
+            }
+        }
+        None
+    }
+}